@@ -0,0 +1,241 @@
+//! Account operations: balance arithmetic shared by CheckTx and execution, and
+//! execution of deposits, withdrawals and transfers
+//!
+//! Arithmetic is overflow-checked and pure: new balances are computed first and
+//! only written once the whole transaction is known to succeed.
+
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{Receipt, ReceiptKind, TxDeposit, TxTransfer, TxWithdraw, MAX_DESTINATION_LENGTH};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+
+/// Balance left after paying `amount`
+pub fn debit(balance: u64, amount: u64) -> Result<u64, TxRejection> {
+    balance.checked_sub(amount).ok_or_else(|| {
+        TxRejection::new(
+            codes::INSUFFICIENT_FUNDS,
+            format!("Insufficient balance: have {}, need {}", balance, amount),
+        )
+    })
+}
+
+/// Balance after receiving `amount`
+pub fn credit(balance: u64, amount: u64) -> Result<u64, TxRejection> {
+    balance
+        .checked_add(amount)
+        .ok_or_else(|| TxRejection::new(codes::BALANCE_OVERFLOW, "Balance overflow"))
+}
+
+/// Require a recipient other than the zero address and the sender
+pub fn check_recipient(sender: &[u8; 32], recipient: &[u8; 32]) -> Result<(), TxRejection> {
+    if recipient == &[0u8; 32] {
+        return Err(TxRejection::new(codes::INVALID_RECIPIENT, "Invalid recipient: cannot be zero"));
+    }
+    if recipient == sender {
+        return Err(TxRejection::new(codes::INVALID_RECIPIENT, "Invalid recipient: cannot be the sender"));
+    }
+    Ok(())
+}
+
+/// Require a non-empty withdrawal destination of bounded length
+pub fn check_destination(destination: &str) -> Result<(), TxRejection> {
+    if destination.is_empty() || destination.len() > MAX_DESTINATION_LENGTH {
+        return Err(TxRejection::new(
+            codes::INVALID_DESTINATION,
+            format!("Invalid destination: must be 1 to {} bytes", MAX_DESTINATION_LENGTH),
+        ));
+    }
+    Ok(())
+}
+
+/// Require the signer of a deposit to be the registered minter
+pub fn check_minter(storage: &Storage, public_key: &[u8; 32]) -> Result<(), TxRejection> {
+    match storage.get_minter_public_key().map_err(TxRejection::internal)? {
+        Some(minter) if &minter == public_key => Ok(()),
+        Some(_) => Err(TxRejection::new(codes::UNAUTHORIZED, "Deposits must be signed by the minter")),
+        None => Err(TxRejection::new(codes::UNAUTHORIZED, "Deposits are disabled: no minter registered")),
+    }
+}
+
+/// Credit a deposit to its recipient
+pub fn execute_deposit(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    public_key: &[u8; 32],
+    tx: &TxDeposit,
+    height: u64,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    check_minter(storage, public_key)?;
+    let balance = storage.get_pending_balance(&tx.recipient, batch).map_err(TxRejection::internal)?;
+    let balance = credit(balance, tx.amount)?;
+
+    storage.set_balance(&tx.recipient, balance, batch).map_err(TxRejection::internal)?;
+    let receipt = Receipt {
+        kind: ReceiptKind::Deposit { recipient: tx.recipient, amount: tx.amount },
+        height,
+        tx_hash,
+    };
+    storage.store_receipt(&tx_hash, &receipt, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "deposit".to_string(),
+        attributes: vec![
+            ("recipient".to_string(), hex::encode(tx.recipient)).into(),
+            ("amount".to_string(), tx.amount.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(tx_hash)).into(),
+        ],
+    })
+}
+
+/// Burn a withdrawal from the sender's balance
+pub fn execute_withdraw(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    tx: &TxWithdraw,
+    height: u64,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = debit(balance, tx.amount)?;
+
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+    let receipt = Receipt {
+        kind: ReceiptKind::Withdraw {
+            wallet: tx.wallet,
+            amount: tx.amount,
+            destination: tx.destination.clone(),
+        },
+        height,
+        tx_hash,
+    };
+    storage.store_receipt(&tx_hash, &receipt, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "withdraw".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(tx.wallet)).into(),
+            ("amount".to_string(), tx.amount.to_string()).into(),
+            ("destination".to_string(), tx.destination.clone()).into(),
+            ("tx_hash".to_string(), hex::encode(tx_hash)).into(),
+        ],
+    })
+}
+
+/// Move funds from the sender to the recipient
+pub fn execute_transfer(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    tx: &TxTransfer,
+    height: u64,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let sender_balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let sender_balance = debit(sender_balance, tx.amount)?;
+    let recipient_balance = storage.get_pending_balance(&tx.recipient, batch).map_err(TxRejection::internal)?;
+    let recipient_balance = credit(recipient_balance, tx.amount)?;
+
+    storage.set_balance(&tx.wallet, sender_balance, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.recipient, recipient_balance, batch).map_err(TxRejection::internal)?;
+    let receipt = Receipt {
+        kind: ReceiptKind::Transfer { wallet: tx.wallet, recipient: tx.recipient, amount: tx.amount },
+        height,
+        tx_hash,
+    };
+    storage.store_receipt(&tx_hash, &receipt, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "transfer".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(tx.wallet)).into(),
+            ("recipient".to_string(), hex::encode(tx.recipient)).into(),
+            ("amount".to_string(), tx.amount.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(tx_hash)).into(),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_arithmetic() {
+        assert_eq!(debit(100, 40), Ok(60));
+        assert_eq!(debit(100, 100), Ok(0));
+        assert_eq!(debit(100, 101).unwrap_err().code, codes::INSUFFICIENT_FUNDS);
+
+        assert_eq!(credit(60, 40), Ok(100));
+        assert_eq!(credit(u64::MAX, 1).unwrap_err().code, codes::BALANCE_OVERFLOW);
+    }
+
+    fn storage_with_balances(balances: &[([u8; 32], u64)]) -> anyhow::Result<(tempfile::TempDir, Storage)> {
+        let temp_dir = tempfile::tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        let mut batch = storage.batch();
+        storage.set_minter_public_key(&[9u8; 32], &mut batch)?;
+        for (wallet, balance) in balances {
+            storage.set_balance(wallet, *balance, &mut batch)?;
+        }
+        storage.apply_batch(batch)?;
+        Ok((temp_dir, storage))
+    }
+
+    #[test]
+    fn test_transfer_moves_funds() -> anyhow::Result<()> {
+        let (alice, bob) = ([1u8; 32], [2u8; 32]);
+        let (_dir, storage) = storage_with_balances(&[(alice, 100)])?;
+        let mut batch = storage.batch();
+
+        let tx = TxTransfer { wallet: alice, recipient: bob, amount: 70, nonce: 0 };
+        execute_transfer(&storage, &mut batch, &tx, 1, [7u8; 32])?;
+        assert_eq!(storage.get_pending_balance(&alice, &batch)?, 30);
+        assert_eq!(storage.get_pending_balance(&bob, &batch)?, 70);
+
+        // The second transfer sees the first one's pending debit
+        let rejection = execute_transfer(&storage, &mut batch, &tx, 1, [8u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INSUFFICIENT_FUNDS);
+
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_balance(&alice)?, 30);
+        assert!(storage.get_receipt(&[7u8; 32])?.is_some());
+        assert!(storage.get_receipt(&[8u8; 32])?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deposit_requires_minter() -> anyhow::Result<()> {
+        let (_dir, storage) = storage_with_balances(&[])?;
+        let mut batch = storage.batch();
+        let tx = TxDeposit { wallet: [5u8; 32], recipient: [1u8; 32], amount: 50, nonce: 0 };
+
+        let rejection = execute_deposit(&storage, &mut batch, &[8u8; 32], &tx, 1, [7u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::UNAUTHORIZED);
+
+        execute_deposit(&storage, &mut batch, &[9u8; 32], &tx, 1, [7u8; 32])?;
+        assert_eq!(storage.get_pending_balance(&[1u8; 32], &batch)?, 50);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_withdraw_and_validation() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_dir, storage) = storage_with_balances(&[(wallet, 100)])?;
+        let mut batch = storage.batch();
+
+        let tx = TxWithdraw { wallet, amount: 100, destination: "bank:123".to_string(), nonce: 0 };
+        execute_withdraw(&storage, &mut batch, &tx, 1, [7u8; 32])?;
+        assert_eq!(storage.get_pending_balance(&wallet, &batch)?, 0);
+
+        assert_eq!(check_destination("").unwrap_err().code, codes::INVALID_DESTINATION);
+        assert_eq!(check_destination(&"x".repeat(MAX_DESTINATION_LENGTH + 1)).unwrap_err().code, codes::INVALID_DESTINATION);
+        assert_eq!(check_recipient(&wallet, &wallet).unwrap_err().code, codes::INVALID_RECIPIENT);
+        assert_eq!(check_recipient(&wallet, &[0u8; 32]).unwrap_err().code, codes::INVALID_RECIPIENT);
+        check_recipient(&wallet, &[2u8; 32])?;
+
+        Ok(())
+    }
+}
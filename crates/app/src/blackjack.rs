@@ -0,0 +1,739 @@
+//! Blackjack: six decks, the dealer stands on all 17s, blackjack pays 3:2
+//!
+//! A session is dealt by one transaction and played by as many moves as it takes,
+//! across blocks. The shoe is shuffled from a VRF output proven at the deal. While
+//! the session is in play only the commitment to that output is stored, and every
+//! move proves the deal's message again to read the next cards. The dealer's hole
+//! card is the fourth card of the shoe; it is shown when the session ends, along
+//! with the proof and output that let anyone replay the shoe.
+//!
+//! The dealer peeks, so a session where either side has blackjack ends at the
+//! deal. Any two cards can be doubled, and two cards of the same rank split, up to
+//! [`MAX_HANDS`] hands; split aces take one card each.
+//!
+//! The stakes, doubles and splits included, are held by a game
+//! [`session`](crate::session); a session left alone until it times out stands
+//! on every hand and the dealer plays out.
+
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    BetKind, BetRecord, BlackjackHand, BlackjackMove, BlackjackSession, GameSession, SignedTx, Tx,
+    TxBlackjackAction, TxBlackjackDeal, TxKind,
+};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::{VrfEngine, VrfRng};
+use crate::{accounts, params, session, BlockContext};
+
+/// Decks in the shoe
+pub const DECKS: usize = 6;
+
+/// Cards in the shoe
+pub const SHOE_SIZE: usize = 52 * DECKS;
+
+/// Most hands a session can be split into
+pub const MAX_HANDS: usize = 4;
+
+/// The dealer draws below this total
+const DEALER_STANDS_ON: u32 = 17;
+
+/// Position in the shoe of the dealer's hole card
+const HOLE_CARD: usize = 3;
+
+/// Session state while the player has hands to play
+const PLAYING: &str = "playing";
+
+/// Blackjack sessions
+pub struct Blackjack;
+
+impl Game for Blackjack {
+    fn id(&self) -> &'static str {
+        "blackjack"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::BlackjackDeal, TxKind::BlackjackAction]
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        match &signed.tx {
+            Tx::BlackjackDeal(deal) => game::stake(storage, deal.amount),
+            // Doubling and splitting stake the bet of the hand in play again
+            Tx::BlackjackAction(action) => action_stake(storage, action),
+            tx => Ok(tx.amount()),
+        }
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::BlackjackDeal(tx) => execute_deal(storage, batch, vrf_engine, block, tx, tx_hash),
+            Tx::BlackjackAction(tx) => execute_action(storage, batch, vrf_engine, block, tx),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+
+    fn session_actions(&self, state: &str) -> &'static [TxKind] {
+        match state {
+            PLAYING => &[TxKind::BlackjackAction],
+            _ => &[],
+        }
+    }
+
+    fn time_out(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        escrow: GameSession,
+    ) -> anyhow::Result<Vec<Event>> {
+        let mut session = session_in_play(storage, batch, &escrow.wallet)?;
+        let vrf = prove_shoe(vrf_engine, &session)?;
+        for hand in &mut session.hands {
+            hand.stood = true;
+        }
+        session.active = session.hands.len() as u8;
+        let balance = storage.get_pending_balance(&escrow.wallet, batch)?;
+        Ok(vec![finish(storage, batch, block, (session, escrow), vrf, balance, "timeout")?])
+    }
+}
+
+/// Value of a card, counting an ace as 1
+pub fn card_value(card: u8) -> u32 {
+    match card % 13 {
+        0 => 1,
+        rank @ 1..=9 => rank as u32 + 1,
+        _ => 10,
+    }
+}
+
+/// Best total of a hand, and whether it counts an ace as 11
+pub fn hand_value(cards: &[u8]) -> (u32, bool) {
+    let total: u32 = cards.iter().map(|&card| card_value(card)).sum();
+    if total <= 11 && cards.iter().any(|&card| card % 13 == 0) {
+        (total + 10, true)
+    } else {
+        (total, false)
+    }
+}
+
+/// Whether the cards are a blackjack: 21 with the first two
+pub fn is_blackjack(cards: &[u8]) -> bool {
+    cards.len() == 2 && hand_value(cards).0 == 21
+}
+
+/// Shoe shuffled from a VRF output
+pub fn shoe(vrf_output: &[u8]) -> Vec<u8> {
+    let mut cards: Vec<u8> = (0..SHOE_SIZE).map(|i| (i % 52) as u8).collect();
+    VrfRng::new(vrf_output).shuffle(&mut cards);
+    cards
+}
+
+/// Payout of a hand against the dealer's final cards
+///
+/// Only the hand of an unsplit session can be a blackjack.
+pub fn hand_payout(hand: &BlackjackHand, dealer: &[u8], unsplit: bool) -> Result<u64, TxRejection> {
+    let bet = hand.bet as u128;
+    let (player, _) = hand_value(&hand.cards);
+    let (house, _) = hand_value(dealer);
+    let payout = if unsplit && is_blackjack(&hand.cards) {
+        if is_blackjack(dealer) { bet } else { bet + bet * 3 / 2 }
+    } else if player > 21 || is_blackjack(dealer) {
+        0
+    } else if house > 21 || player > house {
+        bet * 2
+    } else if player == house {
+        bet
+    } else {
+        0
+    };
+    u64::try_from(payout).map_err(|_| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+/// Deal a session: debit the stake, shuffle the shoe with the VRF and deal two
+/// cards each
+pub fn execute_deal(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxBlackjackDeal,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+
+    let escrow = session::open(storage, batch, block, &Blackjack, &tx.wallet, tx.amount, PLAYING)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    // The most a stake can return: every hand split off, doubled and won
+    tx.amount
+        .checked_mul(4 * MAX_HANDS as u64)
+        .ok_or_else(|| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))?;
+
+    let vrf_message = VrfEngine::compute_blackjack_message(
+        &block.chain_id,
+        block.height,
+        &block.block_random,
+        &tx_hash,
+        &tx.wallet,
+        tx.nonce,
+    );
+    let (vrf_output, vrf_proof) = vrf_engine.prove(&vrf_message).map_err(TxRejection::internal)?;
+    let shoe = shoe(&vrf_output);
+    let session = BlackjackSession {
+        wallet: tx.wallet,
+        height: block.height,
+        tx_hash,
+        nonce: tx.nonce,
+        vrf_message,
+        commitment: *blake3::hash(&vrf_output).as_bytes(),
+        hands: vec![BlackjackHand { cards: vec![shoe[0], shoe[2]], bet: tx.amount, doubled: false, stood: false }],
+        active: 0,
+        dealer: vec![shoe[1]],
+        next_card: HOLE_CARD as u16 + 1,
+        finished: false,
+        vrf_proof: Vec::new(),
+        vrf_output: Vec::new(),
+    };
+
+    // The dealer peeks: a blackjack on either side settles at once
+    if is_blackjack(&session.hands[0].cards) || is_blackjack(&[shoe[1], shoe[HOLE_CARD]]) {
+        return finish(storage, batch, block, (session, escrow), (vrf_output, vrf_proof), balance, "deal");
+    }
+
+    storage.store_game_session(&escrow, batch).map_err(TxRejection::internal)?;
+    storage.store_blackjack_session(&session, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+    Ok(session_event(&session, &escrow, "deal", None))
+}
+
+/// Play a move on the hand in play; the session ends once every hand stands
+pub fn execute_action(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxBlackjackAction,
+) -> Result<Event, TxRejection> {
+    let mut escrow = session::in_play(storage, batch, &Blackjack, &tx.wallet, TxKind::BlackjackAction)?;
+    let mut session = session_in_play(storage, batch, &tx.wallet)?;
+    let (vrf_output, vrf_proof) = prove_shoe(vrf_engine, &session)?;
+    let shoe = shoe(&vrf_output);
+
+    let mut balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let active = session.active as usize;
+    let hand = &session.hands[active];
+    match tx.action {
+        BlackjackMove::Hit => {
+            let card = draw(&mut session, &shoe);
+            session.hands[active].cards.push(card);
+        }
+        BlackjackMove::Stand => session.hands[active].stood = true,
+        BlackjackMove::Double => {
+            if hand.cards.len() != 2 {
+                return Err(TxRejection::new(codes::INVALID_MOVE, "Only two cards can be doubled"));
+            }
+            balance = accounts::debit(balance, hand.bet)?;
+            escrow.stake = accounts::credit(escrow.stake, hand.bet)?;
+            let card = draw(&mut session, &shoe);
+            let hand = &mut session.hands[active];
+            hand.bet *= 2;
+            hand.doubled = true;
+            hand.cards.push(card);
+            hand.stood = true;
+        }
+        BlackjackMove::Split => {
+            if hand.cards.len() != 2 || hand.cards[0] % 13 != hand.cards[1] % 13 {
+                return Err(TxRejection::new(codes::INVALID_MOVE, "Only two cards of the same rank can be split"));
+            }
+            if session.hands.len() >= MAX_HANDS {
+                return Err(TxRejection::new(
+                    codes::INVALID_MOVE,
+                    format!("A session can be split into at most {} hands", MAX_HANDS),
+                ));
+            }
+            balance = accounts::debit(balance, hand.bet)?;
+            escrow.stake = accounts::credit(escrow.stake, hand.bet)?;
+            let split_aces = hand.cards[0] % 13 == 0;
+            let mut split = BlackjackHand { cards: Vec::new(), bet: hand.bet, doubled: false, stood: split_aces };
+            split.cards.extend(session.hands[active].cards.pop());
+            split.cards.push(draw(&mut session, &shoe));
+            let card = draw(&mut session, &shoe);
+            let hand = &mut session.hands[active];
+            hand.cards.push(card);
+            hand.stood = split_aces;
+            session.hands.insert(active + 1, split);
+        }
+    }
+
+    // A hand at 21 or bust takes no more cards
+    for hand in &mut session.hands {
+        hand.stood |= hand_value(&hand.cards).0 >= 21;
+    }
+    while session.hands.get(session.active as usize).is_some_and(|hand| hand.stood) {
+        session.active += 1;
+    }
+
+    let action = move_name(tx.action);
+    if session.active as usize == session.hands.len() {
+        return finish(storage, batch, block, (session, escrow), (vrf_output, vrf_proof), balance, action);
+    }
+
+    session::advance(storage, block, &mut escrow, PLAYING)?;
+    storage.store_game_session(&escrow, batch).map_err(TxRejection::internal)?;
+    storage.store_blackjack_session(&session, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+    Ok(session_event(&session, &escrow, action, None))
+}
+
+/// Stake a move adds to the session: the bet of the hand in play again for a
+/// double or a split, nothing for any other move
+pub fn action_stake(storage: &Storage, tx: &TxBlackjackAction) -> Result<u64, TxRejection> {
+    if !matches!(tx.action, BlackjackMove::Double | BlackjackMove::Split) {
+        return Ok(0);
+    }
+    let session = storage.get_blackjack_session(&tx.wallet).map_err(TxRejection::internal)?;
+    Ok(session
+        .filter(|session| !session.finished)
+        .and_then(|session| session.hands.get(session.active as usize).map(|hand| hand.bet))
+        .unwrap_or(0))
+}
+
+/// Name of a move, as used in events
+pub fn move_name(action: BlackjackMove) -> &'static str {
+    match action {
+        BlackjackMove::Hit => "hit",
+        BlackjackMove::Stand => "stand",
+        BlackjackMove::Double => "double",
+        BlackjackMove::Split => "split",
+    }
+}
+
+/// The wallet's hand, which is in play while its game session is
+fn session_in_play(
+    storage: &Storage,
+    batch: &StorageBatch,
+    wallet: &[u8; 32],
+) -> Result<BlackjackSession, TxRejection> {
+    storage.get_pending_blackjack_session(wallet, batch)
+        .map_err(TxRejection::internal)?
+        .filter(|session| !session.finished)
+        .ok_or_else(|| TxRejection::internal(format!("Blackjack session of {} has no hand in play", hex::encode(wallet))))
+}
+
+/// Prove the deal's message again; the output must be the one committed to
+fn prove_shoe(vrf_engine: &VrfEngine, session: &BlackjackSession) -> Result<(Vec<u8>, Vec<u8>), TxRejection> {
+    let (vrf_output, vrf_proof) = vrf_engine.prove(&session.vrf_message).map_err(TxRejection::internal)?;
+    if *blake3::hash(&vrf_output).as_bytes() != session.commitment {
+        return Err(TxRejection::internal(format!(
+            "VRF output of the blackjack session of {} does not match its commitment",
+            hex::encode(session.wallet)
+        )));
+    }
+    Ok((vrf_output, vrf_proof))
+}
+
+/// Next card of the shoe
+fn draw(session: &mut BlackjackSession, shoe: &[u8]) -> u8 {
+    let card = shoe[session.next_card as usize];
+    session.next_card += 1;
+    card
+}
+
+/// End a session: show the hole card, play the dealer's hand, pay the player and
+/// publish the shoe
+fn finish(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    block: &BlockContext,
+    (mut session, mut escrow): (BlackjackSession, GameSession),
+    (vrf_output, vrf_proof): (Vec<u8>, Vec<u8>),
+    balance: u64,
+    action: &str,
+) -> Result<Event, TxRejection> {
+    let shoe = shoe(&vrf_output);
+    session.dealer.push(shoe[HOLE_CARD]);
+    let unsplit = session.hands.len() == 1;
+    let natural = unsplit && is_blackjack(&session.hands[0].cards);
+    let live = session.hands.iter().any(|hand| hand_value(&hand.cards).0 <= 21);
+    if live && !natural && !is_blackjack(&session.dealer) {
+        while hand_value(&session.dealer).0 < DEALER_STANDS_ON {
+            let card = draw(&mut session, &shoe);
+            session.dealer.push(card);
+        }
+    }
+
+    let mut staked = 0u64;
+    let mut payout = 0u64;
+    for hand in &session.hands {
+        staked = staked.saturating_add(hand.bet);
+        payout = accounts::credit(payout, hand_payout(hand, &session.dealer, unsplit)?)?;
+    }
+    let balance = accounts::credit(balance, payout)?;
+
+    session.finished = true;
+    session.vrf_proof = vrf_proof;
+    session.vrf_output = vrf_output;
+    let record = BetRecord {
+        wallet: session.wallet,
+        amount: staked,
+        kind: BetKind::Blackjack { hands: session.hands.clone(), dealer: session.dealer.clone() },
+        nonce: session.nonce,
+        vrf_message: session.vrf_message.clone(),
+        vrf_proof: session.vrf_proof.clone(),
+        vrf_output: session.vrf_output.clone(),
+        won: payout > staked,
+        payout,
+        height: block.height,
+        tx_hash: session.tx_hash,
+    };
+
+    session::settle(block, &mut escrow, payout);
+
+    storage.store_bet(&record.tx_hash, &record, batch).map_err(TxRejection::internal)?;
+    storage.store_game_session(&escrow, batch).map_err(TxRejection::internal)?;
+    storage.store_blackjack_session(&session, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&session.wallet, balance, batch).map_err(TxRejection::internal)?;
+    Ok(session_event(&session, &escrow, action, Some(&record)))
+}
+
+/// Event of a move, with the outcome when it ended the session
+fn session_event(session: &BlackjackSession, escrow: &GameSession, action: &str, record: Option<&BetRecord>) -> Event {
+    let cards = |cards: &[u8]| cards.iter().map(u8::to_string).collect::<Vec<_>>().join(",");
+    let hands: Vec<String> = session.hands.iter().map(|hand| cards(&hand.cards)).collect();
+    let mut attributes = vec![
+        ("wallet".to_string(), hex::encode(session.wallet)).into(),
+        ("session".to_string(), escrow.id.to_string()).into(),
+        ("action".to_string(), action.to_string()).into(),
+        ("hands".to_string(), hands.join(";")).into(),
+        ("dealer".to_string(), cards(&session.dealer)).into(),
+        ("finished".to_string(), session.finished.to_string()).into(),
+        ("tx_hash".to_string(), hex::encode(session.tx_hash)).into(),
+    ];
+    if !session.finished {
+        attributes.push(("expires_at".to_string(), escrow.expires_at.to_string()).into());
+    }
+    if let Some(record) = record {
+        attributes.extend([
+            ("amount".to_string(), record.amount.to_string()).into(),
+            ("won".to_string(), record.won.to_string()).into(),
+            ("payout".to_string(), record.payout.to_string()).into(),
+            ("vrf_proof".to_string(), hex::encode(&record.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&record.vrf_output)).into(),
+        ]);
+    }
+    Event { kind: "blackjack".to_string(), attributes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+
+    fn hand(cards: &[u8], bet: u64) -> BlackjackHand {
+        BlackjackHand { cards: cards.to_vec(), bet, doubled: false, stood: true }
+    }
+
+    fn live(cards: &[u8], bet: u64) -> BlackjackHand {
+        BlackjackHand { stood: false, ..hand(cards, bet) }
+    }
+
+    /// Seat a wallet holding 1_000 at a session with these hands, the hand at
+    /// `active` in play; the next cards drawn are the shoe's from position 4
+    fn seat(
+        storage: &Storage,
+        vrf_engine: &VrfEngine,
+        wallet: [u8; 32],
+        hands: Vec<BlackjackHand>,
+        active: u8,
+    ) -> anyhow::Result<Vec<u8>> {
+        let vrf_message = wallet.to_vec();
+        let (vrf_output, _) = vrf_engine.prove(&vrf_message)?;
+        let cards = shoe(&vrf_output);
+        let stake = hands.iter().map(|hand| hand.bet).sum();
+        let mut batch = storage.batch();
+        let escrow = session::open(storage, &batch, &block(10), &Blackjack, &wallet, stake, PLAYING)?;
+        storage.store_game_session(&escrow, &mut batch)?;
+        storage.store_blackjack_session(&BlackjackSession {
+            wallet,
+            height: 10,
+            tx_hash: wallet,
+            nonce: 0,
+            vrf_message,
+            commitment: *blake3::hash(&vrf_output).as_bytes(),
+            hands,
+            active,
+            dealer: vec![cards[1]],
+            next_card: HOLE_CARD as u16 + 1,
+            finished: false,
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+        }, &mut batch)?;
+        storage.set_balance(&wallet, 1_000 - stake, &mut batch)?;
+        storage.apply_batch(batch)?;
+        Ok(cards)
+    }
+
+    /// Play a move on the wallet's session in a new block
+    fn play(storage: &Storage, vrf_engine: &VrfEngine, wallet: [u8; 32], action: BlackjackMove) -> Result<Event, TxRejection> {
+        let mut batch = storage.batch();
+        let tx = TxBlackjackAction { wallet, action, nonce: 1 };
+        let event = execute_action(storage, &mut batch, vrf_engine, &block(11), &tx)?;
+        storage.apply_batch(batch).map_err(TxRejection::internal)?;
+        Ok(event)
+    }
+
+    #[test]
+    fn test_hand_values_and_payouts() -> anyhow::Result<()> {
+        // Ace, king, five, six (by rank)
+        let (ace, king, five, six) = (0u8, 12u8, 4u8, 5u8);
+        assert_eq!(hand_value(&[ace, six]), (17, true));
+        assert_eq!(hand_value(&[ace, six, king]), (17, false));
+        assert_eq!(hand_value(&[ace, ace + 13, king]), (12, false));
+        assert!(is_blackjack(&[king, ace + 26]));
+        assert!(!is_blackjack(&[five, six, king]));
+
+        // Blackjack pays 3:2, rounded down, unless the hand was split
+        assert_eq!(hand_payout(&hand(&[ace, king], 101), &[king, six, five], true)?, 252);
+        assert_eq!(hand_payout(&hand(&[ace, king], 100), &[king, six, five], false)?, 100);
+        assert_eq!(hand_payout(&hand(&[ace, king], 100), &[king, ace], true)?, 100);
+        assert_eq!(hand_payout(&hand(&[king, king], 100), &[king, ace], true)?, 0);
+
+        // Bust loses even when the dealer busts; otherwise the higher total wins
+        assert_eq!(hand_payout(&hand(&[king, six, king], 100), &[king, six, king], true)?, 0);
+        assert_eq!(hand_payout(&hand(&[king, six], 100), &[king, six, king], true)?, 200);
+        assert_eq!(hand_payout(&hand(&[king, six, ace], 100), &[king, ace + 13, six], true)?, 100);
+        assert_eq!(hand_payout(&hand(&[king, six], 100), &[king, ace + 13, six], true)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shoe_holds_every_deck() {
+        let mut cards = shoe(&[3u8; 64]);
+        assert_ne!(cards, shoe(&[4u8; 64]));
+        cards.sort_unstable();
+        let decks: Vec<u8> = (0..52u8).flat_map(|card| [card; DECKS]).collect();
+        assert_eq!(cards, decks);
+    }
+
+    #[test]
+    fn test_session_plays_across_moves() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 10_000)?;
+        let stand = TxBlackjackAction { wallet, action: BlackjackMove::Stand, nonce: 1 };
+
+        let mut batch = storage.batch();
+        let rejection = execute_action(&storage, &mut batch, &vrf_engine, &block(10), &stand).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_BET);
+
+        // Deal until a session stays in play, as blackjacks settle at the deal
+        let mut expected = 10_000;
+        let mut tx_hash = [0u8; 32];
+        loop {
+            let tx = TxBlackjackDeal { wallet, amount: 100, nonce: 0 };
+            tx_hash[0] += 1;
+            execute_deal(&storage, &mut batch, &vrf_engine, &block(10), &tx, tx_hash)?;
+            storage.apply_batch(batch)?;
+            batch = storage.batch();
+            expected -= 100;
+            match storage.get_bet(&tx_hash)? {
+                Some(record) => expected += record.payout,
+                None => break,
+            }
+        }
+        assert_eq!(storage.get_balance(&wallet)?, expected);
+
+        // The session continues in a later block, and holds no hidden card
+        let session = storage.get_blackjack_session(&wallet)?.unwrap();
+        assert_eq!(session.dealer.len(), 1);
+        assert!(session.vrf_output.is_empty());
+        let tx = TxBlackjackDeal { wallet, amount: 100, nonce: 1 };
+        let rejection = execute_deal(&storage, &mut batch, &vrf_engine, &block(11), &tx, [99u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_MOVE);
+        execute_action(&storage, &mut batch, &vrf_engine, &block(11), &stand)?;
+        storage.apply_batch(batch)?;
+
+        // The published shoe replays every card of the session
+        let session = storage.get_blackjack_session(&wallet)?.unwrap();
+        assert!(session.finished);
+        assert!(VrfEngine::verify(&vrf_engine.public_key(), &session.vrf_message, &session.vrf_proof, &session.vrf_output)?);
+        let cards = shoe(&session.vrf_output);
+        assert_eq!(session.hands[0].cards, vec![cards[0], cards[2]]);
+        assert_eq!(session.dealer[..2], [cards[1], cards[3]]);
+        assert_eq!(session.dealer[2..], cards[4..session.next_card as usize]);
+        assert!(hand_value(&session.dealer).0 >= DEALER_STANDS_ON);
+
+        let record = storage.get_bet(&tx_hash)?.unwrap();
+        assert_eq!(record.payout, hand_payout(&session.hands[0], &session.dealer, true)?);
+        assert_eq!(storage.get_balance(&wallet)?, expected + record.payout);
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_stands_when_it_times_out() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 10_000)?;
+        let mut batch = storage.batch();
+        let mut tx_hash = [0u8; 32];
+        loop {
+            let tx = TxBlackjackDeal { wallet, amount: 100, nonce: 0 };
+            tx_hash[0] += 1;
+            execute_deal(&storage, &mut batch, &vrf_engine, &block(10), &tx, tx_hash)?;
+            storage.apply_batch(batch)?;
+            batch = storage.batch();
+            if storage.get_bet(&tx_hash)?.is_none() {
+                break;
+            }
+        }
+        let balance = storage.get_balance(&wallet)?;
+
+        // Nobody acts until the session expires, so the hand stands and the dealer plays out
+        let escrow = storage.get_game_session(storage.get_latest_session_id()?.unwrap())?.unwrap();
+        assert_eq!((escrow.game.as_str(), escrow.stake, escrow.expires_at), ("blackjack", 100, 110));
+        let events = session::end_block(&storage, &mut batch, &vrf_engine, &block(110), &game::GameRegistry::builtin())?;
+        storage.apply_batch(batch)?;
+        assert_eq!(events.iter().map(|event| event.kind.as_str()).collect::<Vec<_>>(), ["blackjack", "session_timeout"]);
+
+        let session = storage.get_blackjack_session(&wallet)?.unwrap();
+        assert!(session.finished && session.hands[0].stood);
+        assert!(hand_value(&session.dealer).0 >= DEALER_STANDS_ON);
+        let record = storage.get_bet(&tx_hash)?.unwrap();
+        assert_eq!(record.payout, hand_payout(&session.hands[0], &session.dealer, true)?);
+        assert_eq!(storage.get_game_session(escrow.id)?.unwrap().payout, record.payout);
+        assert_eq!(storage.get_balance(&wallet)?, balance + record.payout);
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_stake_of_doubles_and_splits() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, _) = setup(&[], 0)?;
+        let action = |action: BlackjackMove| TxBlackjackAction { wallet, action, nonce: 1 };
+        assert_eq!(action_stake(&storage, &action(BlackjackMove::Double))?, 0);
+
+        let mut batch = storage.batch();
+        storage.store_blackjack_session(&BlackjackSession {
+            wallet,
+            height: 10,
+            tx_hash: [2u8; 32],
+            nonce: 0,
+            vrf_message: Vec::new(),
+            commitment: [0u8; 32],
+            hands: vec![hand(&[7, 20], 100), live(&[7, 33], 100)],
+            active: 1,
+            dealer: vec![4],
+            next_card: 6,
+            finished: false,
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+        }, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        // Doubling or splitting the hand in play stakes its bet again; other moves stake nothing
+        assert_eq!(action_stake(&storage, &action(BlackjackMove::Double))?, 100);
+        assert_eq!(action_stake(&storage, &action(BlackjackMove::Split))?, 100);
+        assert_eq!(action_stake(&storage, &action(BlackjackMove::Hit))?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_double_takes_one_card_for_a_second_stake() -> anyhow::Result<()> {
+        let (_temp_dir, storage, vrf_engine) = setup(&[], 0)?;
+
+        // A five and a six double to one more card and end the session
+        let wallet = [1u8; 32];
+        let cards = seat(&storage, &vrf_engine, wallet, vec![live(&[4, 5], 100)], 0)?;
+        play(&storage, &vrf_engine, wallet, BlackjackMove::Double)?;
+        let session = storage.get_blackjack_session(&wallet)?.unwrap();
+        assert!(session.finished);
+        let doubled = &session.hands[0];
+        assert_eq!(doubled.cards, vec![4, 5, cards[4]]);
+        assert!(doubled.doubled && doubled.stood);
+        assert_eq!(doubled.bet, 200);
+
+        // Both stakes were escrowed and settle together
+        let record = storage.get_bet(&wallet)?.unwrap();
+        let payout = hand_payout(doubled, &session.dealer, true)?;
+        assert_eq!((record.amount, record.payout), (200, payout));
+        let escrow = storage.get_game_session(storage.get_latest_session_id()?.unwrap())?.unwrap();
+        assert_eq!((escrow.stake, escrow.payout), (200, payout));
+        assert_eq!(storage.get_balance(&wallet)?, 800 + payout);
+
+        // Three cards cannot be doubled
+        let wallet = [2u8; 32];
+        seat(&storage, &vrf_engine, wallet, vec![live(&[1, 2, 3], 100)], 0)?;
+        let rejection = play(&storage, &vrf_engine, wallet, BlackjackMove::Double).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_MOVE);
+        assert_eq!(storage.get_blackjack_session(&wallet)?.unwrap().hands[0].bet, 100);
+        assert_eq!(storage.get_balance(&wallet)?, 900);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_plays_each_hand_on_its_own_stake() -> anyhow::Result<()> {
+        let (_temp_dir, storage, vrf_engine) = setup(&[], 0)?;
+        let (ace, seven, eight, king) = (0u8, 6u8, 7u8, 12u8);
+
+        // A pair of eights splits into two hands, each taking a new second card
+        let wallet = [1u8; 32];
+        let cards = seat(&storage, &vrf_engine, wallet, vec![live(&[eight, eight + 13], 100)], 0)?;
+        play(&storage, &vrf_engine, wallet, BlackjackMove::Split)?;
+        let session = storage.get_blackjack_session(&wallet)?.unwrap();
+        assert_eq!(session.hands[0].cards, vec![eight, cards[5]]);
+        assert_eq!(session.hands[1].cards, vec![eight + 13, cards[4]]);
+        assert!(session.hands.iter().all(|hand| hand.bet == 100));
+        let escrow = storage.get_game_session(storage.get_latest_session_id()?.unwrap())?.unwrap();
+        assert_eq!(escrow.stake, 200);
+        assert_eq!(storage.get_balance(&wallet)?, 800);
+
+        // Each hand is settled on its own against the dealer
+        while !storage.get_blackjack_session(&wallet)?.unwrap().finished {
+            play(&storage, &vrf_engine, wallet, BlackjackMove::Stand)?;
+        }
+        let session = storage.get_blackjack_session(&wallet)?.unwrap();
+        let mut payout = 0;
+        for hand in &session.hands {
+            payout += hand_payout(hand, &session.dealer, false)?;
+        }
+        let record = storage.get_bet(&wallet)?.unwrap();
+        assert_eq!((record.amount, record.payout), (200, payout));
+        assert_eq!(storage.get_balance(&wallet)?, 800 + payout);
+
+        // 21 on two cards of a split hand is not a blackjack: it pays 1:1, not 3:2
+        let dealer = [king, seven];
+        assert_eq!(hand_payout(&hand(&[ace, king], 100), &dealer, true)?, 250);
+        assert_eq!(hand_payout(&hand(&[ace, king], 100), &dealer, false)?, 200);
+
+        // Split aces take one card each, which ends the session
+        let wallet = [2u8; 32];
+        let cards = seat(&storage, &vrf_engine, wallet, vec![live(&[ace, ace + 13], 100)], 0)?;
+        play(&storage, &vrf_engine, wallet, BlackjackMove::Split)?;
+        let session = storage.get_blackjack_session(&wallet)?.unwrap();
+        assert!(session.finished);
+        assert_eq!(session.hands[0].cards, vec![ace, cards[5]]);
+        assert_eq!(session.hands[1].cards, vec![ace + 13, cards[4]]);
+
+        // No more than MAX_HANDS hands
+        let wallet = [3u8; 32];
+        let mut hands = vec![hand(&[king, seven], 10); MAX_HANDS - 1];
+        hands.push(live(&[eight, eight + 13], 10));
+        seat(&storage, &vrf_engine, wallet, hands, MAX_HANDS as u8 - 1)?;
+        let rejection = play(&storage, &vrf_engine, wallet, BlackjackMove::Split).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_MOVE);
+        assert_eq!(storage.get_blackjack_session(&wallet)?.unwrap().hands.len(), MAX_HANDS);
+        Ok(())
+    }
+}
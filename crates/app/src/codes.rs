@@ -0,0 +1,71 @@
+//! Result codes of CheckTx and of transaction results in FinalizeBlock
+//!
+//! Both use the same codes, so a transaction rejected at execution time reports
+//! the same reason it would have been rejected for in the mempool.
+
+/// Transaction accepted
+pub const OK: u32 = 0;
+/// Bet amount is zero, or not a whole number of lottery tickets
+pub const INVALID_AMOUNT: u32 = 1;
+/// Wallet is the zero address
+pub const INVALID_WALLET: u32 = 2;
+/// Transaction bytes could not be decoded
+pub const DECODE_ERROR: u32 = 3;
+/// Randomness transactions can only be injected by the proposer
+pub const RESERVED_TX: u32 = 4;
+/// Signature does not verify or does not match the wallet
+pub const INVALID_SIGNATURE: u32 = 5;
+/// The node failed to process the transaction (storage, encoding, VRF)
+pub const INTERNAL_ERROR: u32 = 6;
+/// Nonce is not the next one expected from the wallet
+pub const INVALID_NONCE: u32 = 7;
+/// Balance does not cover the amount
+pub const INSUFFICIENT_FUNDS: u32 = 8;
+/// A balance or payout would not fit in a u64
+pub const BALANCE_OVERFLOW: u32 = 9;
+/// Signer is not allowed to send this transaction (deposits or lottery draws from a non-minter)
+pub const UNAUTHORIZED: u32 = 10;
+/// Recipient is the zero address or the sender itself
+pub const INVALID_RECIPIENT: u32 = 11;
+/// Withdrawal destination is empty or too long
+pub const INVALID_DESTINATION: u32 = 12;
+/// Envelope version this node does not understand (it may be from a newer release)
+pub const UNSUPPORTED_VERSION: u32 = 13;
+/// Transaction kind tag this node does not understand
+pub const UNKNOWN_TX_KIND: u32 = 14;
+/// Bet is below the minimum or above the maximum set in the chain params
+pub const BET_OUT_OF_RANGE: u32 = 15;
+/// Dice target outside the range that leaves both a winning and a losing roll
+pub const INVALID_TARGET: u32 = 16;
+/// Bet that does not exist in the game (a roulette split of non-adjacent numbers,
+/// a mines board without a gem, a slot machine or Plinko board not in the params),
+/// or an action on a bet the wallet does not have
+pub const INVALID_BET: u32 = 17;
+/// Game round is not taking this transaction (a crash bet while the round is in
+/// flight, lottery tickets once the draw height is reached)
+pub const ROUND_CLOSED: u32 = 18;
+/// Lottery draw whose draw height has passed or whose winner count is out of range
+pub const INVALID_DRAW: u32 = 19;
+/// Move the game's state does not allow (a blackjack deal while a hand is in play,
+/// a double after hitting, a mines tile revealed twice, an action the state of a
+/// game session does not take)
+pub const INVALID_MOVE: u32 = 20;
+
+/// A transaction rejected with a result code
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{log} (code {code})")]
+pub struct TxRejection {
+    pub code: u32,
+    pub log: String,
+}
+
+impl TxRejection {
+    pub fn new(code: u32, log: impl Into<String>) -> Self {
+        Self { code, log: log.into() }
+    }
+
+    /// Rejection for a failure of the node rather than of the transaction
+    pub fn internal(error: impl std::fmt::Display) -> Self {
+        Self::new(INTERNAL_ERROR, format!("Internal error: {:#}", error))
+    }
+}
@@ -0,0 +1,455 @@
+//! Crash: a multiplier that grows every block until it crashes
+//!
+//! The first bet when no round is open opens one. Bets are taken for
+//! [`BETTING_BLOCKS`] blocks, then the round is in flight: the multiplier starts at
+//! 1.00x and grows by [`MULTIPLIER_STEP`] every block. A cash-out locks in the
+//! multiplier of the block it lands in. The round ends at the end of the first
+//! block whose multiplier reaches the crash point; bets cashed out below it win,
+//! all others lose.
+//!
+//! The crash point comes from a VRF proof over a message fixed when the round
+//! opens. Only the commitment `blake3(vrf_output)` is stored until the round ends,
+//! when the proof and output are published so anyone can check the crash point.
+//! Multipliers are in hundredths (150 = 1.50x).
+
+use anyhow::{ensure, Result};
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    BetKind, BetRecord, ChainParams, CrashBet, CrashRound, SignedTx, Tx, TxCrashBet, TxCrashCashOut,
+    TxKind, BPS_DENOMINATOR,
+};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::{VrfEngine, VrfRng};
+use crate::{accounts, params, BlockContext};
+
+/// Blocks a round takes bets for, starting with the block of the opening bet
+pub const BETTING_BLOCKS: u64 = 5;
+
+/// Multiplier of the first block of the flight
+pub const START_MULTIPLIER: u64 = 100;
+
+/// Multiplier growth per block of flight
+pub const MULTIPLIER_STEP: u64 = 10;
+
+/// Highest crash point: every round ends by this multiplier
+pub const MAX_CRASH_POINT: u64 = 10_000;
+
+/// Resolution of the uniform draw behind the crash point
+const CRASH_DRAW_RANGE: u64 = 1 << 52;
+
+/// The crash game
+pub struct Crash;
+
+impl Game for Crash {
+    fn id(&self) -> &'static str {
+        "crash"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::CrashBet, TxKind::CrashCashOut]
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        match &signed.tx {
+            Tx::CrashBet(bet) => game::stake(storage, bet.amount),
+            tx => Ok(tx.amount()),
+        }
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::CrashBet(tx) => execute_bet(storage, batch, vrf_engine, block, tx, tx_hash),
+            Tx::CrashCashOut(tx) => execute_cash_out(storage, batch, block, tx),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+
+    fn end_block(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+    ) -> Result<Vec<Event>> {
+        end_block(storage, batch, vrf_engine, block)
+    }
+}
+
+/// Crash point of a VRF output
+///
+/// With `u` uniform in `[0, 1)` the crash point is `(1 - edge) / (1 - u)`, rounded
+/// down and clamped to `START_MULTIPLIER..=MAX_CRASH_POINT`, so a cash-out at
+/// multiplier `m` survives with probability `(1 - edge) / m`.
+pub fn crash_point(params: &ChainParams, vrf_output: &[u8]) -> u64 {
+    let draw = VrfRng::new(vrf_output).range(CRASH_DRAW_RANGE) as u128;
+    let net_bps = BPS_DENOMINATOR.saturating_sub(params.house_edge_bps) as u128;
+    let point = START_MULTIPLIER as u128 * net_bps * CRASH_DRAW_RANGE as u128
+        / (BPS_DENOMINATOR as u128 * (CRASH_DRAW_RANGE as u128 - draw));
+    (point.min(MAX_CRASH_POINT as u128) as u64).max(START_MULTIPLIER)
+}
+
+/// Multiplier of a block of the round's flight
+pub fn multiplier_at(round: &CrashRound, height: u64) -> u64 {
+    let blocks = height.saturating_sub(round.flight_height);
+    START_MULTIPLIER.saturating_add(blocks.saturating_mul(MULTIPLIER_STEP))
+}
+
+/// Payout of a bet cashed out at `multiplier`
+pub fn payout(amount: u64, multiplier: u64) -> Result<u64, TxRejection> {
+    let payout = amount as u128 * multiplier as u128 / START_MULTIPLIER as u128;
+    u64::try_from(payout).map_err(|_| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+/// Place a bet on the round taking bets, opening one if none is open
+pub fn execute_bet(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxCrashBet,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    // No bet may be cashed out for more than the highest crash point pays
+    payout(tx.amount, MAX_CRASH_POINT)?;
+
+    let latest = storage.get_pending_latest_crash_round_id(batch).map_err(TxRejection::internal)?;
+    let open_round = match latest {
+        Some(id) => storage.get_pending_crash_round(id, batch)
+            .map_err(TxRejection::internal)?
+            .filter(|round| round.crash_point.is_none()),
+        None => None,
+    };
+    let mut round = match open_round {
+        Some(round) if block.height >= round.flight_height => {
+            return Err(TxRejection::new(
+                codes::ROUND_CLOSED,
+                format!("Crash round {} is in flight, bets reopen when it ends", round.id),
+            ));
+        }
+        Some(round) => round,
+        None => open(vrf_engine, block, latest.map_or(1, |id| id + 1)).map_err(TxRejection::internal)?,
+    };
+
+    if storage.get_pending_crash_bet(round.id, &tx.wallet, batch).map_err(TxRejection::internal)?.is_some() {
+        return Err(TxRejection::new(
+            codes::INVALID_BET,
+            format!("Already bet in crash round {}", round.id),
+        ));
+    }
+    round.wallets.push(tx.wallet);
+    let bet = CrashBet { wallet: tx.wallet, amount: tx.amount, cash_out: None, nonce: tx.nonce, tx_hash };
+
+    storage.store_crash_round(&round, batch).map_err(TxRejection::internal)?;
+    storage.store_crash_bet(round.id, &bet, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "crash_bet".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(tx.wallet)).into(),
+            ("amount".to_string(), tx.amount.to_string()).into(),
+            ("round".to_string(), round.id.to_string()).into(),
+            ("flight_height".to_string(), round.flight_height.to_string()).into(),
+            ("commitment".to_string(), hex::encode(round.commitment)).into(),
+            ("tx_hash".to_string(), hex::encode(tx_hash)).into(),
+        ],
+    })
+}
+
+/// Lock in the multiplier of this block for a bet of the round in flight
+pub fn execute_cash_out(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    block: &BlockContext,
+    tx: &TxCrashCashOut,
+) -> Result<Event, TxRejection> {
+    let round = storage.get_pending_crash_round(tx.round, batch)
+        .map_err(TxRejection::internal)?
+        .ok_or_else(|| TxRejection::new(codes::ROUND_CLOSED, format!("Unknown crash round {}", tx.round)))?;
+    if round.crash_point.is_some() {
+        return Err(TxRejection::new(codes::ROUND_CLOSED, format!("Crash round {} has ended", round.id)));
+    }
+    if block.height < round.flight_height {
+        return Err(TxRejection::new(
+            codes::ROUND_CLOSED,
+            format!("Crash round {} is in flight from height {}", round.id, round.flight_height),
+        ));
+    }
+
+    let mut bet = storage.get_pending_crash_bet(round.id, &tx.wallet, batch)
+        .map_err(TxRejection::internal)?
+        .ok_or_else(|| TxRejection::new(codes::INVALID_BET, format!("No bet in crash round {}", round.id)))?;
+    if bet.cash_out.is_some() {
+        return Err(TxRejection::new(codes::INVALID_BET, format!("Already cashed out of crash round {}", round.id)));
+    }
+    let multiplier = multiplier_at(&round, block.height);
+    bet.cash_out = Some(multiplier);
+    storage.store_crash_bet(round.id, &bet, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "crash_cash_out".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(tx.wallet)).into(),
+            ("round".to_string(), round.id.to_string()).into(),
+            ("multiplier".to_string(), multiplier.to_string()).into(),
+        ],
+    })
+}
+
+/// End the round in flight if this block reached its crash point, settling its bets
+pub fn end_block(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+) -> Result<Vec<Event>> {
+    let Some(id) = storage.get_pending_latest_crash_round_id(batch)? else {
+        return Ok(vec![]);
+    };
+    let Some(mut round) = storage.get_pending_crash_round(id, batch)? else {
+        anyhow::bail!("Missing crash round {}", id);
+    };
+    if round.crash_point.is_some() || block.height < round.flight_height {
+        return Ok(vec![]);
+    }
+
+    // The output is recomputed rather than stored, so it stays hidden until now
+    let (vrf_output, vrf_proof) = vrf_engine.prove(&round.vrf_message)?;
+    ensure!(
+        *blake3::hash(&vrf_output).as_bytes() == round.commitment,
+        "VRF output of crash round {} does not match its commitment",
+        round.id
+    );
+    let chain_params = params::load(storage)?;
+    let point = crash_point(&chain_params, &vrf_output);
+    if multiplier_at(&round, block.height) < point {
+        return Ok(vec![]);
+    }
+
+    let mut paid = 0u64;
+    for wallet in &round.wallets {
+        let Some(bet) = storage.get_pending_crash_bet(round.id, wallet, batch)? else {
+            anyhow::bail!("Missing bet of {} in crash round {}", hex::encode(wallet), round.id);
+        };
+        let won = bet.cash_out.is_some_and(|multiplier| multiplier < point);
+        let bet_payout = match bet.cash_out {
+            Some(multiplier) if won => payout(bet.amount, multiplier)?,
+            _ => 0,
+        };
+        let record = BetRecord {
+            wallet: bet.wallet,
+            amount: bet.amount,
+            kind: BetKind::Crash { round: round.id, cash_out: bet.cash_out, crash_point: point },
+            nonce: bet.nonce,
+            vrf_message: Vec::new(),
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+            won,
+            payout: bet_payout,
+            height: block.height,
+            tx_hash: bet.tx_hash,
+        };
+
+        let balance = storage.get_pending_balance(wallet, batch)?;
+        storage.set_balance(wallet, accounts::credit(balance, bet_payout)?, batch)?;
+        storage.store_bet(&bet.tx_hash, &record, batch)?;
+        paid = paid.saturating_add(bet_payout);
+    }
+
+    round.end_height = Some(block.height);
+    round.crash_point = Some(point);
+    round.vrf_proof = vrf_proof;
+    round.vrf_output = vrf_output;
+    storage.store_crash_round(&round, batch)?;
+
+    Ok(vec![Event {
+        kind: "crash_round_end".to_string(),
+        attributes: vec![
+            ("round".to_string(), round.id.to_string()).into(),
+            ("crash_point".to_string(), point.to_string()).into(),
+            ("bets".to_string(), round.wallets.len().to_string()).into(),
+            ("paid".to_string(), paid.to_string()).into(),
+            ("vrf_proof".to_string(), hex::encode(&round.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&round.vrf_output)).into(),
+        ],
+    }])
+}
+
+/// Open a round at this block, committing to its crash point
+fn open(vrf_engine: &VrfEngine, block: &BlockContext, id: u64) -> Result<CrashRound> {
+    let vrf_message = VrfEngine::compute_crash_message(&block.chain_id, id, block.height, &block.block_random);
+    let (vrf_output, _) = vrf_engine.prove(&vrf_message)?;
+    Ok(CrashRound {
+        id,
+        open_height: block.height,
+        flight_height: block.height + BETTING_BLOCKS,
+        vrf_message,
+        commitment: *blake3::hash(&vrf_output).as_bytes(),
+        wallets: Vec::new(),
+        end_height: None,
+        crash_point: None,
+        vrf_proof: Vec::new(),
+        vrf_output: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+
+    #[test]
+    fn test_crash_point_distribution() {
+        let fair = ChainParams::default();
+        let points: Vec<u64> = (0u32..20_000).map(|i| crash_point(&fair, &i.to_le_bytes())).collect();
+        assert!(points.iter().all(|point| (START_MULTIPLIER..=MAX_CRASH_POINT).contains(point)));
+
+        // A cash-out at 2.00x survives about half the time, at 4.00x about a quarter
+        let survive = |m: u64| points.iter().filter(|&&point| point > m).count();
+        assert!((9_500..10_500).contains(&survive(200)), "{}", survive(200));
+        assert!((4_600..5_400).contains(&survive(400)), "{}", survive(400));
+
+        // The edge lowers every crash point
+        let edged = ChainParams { house_edge_bps: 500, ..Default::default() };
+        assert!((0u32..100).all(|i| crash_point(&edged, &i.to_le_bytes()) <= crash_point(&fair, &i.to_le_bytes())));
+    }
+
+    #[test]
+    fn test_round_settles_cash_outs_against_crash_point() -> anyhow::Result<()> {
+        let wallets = [[1u8; 32], [2u8; 32]];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallets[0], wallets[1], [3u8; 32]], 1_000)?;
+
+        // Both bets join the round opened by the first
+        let mut batch = storage.batch();
+        for (i, wallet) in wallets.iter().enumerate() {
+            let tx = TxCrashBet { wallet: *wallet, amount: 100, nonce: 0 };
+            execute_bet(&storage, &mut batch, &vrf_engine, &block(10), &tx, [i as u8; 32])?;
+        }
+        storage.apply_batch(batch)?;
+        let round = storage.get_crash_round(1)?.unwrap();
+        assert_eq!(round.wallets, wallets.to_vec());
+        assert_eq!(round.flight_height, 10 + BETTING_BLOCKS);
+        assert_eq!(storage.get_balance(&wallets[0])?, 900);
+
+        // Cash-outs wait for the flight; bets stop when it starts
+        let cash_out = TxCrashCashOut { wallet: wallets[0], round: 1, nonce: 1 };
+        let mut batch = storage.batch();
+        let rejection = execute_cash_out(&storage, &mut batch, &block(11), &cash_out).unwrap_err();
+        assert_eq!(rejection.code, codes::ROUND_CLOSED);
+        let late = TxCrashBet { wallet: [3u8; 32], amount: 100, nonce: 0 };
+        let rejection = execute_bet(&storage, &mut batch, &vrf_engine, &block(15), &late, [3u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::ROUND_CLOSED);
+
+        // The first wallet cashes out at 1.00x, at the first block of the flight
+        let mut height = round.flight_height;
+        let mut batch = storage.batch();
+        execute_cash_out(&storage, &mut batch, &block(height), &cash_out)?;
+        assert_eq!(
+            execute_cash_out(&storage, &mut batch, &block(height), &cash_out).unwrap_err().code,
+            codes::INVALID_BET
+        );
+
+        // Fly until the round crashes
+        let events = loop {
+            let events = end_block(&storage, &mut batch, &vrf_engine, &block(height))?;
+            storage.apply_batch(batch)?;
+            batch = storage.batch();
+            if !events.is_empty() {
+                break events;
+            }
+            height += 1;
+        };
+        assert_eq!(events[0].kind, "crash_round_end");
+
+        let round = storage.get_crash_round(1)?.unwrap();
+        let point = round.crash_point.unwrap();
+        assert_eq!(round.end_height, Some(height));
+        assert!(multiplier_at(&round, height) >= point);
+        assert!(multiplier_at(&round, height - 1) < point || height == round.flight_height);
+        assert_eq!(*blake3::hash(&round.vrf_output).as_bytes(), round.commitment);
+        assert!(VrfEngine::verify(&vrf_engine.public_key(), &round.vrf_message, &round.vrf_proof, &round.vrf_output)?);
+        assert_eq!(point, crash_point(&ChainParams::default(), &round.vrf_output));
+
+        // A 1.00x cash-out wins back the stake unless the round crashed at once
+        let record = storage.get_bet(&[0u8; 32])?.unwrap();
+        assert_eq!(record.won, point > START_MULTIPLIER);
+        assert_eq!(storage.get_balance(&wallets[0])?, if record.won { 1_000 } else { 900 });
+        let record = storage.get_bet(&[1u8; 32])?.unwrap();
+        assert!(!record.won);
+        assert_eq!(storage.get_balance(&wallets[1])?, 900);
+
+        // The next bet opens the next round
+        let tx = TxCrashBet { wallet: wallets[1], amount: 100, nonce: 1 };
+        let mut batch = storage.batch();
+        execute_bet(&storage, &mut batch, &vrf_engine, &block(height + 1), &tx, [4u8; 32])?;
+        assert_eq!(storage.get_pending_latest_crash_round_id(&batch)?, Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_left_unsettled_by_a_failure_settles_once_later() -> anyhow::Result<()> {
+        let (wallet, missing) = ([1u8; 32], [2u8; 32]);
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let games = game::GameRegistry::builtin();
+
+        // A round listing a wallet whose bet is missing cannot settle
+        let mut batch = storage.batch();
+        let tx = TxCrashBet { wallet, amount: 100, nonce: 0 };
+        execute_bet(&storage, &mut batch, &vrf_engine, &block(10), &tx, [1u8; 32])?;
+        let mut round = storage.get_pending_crash_round(1, &batch)?.unwrap();
+        round.wallets.push(missing);
+        storage.store_crash_round(&round, &mut batch)?;
+        let cash_out = TxCrashCashOut { wallet, round: 1, nonce: 1 };
+        execute_cash_out(&storage, &mut batch, &block(round.flight_height), &cash_out)?;
+        storage.apply_batch(batch)?;
+
+        let (vrf_output, _) = vrf_engine.prove(&round.vrf_message)?;
+        let point = crash_point(&ChainParams::default(), &vrf_output);
+        let height = (round.flight_height..).find(|&height| multiplier_at(&round, height) >= point).unwrap();
+
+        // The failed settlement leaves no credit behind
+        let mut batch = storage.batch();
+        assert!(games.end_block(&storage, &mut batch, &vrf_engine, &block(height)).is_empty());
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_crash_round(1)?.unwrap().crash_point, None);
+        assert!(storage.get_bet(&[1u8; 32])?.is_none());
+        assert_eq!(storage.get_balance(&wallet)?, 900);
+
+        // Once the bet is there, the next block settles the round, paying each bet once
+        let mut batch = storage.batch();
+        let bet = CrashBet { wallet: missing, amount: 100, cash_out: None, nonce: 0, tx_hash: [2u8; 32] };
+        storage.store_crash_bet(1, &bet, &mut batch)?;
+        let events = games.end_block(&storage, &mut batch, &vrf_engine, &block(height + 1));
+        storage.apply_batch(batch)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, "crash_round_end");
+        assert_eq!(storage.get_crash_round(1)?.unwrap().end_height, Some(height + 1));
+        let record = storage.get_bet(&[1u8; 32])?.unwrap();
+        assert_eq!(storage.get_balance(&wallet)?, 900 + record.payout);
+        assert!(!storage.get_bet(&[2u8; 32])?.unwrap().won);
+
+        let mut batch = storage.batch();
+        assert!(games.end_block(&storage, &mut batch, &vrf_engine, &block(height + 2)).is_empty());
+        assert_eq!(storage.get_pending_balance(&wallet, &batch)?, 900 + record.payout);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+//! Roll-under dice: the player picks a target and wins when a uniform roll in
+//! `0..DICE_SIDES` lands below it
+//!
+//! A winning bet pays the fair odds `DICE_SIDES / target` less the house edge, so
+//! every target has the same return to player.
+
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    BetKind, BetRecord, ChainParams, SignedTx, Tx, TxDice, TxKind, BPS_DENOMINATOR, DICE_SIDES,
+    MAX_DICE_TARGET, MIN_DICE_TARGET,
+};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::VrfEngine;
+use crate::{accounts, params, BlockContext};
+
+/// The roll-under dice game
+pub struct Dice;
+
+impl Game for Dice {
+    fn id(&self) -> &'static str {
+        "dice"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::Dice]
+    }
+
+    fn validate(&self, tx: &Tx) -> Result<(), TxRejection> {
+        match tx {
+            Tx::Dice(bet) => check_target(bet.target),
+            _ => Ok(()),
+        }
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        game::stake(storage, signed.tx.amount())
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::Dice(tx) => execute_dice(storage, batch, vrf_engine, block, tx, tx_hash),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+}
+
+/// Require a target that leaves both a winning and a losing roll
+pub fn check_target(target: u16) -> Result<(), TxRejection> {
+    if !(MIN_DICE_TARGET..=MAX_DICE_TARGET).contains(&target) {
+        return Err(TxRejection::new(
+            codes::INVALID_TARGET,
+            format!("Invalid target: must be {} to {}", MIN_DICE_TARGET, MAX_DICE_TARGET),
+        ));
+    }
+    Ok(())
+}
+
+/// Payout of a winning roll under `target`, rounded down
+pub fn payout(params: &ChainParams, amount: u64, target: u16) -> Result<u64, TxRejection> {
+    check_target(target)?;
+    let net_bps = BPS_DENOMINATOR.saturating_sub(params.house_edge_bps) as u128;
+    let payout = amount as u128 * DICE_SIDES as u128 * net_bps
+        / (target as u128 * BPS_DENOMINATOR as u128);
+    u64::try_from(payout).map_err(|_| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+/// Settle a dice bet: debit the stake, roll with the VRF, credit any payout
+pub fn execute_dice(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxDice,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    let win_payout = payout(&chain_params, tx.amount, tx.target)?;
+    let (vrf_message, vrf_proof, vrf_output, roll) = vrf_engine
+        .process_dice(&block.chain_id, block.height, &block.block_random, &tx_hash, &tx.wallet, tx.nonce)
+        .map_err(TxRejection::internal)?;
+    let bet_payout = if roll < tx.target { win_payout } else { 0 };
+    let record = BetRecord {
+        wallet: tx.wallet,
+        amount: tx.amount,
+        kind: BetKind::Dice { target: tx.target, roll },
+        nonce: tx.nonce,
+        vrf_message,
+        vrf_proof,
+        vrf_output,
+        // At the highest targets a winning roll pays back less than the stake
+        won: bet_payout > tx.amount,
+        payout: bet_payout,
+        height: block.height,
+        tx_hash,
+    };
+    let balance = accounts::credit(balance, record.payout)?;
+
+    storage.store_bet(&tx_hash, &record, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "dice".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(record.wallet)).into(),
+            ("amount".to_string(), record.amount.to_string()).into(),
+            ("target".to_string(), tx.target.to_string()).into(),
+            ("roll".to_string(), roll.to_string()).into(),
+            ("won".to_string(), record.won.to_string()).into(),
+            ("payout".to_string(), record.payout.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(record.tx_hash)).into(),
+            ("vrf_proof".to_string(), hex::encode(&record.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&record.vrf_output)).into(),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+
+    #[test]
+    fn test_check_target() {
+        assert!(check_target(MIN_DICE_TARGET).is_ok());
+        assert!(check_target(MAX_DICE_TARGET).is_ok());
+        assert_eq!(check_target(0).unwrap_err().code, codes::INVALID_TARGET);
+        assert_eq!(check_target(DICE_SIDES).unwrap_err().code, codes::INVALID_TARGET);
+    }
+
+    #[test]
+    fn test_payout_follows_target_and_edge() {
+        let fair = ChainParams::default();
+        assert_eq!(payout(&fair, 100, 5_000), Ok(200));
+        assert_eq!(payout(&fair, 100, 2_500), Ok(400));
+        assert_eq!(payout(&fair, 100, 1), Ok(1_000_000));
+
+        // 1% edge, rounded down
+        let params = ChainParams { house_edge_bps: 100, ..Default::default() };
+        assert_eq!(payout(&params, 100, 5_000), Ok(198));
+        assert_eq!(payout(&params, 100, 9_999), Ok(99));
+        assert_eq!(payout(&params, u64::MAX, 1).unwrap_err().code, codes::BALANCE_OVERFLOW);
+        assert_eq!(payout(&params, 100, 0).unwrap_err().code, codes::INVALID_TARGET);
+    }
+
+    #[test]
+    fn test_execute_dice() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let block = block(5);
+        let tx = TxDice { wallet, amount: 100, target: 5_000, nonce: 0 };
+
+        let mut batch = storage.batch();
+        execute_dice(&storage, &mut batch, &vrf_engine, &block, &tx, [9u8; 32])?;
+        storage.apply_batch(batch)?;
+
+        // The stored record carries the roll, which decides the balance
+        let record = storage.get_bet(&[9u8; 32])?.unwrap();
+        let BetKind::Dice { target, roll } = record.kind else {
+            panic!("expected a dice record, got {:?}", record.kind);
+        };
+        assert_eq!(target, 5_000);
+        assert_eq!(record.won, roll < target);
+        assert_eq!(roll, VrfEngine::derive_dice_roll(&record.vrf_output));
+        let expected = if record.won { 1_100 } else { 900 };
+        assert_eq!(storage.get_balance(&wallet)?, expected);
+
+        // A stake above the balance is rejected without writing anything
+        let tx = TxDice { amount: 5_000, ..tx };
+        let mut batch = storage.batch();
+        let rejection = execute_dice(&storage, &mut batch, &vrf_engine, &block, &tx, [8u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INSUFFICIENT_FUNDS);
+        storage.apply_batch(batch)?;
+        assert!(storage.get_bet(&[8u8; 32])?.is_none());
+        assert_eq!(storage.get_balance(&wallet)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_roll_paying_back_less_than_the_stake_is_a_loss() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let mut batch = storage.batch();
+        storage.set_params(&ChainParams { house_edge_bps: 100, ..Default::default() }, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        // With a 1% edge a roll under 9999 pays 99 on a stake of 100
+        let tx = TxDice { wallet, amount: 100, target: 9_999, nonce: 0 };
+        let mut batch = storage.batch();
+        execute_dice(&storage, &mut batch, &vrf_engine, &block(5), &tx, [9u8; 32])?;
+        storage.apply_batch(batch)?;
+
+        let record = storage.get_bet(&[9u8; 32])?.unwrap();
+        let BetKind::Dice { roll, .. } = record.kind else {
+            panic!("expected a dice record, got {:?}", record.kind);
+        };
+        assert!(!record.won);
+        assert_eq!(record.payout, if roll < 9_999 { 99 } else { 0 });
+        assert_eq!(storage.get_balance(&wallet)?, 900 + record.payout);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! Coin flip: the player picks a side and wins the payout multiplier, less the
+//! house edge, when the VRF shows it
+
+use anyhow::Result;
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{BetKind, BetRecord, CoinSide, SignedTx, Tx, TxFlip, TxKind};
+use tendermint::abci::Event;
+
+use crate::codes::TxRejection;
+use crate::game::{self, Game};
+use crate::vrf::VrfEngine;
+use crate::{accounts, params, BlockContext};
+
+/// The coin flip game
+pub struct Flip;
+
+impl Game for Flip {
+    fn id(&self) -> &'static str {
+        "flip"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::Flip]
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        game::stake(storage, signed.tx.amount())
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::Flip(tx) => execute_flip(storage, batch, vrf_engine, block, tx, tx_hash),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+}
+
+/// Flip the coin for a transaction and record the bet
+///
+/// `win_payout` is what the player is credited if the coin shows their side.
+pub fn process_flip(
+    vrf_engine: &VrfEngine,
+    tx: &TxFlip,
+    tx_hash: [u8; 32],
+    block: &BlockContext,
+    win_payout: u64,
+) -> Result<BetRecord> {
+    // Process VRF computation
+    let (vrf_message, vrf_proof, vrf_output, flip_result) = vrf_engine.process_flip(
+        &block.chain_id,
+        block.height,
+        &block.block_random,
+        &tx_hash,
+        &tx.wallet,
+        tx.nonce,
+    )?;
+    let won = CoinSide::from_result(flip_result) == tx.choice;
+
+    Ok(BetRecord {
+        wallet: tx.wallet,
+        amount: tx.amount,
+        kind: BetKind::Flip { choice: tx.choice, result: flip_result },
+        nonce: tx.nonce,
+        vrf_message,
+        vrf_proof,
+        vrf_output,
+        won,
+        payout: if won { win_payout } else { 0 },
+        height: block.height,
+        tx_hash,
+    })
+}
+
+/// Settle a coin flip: debit the stake, draw the VRF result, credit any payout
+pub fn execute_flip(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxFlip,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch)
+        .map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    let win_payout = params::flip_payout(&chain_params, tx.amount)?;
+    let record = process_flip(vrf_engine, tx, tx_hash, block, win_payout)
+        .map_err(TxRejection::internal)?;
+    let balance = accounts::credit(balance, record.payout)?;
+
+    storage.store_bet(&tx_hash, &record, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+    let BetKind::Flip { result, .. } = record.kind else {
+        unreachable!("process_flip records a flip");
+    };
+
+    Ok(Event {
+        kind: "flip".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(record.wallet)).into(),
+            ("amount".to_string(), record.amount.to_string()).into(),
+            ("choice".to_string(), tx.choice.name().to_string()).into(),
+            ("result".to_string(), CoinSide::from_result(result).name().to_string()).into(),
+            ("won".to_string(), record.won.to_string()).into(),
+            ("payout".to_string(), record.payout.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(record.tx_hash)).into(),
+            ("vrf_proof".to_string(), hex::encode(&record.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&record.vrf_output)).into(),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codes;
+    use crate::testing::{block, setup};
+
+    #[test]
+    fn test_execute_flip() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let block = block(5);
+        let tx = TxFlip::new(wallet, 100, CoinSide::Heads, 0);
+
+        let mut batch = storage.batch();
+        execute_flip(&storage, &mut batch, &vrf_engine, &block, &tx, [9u8; 32])?;
+        storage.apply_batch(batch)?;
+
+        // The stored result comes from the VRF output and decides the balance
+        let record = storage.get_bet(&[9u8; 32])?.unwrap();
+        // The record carries the envelope hash it is stored and queried under
+        assert_eq!(record.tx_hash, [9u8; 32]);
+        assert!(storage.get_bet(&record.tx_hash)?.is_some());
+        let BetKind::Flip { choice, result } = record.kind else {
+            panic!("expected a flip record, got {:?}", record.kind);
+        };
+        assert_eq!(choice, CoinSide::Heads);
+        assert_eq!(result, VrfEngine::derive_flip_result(&record.vrf_output));
+        assert_eq!(record.won, result);
+        let expected = if record.won { 1_100 } else { 900 };
+        assert_eq!(storage.get_balance(&wallet)?, expected);
+
+        // A stake above the balance is rejected without writing anything
+        let tx = TxFlip::new(wallet, 5_000, CoinSide::Tails, 1);
+        let mut batch = storage.batch();
+        let rejection = execute_flip(&storage, &mut batch, &vrf_engine, &block, &tx, [8u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INSUFFICIENT_FUNDS);
+        assert!(storage.get_bet(&[8u8; 32])?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+//! Games plug into the ABCI handlers through the [`Game`] trait
+//!
+//! A game declares the transaction kinds it settles. CheckTx asks it what the
+//! signer must hold, FinalizeBlock hands it each of its transactions and, after
+//! the last one, a chance to settle what the block left open (shared spins,
+//! rounds that crashed, draws that are due). Games played over several
+//! transactions keep their stakes in a [`session`](crate::session), which the
+//! game settles when it times out. The [`GameRegistry`] routes each kind to its
+//! game; account transactions are not games and stay with the handlers.
+
+use anyhow::{ensure, Result};
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{GameInfo, GameSession, SignedTx, Tx, TxKind};
+use std::sync::Arc;
+use tendermint::abci::Event;
+use tracing::error;
+
+use crate::codes::{self, TxRejection};
+use crate::vrf::VrfEngine;
+use crate::{blackjack, crash, dice, flip, lottery, mines, params, plinko, roulette, session, slots, BlockContext};
+
+/// A game the chain runs
+pub trait Game: Send + Sync {
+    /// Stable id, unique in the registry
+    fn id(&self) -> &'static str;
+
+    /// Transaction kinds the game settles
+    fn tx_kinds(&self) -> &'static [TxKind];
+
+    /// Check a transaction of the game without reading state, when it is decoded
+    fn validate(&self, _tx: &Tx) -> Result<(), TxRejection> {
+        Ok(())
+    }
+
+    /// Amount the signer must hold for the transaction to enter the mempool
+    ///
+    /// Payouts are unknown before execution, so this is what the transaction can
+    /// lose at most: its stake for a bet, the stake it adds for a move raising one
+    /// (a blackjack double or split), nothing for a move in a game already paid for.
+    fn exposure(&self, _storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        Ok(signed.tx.amount())
+    }
+
+    /// Execute a transaction of the game on top of the block's pending writes,
+    /// drawing its outcome from the block randomness
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection>;
+
+    /// Settle what the block's transactions left open, after the last one
+    ///
+    /// If it fails, none of its writes are kept, and what it left open is still
+    /// open for the next block.
+    fn end_block(
+        &self,
+        _storage: &Storage,
+        _batch: &mut StorageBatch,
+        _vrf_engine: &VrfEngine,
+        _block: &BlockContext,
+    ) -> Result<Vec<Event>> {
+        Ok(Vec::new())
+    }
+
+    /// Transaction kinds a session of the game takes in a state
+    fn session_actions(&self, _state: &str) -> &'static [TxKind] {
+        &[]
+    }
+
+    /// Settle a session nobody acted on before it expired, storing it settled
+    ///
+    /// Credits the stake back unless the game overrides it, for instance to
+    /// stand or cash out on the player's behalf.
+    fn time_out(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        _vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        session: GameSession,
+    ) -> Result<Vec<Event>> {
+        session::refund(storage, batch, block, session)?;
+        Ok(Vec::new())
+    }
+}
+
+/// Exposure of a bet: its stake, once it is within the bet limits
+pub fn stake(storage: &Storage, amount: u64) -> Result<u64, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, amount)?;
+    Ok(amount)
+}
+
+/// Rejection for a transaction routed to a game that does not settle its kind
+pub fn unhandled(game: &str, tx: &Tx) -> TxRejection {
+    TxRejection::new(
+        codes::UNKNOWN_TX_KIND,
+        format!("Game {} does not handle {} transactions", game, tx.kind().name()),
+    )
+}
+
+/// Games keyed by id, and the game settling each transaction kind
+///
+/// Games end blocks in the order they were registered, which is part of consensus.
+#[derive(Clone, Default)]
+pub struct GameRegistry {
+    games: Vec<Arc<dyn Game>>,
+}
+
+impl GameRegistry {
+    /// Registry of every game this release ships
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        let games: [Arc<dyn Game>; 9] = [
+            Arc::new(flip::Flip),
+            Arc::new(dice::Dice),
+            Arc::new(roulette::Roulette),
+            Arc::new(crash::Crash),
+            Arc::new(lottery::Lottery),
+            Arc::new(blackjack::Blackjack),
+            Arc::new(mines::Mines),
+            Arc::new(slots::Slots),
+            Arc::new(plinko::Plinko),
+        ];
+        for game in games {
+            registry.register(game).expect("builtin games have distinct ids and kinds");
+        }
+        registry
+    }
+
+    /// Add a game; its id and transaction kinds must not be taken
+    pub fn register(&mut self, game: Arc<dyn Game>) -> Result<()> {
+        ensure!(self.get(game.id()).is_none(), "Game {} is already registered", game.id());
+        for &kind in game.tx_kinds() {
+            if let Some(other) = self.for_kind(kind) {
+                anyhow::bail!("{} transactions are already settled by {}", kind.name(), other.id());
+            }
+        }
+        self.games.push(game);
+        Ok(())
+    }
+
+    /// Game with the given id
+    pub fn get(&self, id: &str) -> Option<&dyn Game> {
+        self.iter().find(|game| game.id() == id)
+    }
+
+    /// Game settling a transaction kind
+    pub fn for_kind(&self, kind: TxKind) -> Option<&dyn Game> {
+        self.iter().find(|game| game.tx_kinds().contains(&kind))
+    }
+
+    /// Game settling a transaction kind, rejecting kinds no game settles
+    pub fn game_for(&self, kind: TxKind) -> Result<&dyn Game, TxRejection> {
+        self.for_kind(kind).ok_or_else(|| {
+            TxRejection::new(codes::UNKNOWN_TX_KIND, format!("No game settles {} transactions", kind.name()))
+        })
+    }
+
+    /// Let every game settle what the block left open, in registration order
+    ///
+    /// A game that fails is logged and its writes dropped, so the others still settle.
+    pub fn end_block(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+    ) -> Vec<Event> {
+        let mut events = Vec::new();
+        for game in self.iter() {
+            match batch.atomically(|batch| game.end_block(storage, batch, vrf_engine, block)) {
+                Ok(game_events) => events.extend(game_events),
+                Err(e) => error!("Failed to end block for {}: {:#}", game.id(), e),
+            }
+        }
+        events
+    }
+
+    /// Games in registration order
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Game> {
+        self.games.iter().map(|game| game.as_ref())
+    }
+
+    /// What the `/games` query lists
+    pub fn infos(&self) -> Vec<GameInfo> {
+        self.iter()
+            .map(|game| GameInfo {
+                id: game.id().to_string(),
+                tx_kinds: game.tx_kinds().iter().map(|kind| kind.tag()).collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Wheel;
+
+    impl Game for Wheel {
+        fn id(&self) -> &'static str {
+            "wheel"
+        }
+
+        fn tx_kinds(&self) -> &'static [TxKind] {
+            &[TxKind::Dice]
+        }
+
+        fn settle(
+            &self,
+            _storage: &Storage,
+            _batch: &mut StorageBatch,
+            _vrf_engine: &VrfEngine,
+            _block: &BlockContext,
+            signed: &SignedTx,
+            _tx_hash: [u8; 32],
+        ) -> Result<Event, TxRejection> {
+            Err(unhandled(self.id(), &signed.tx))
+        }
+    }
+
+    #[test]
+    fn test_builtin_games_cover_every_game_kind() {
+        let registry = GameRegistry::builtin();
+        let accounts = [TxKind::Deposit, TxKind::Withdraw, TxKind::Transfer];
+        for tag in 1..=17 {
+            let kind = TxKind::from_tag(tag).unwrap();
+            assert_eq!(registry.for_kind(kind).is_some(), !accounts.contains(&kind), "{}", kind.name());
+        }
+        assert_eq!(registry.for_kind(TxKind::MinesReveal).unwrap().id(), "mines");
+        assert_eq!(registry.get("plinko").unwrap().tx_kinds(), &[TxKind::Plinko]);
+        assert!(registry.get("poker").is_none());
+
+        // Registration order is end-block order
+        let ids: Vec<String> = registry.infos().into_iter().map(|info| info.id).collect();
+        assert_eq!(ids[..5], ["flip", "dice", "roulette", "crash", "lottery"]);
+        assert_eq!(registry.infos()[3].tx_kinds, vec![TxKind::CrashBet.tag(), TxKind::CrashCashOut.tag()]);
+    }
+
+    #[test]
+    fn test_register_rejects_taken_ids_and_kinds() {
+        let mut registry = GameRegistry::default();
+        registry.register(Arc::new(Wheel)).unwrap();
+        assert!(registry.register(Arc::new(Wheel)).is_err());
+        // Another id for a kind that is already settled
+        assert!(GameRegistry::builtin().register(Arc::new(Wheel)).is_err());
+        assert_eq!(registry.for_kind(TxKind::Dice).unwrap().id(), "wheel");
+        assert!(registry.for_kind(TxKind::Flip).is_none());
+        assert_eq!(registry.game_for(TxKind::Flip).err().unwrap().code, codes::UNKNOWN_TX_KIND);
+    }
+}
@@ -0,0 +1,58 @@
+//! Deterministic gas accounting
+//!
+//! There are no fees yet. Gas is reported per transaction so CometBFT can apply the
+//! block gas limit (`max_gas`) and clients can see what a transaction cost.
+
+use mychain_types::TxKind;
+
+/// Charged for every transaction: decoding and signature verification
+pub const BASE_GAS: i64 = 1_000;
+
+/// Charged per byte of the encoded transaction
+pub const GAS_PER_BYTE: i64 = 10;
+
+/// Execution cost of a transaction kind on top of the base cost
+pub fn execution_gas(kind: TxKind) -> i64 {
+    match kind {
+        // Proves a VRF output
+        TxKind::Flip
+        | TxKind::Dice
+        | TxKind::Roulette
+        | TxKind::CrashBet
+        | TxKind::BlackjackDeal
+        | TxKind::BlackjackAction
+        | TxKind::MinesOpen
+        | TxKind::MinesReveal
+        | TxKind::MinesCashOut
+        | TxKind::Slots
+        | TxKind::Plinko => 5_000,
+        TxKind::Deposit
+        | TxKind::Withdraw
+        | TxKind::Transfer
+        | TxKind::CrashCashOut
+        | TxKind::LotteryOpen
+        | TxKind::LotteryTicket => 500,
+    }
+}
+
+/// Gas of a transaction of `tx_len` bytes; `kind` is `None` when it was
+/// rejected before executing
+pub fn tx_gas(tx_len: usize, kind: Option<TxKind>) -> i64 {
+    let bytes = i64::try_from(tx_len).unwrap_or(i64::MAX);
+    BASE_GAS
+        .saturating_add(bytes.saturating_mul(GAS_PER_BYTE))
+        .saturating_add(kind.map_or(0, execution_gas))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tx_gas() {
+        assert_eq!(tx_gas(0, None), BASE_GAS);
+        assert_eq!(tx_gas(100, None), BASE_GAS + 100 * GAS_PER_BYTE);
+        assert_eq!(tx_gas(100, Some(TxKind::Flip)), BASE_GAS + 100 * GAS_PER_BYTE + 5_000);
+        assert_eq!(tx_gas(usize::MAX, Some(TxKind::Flip)), i64::MAX);
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use mychain_types::ChainParams;
+use std::collections::BTreeMap;
+
+use crate::params;
+use crate::vote_extension::VALIDATOR_ADDRESS_LENGTH;
+use crate::vrf::VRF_PUBLIC_KEY_LENGTH;
+
+/// Application genesis state, read from `app_state` in the CometBFT genesis file
+///
+/// Example:
+/// ```json
+/// {
+///   "vrf_public_key": "<house vrf public key hex>",
+///   "validator_vrf_keys": {
+///     "<validator address hex>": "<vrf public key hex>"
+///   },
+///   "balances": {
+///     "<wallet hex>": 1000000
+///   },
+///   "minter_public_key": "<ed25519 public key hex>",
+///   "params": {
+///     "house_edge_bps": 100,
+///     "min_bet": 1,
+///     "max_bet": 1000000,
+///     "payout_multiplier": 2,
+///     "slot_machines": [{
+///       "id": 1,
+///       "reels": [[0, 1, 1, 2], [0, 1, 1, 2], [0, 1, 1, 2]],
+///       "rows": 1,
+///       "paylines": [[0, 0, 0]],
+///       "paytable": [{ "symbol": 0, "count": 3, "pays": 2000 }]
+///     }],
+///     "max_slot_rtp_bps": 9500,
+///     "plinko_tables": [{
+///       "rows": 8,
+///       "risk": "low",
+///       "multipliers": [550, 200, 110, 100, 50, 100, 110, 200, 550]
+///     }],
+///     "session_timeout_blocks": 100
+///   }
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenesisState {
+    /// House VRF public key (hex) every game outcome is proven under; each node must
+    /// run with the matching key file or validators would compute different outcomes
+    pub vrf_public_key: String,
+    /// Vote extension VRF public keys by validator address (both hex encoded)
+    pub validator_vrf_keys: BTreeMap<String, String>,
+    /// Initial wallet balances (wallet hex encoded)
+    pub balances: BTreeMap<String, u64>,
+    /// Key allowed to sign deposits (hex encoded); deposits are disabled without one
+    pub minter_public_key: Option<String>,
+    /// Chain parameters; missing fields take their defaults
+    pub params: ChainParams,
+}
+
+impl GenesisState {
+    /// Parse the `app_state_bytes` of InitChain; empty bytes mean default state
+    pub fn from_app_state_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.iter().all(|b| b.is_ascii_whitespace()) {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice(bytes).context("Invalid genesis app_state")
+    }
+
+    /// Decode and validate the house VRF public key, which genesis must set
+    pub fn vrf_public_key(&self) -> Result<Vec<u8>> {
+        if self.vrf_public_key.is_empty() {
+            anyhow::bail!("Genesis app_state has no vrf_public_key");
+        }
+        hex::decode(&self.vrf_public_key)
+            .ok()
+            .filter(|bytes| bytes.len() == VRF_PUBLIC_KEY_LENGTH)
+            .with_context(|| format!("Invalid VRF public key: {}", self.vrf_public_key))
+    }
+
+    /// Decode and validate the validator VRF key registry
+    pub fn validator_vrf_keys(&self) -> Result<Vec<([u8; VALIDATOR_ADDRESS_LENGTH], Vec<u8>)>> {
+        self.validator_vrf_keys
+            .iter()
+            .map(|(address, vrf_pk)| {
+                let address: [u8; VALIDATOR_ADDRESS_LENGTH] = hex::decode(address)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .with_context(|| format!("Invalid validator address: {}", address))?;
+                let vrf_pk = hex::decode(vrf_pk)
+                    .ok()
+                    .filter(|bytes| bytes.len() == VRF_PUBLIC_KEY_LENGTH)
+                    .with_context(|| format!("Invalid VRF public key: {}", vrf_pk))?;
+                Ok((address, vrf_pk))
+            })
+            .collect()
+    }
+
+    /// Decode and validate the initial balances
+    pub fn balances(&self) -> Result<Vec<([u8; 32], u64)>> {
+        self.balances
+            .iter()
+            .map(|(wallet, balance)| {
+                let wallet: [u8; 32] = hex::decode(wallet)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .with_context(|| format!("Invalid wallet: {}", wallet))?;
+                Ok((wallet, *balance))
+            })
+            .collect()
+    }
+
+    /// Decode and validate the minter key
+    pub fn minter_public_key(&self) -> Result<Option<[u8; 32]>> {
+        self.minter_public_key
+            .as_ref()
+            .map(|public_key| {
+                hex::decode(public_key)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .with_context(|| format!("Invalid minter public key: {}", public_key))
+            })
+            .transpose()
+    }
+
+    /// Validate the chain parameters
+    pub fn params(&self) -> Result<ChainParams> {
+        params::validate(&self.params).context("Invalid chain params")?;
+        Ok(self.params.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_genesis_state() -> Result<()> {
+        assert!(GenesisState::from_app_state_bytes(b"")?.validator_vrf_keys.is_empty());
+        assert!(GenesisState::from_app_state_bytes(b"{}")?.validator_vrf_keys.is_empty());
+
+        let json = format!(
+            r#"{{"validator_vrf_keys": {{"{}": "{}"}}}}"#,
+            hex::encode([1u8; 20]),
+            hex::encode([2u8; 32])
+        );
+        let genesis = GenesisState::from_app_state_bytes(json.as_bytes())?;
+        assert_eq!(genesis.validator_vrf_keys()?, vec![([1u8; 20], vec![2u8; 32])]);
+
+        let bad = format!(r#"{{"validator_vrf_keys": {{"abcd": "{}"}}}}"#, hex::encode([2u8; 32]));
+        assert!(GenesisState::from_app_state_bytes(bad.as_bytes())?.validator_vrf_keys().is_err());
+        assert!(GenesisState::from_app_state_bytes(br#"{"unknown": 1}"#).is_err());
+
+        let json = format!(r#"{{"vrf_public_key": "{}"}}"#, hex::encode([3u8; 32]));
+        assert_eq!(GenesisState::from_app_state_bytes(json.as_bytes())?.vrf_public_key()?, vec![3u8; 32]);
+        assert!(GenesisState::from_app_state_bytes(b"{}")?.vrf_public_key().is_err());
+        assert!(GenesisState::from_app_state_bytes(br#"{"vrf_public_key": "abcd"}"#)?.vrf_public_key().is_err());
+
+        let json = format!(r#"{{"balances": {{"{}": 500}}}}"#, hex::encode([3u8; 32]));
+        let genesis = GenesisState::from_app_state_bytes(json.as_bytes())?;
+        assert_eq!(genesis.balances()?, vec![([3u8; 32], 500)]);
+        assert!(GenesisState::from_app_state_bytes(br#"{"balances": {"abcd": 1}}"#)?.balances().is_err());
+
+        assert_eq!(GenesisState::default().minter_public_key()?, None);
+        let json = format!(r#"{{"minter_public_key": "{}"}}"#, hex::encode([4u8; 32]));
+        let genesis = GenesisState::from_app_state_bytes(json.as_bytes())?;
+        assert_eq!(genesis.minter_public_key()?, Some([4u8; 32]));
+        assert!(GenesisState::from_app_state_bytes(br#"{"minter_public_key": "abcd"}"#)?.minter_public_key().is_err());
+
+        assert_eq!(GenesisState::default().params()?, ChainParams::default());
+        let genesis = GenesisState::from_app_state_bytes(br#"{"params": {"house_edge_bps": 100, "max_bet": 500}}"#)?;
+        assert_eq!(
+            genesis.params()?,
+            ChainParams { house_edge_bps: 100, max_bet: 500, ..Default::default() }
+        );
+        assert!(GenesisState::from_app_state_bytes(br#"{"params": {"min_bet": 0}}"#)?.params().is_err());
+        assert!(GenesisState::from_app_state_bytes(br#"{"params": {"session_timeout_blocks": 0}}"#)?.params().is_err());
+        assert!(GenesisState::from_app_state_bytes(br#"{"params": {"rtp": 1}}"#).is_err());
+
+        // Slot machines are configuration; one paying back more than it takes in is refused
+        let machine = |pays: u64| {
+            format!(
+                r#"{{"params": {{"slot_machines": [{{"id": 1, "reels": [[0, 1], [0, 1]], "rows": 1,
+                    "paylines": [[0, 0]], "paytable": [{{"symbol": 0, "count": 2, "pays": {}}}]}}]}}}}"#,
+                pays
+            )
+        };
+        let params = GenesisState::from_app_state_bytes(machine(360).as_bytes())?.params()?;
+        assert_eq!(params.slot_machines[0].reels, vec![vec![0, 1], vec![0, 1]]);
+        assert!(GenesisState::from_app_state_bytes(machine(440).as_bytes())?.params().is_err());
+
+        // So are Plinko boards, named by rows and risk
+        let json = br#"{"params": {"plinko_tables": [{"rows": 8, "risk": "medium", "multipliers": [100, 100, 100, 100, 100, 100, 100, 100, 100]}]}}"#;
+        let params = GenesisState::from_app_state_bytes(json)?.params()?;
+        assert_eq!(params.plinko_tables[0].risk, mychain_types::PlinkoRisk::Medium);
+        let json = br#"{"params": {"plinko_tables": [{"rows": 8, "risk": "extreme", "multipliers": []}]}}"#;
+        assert!(GenesisState::from_app_state_bytes(json).is_err());
+
+        Ok(())
+    }
+}
@@ -1,13 +1,50 @@
+pub mod accounts;
+pub mod blackjack;
+pub mod codes;
+pub mod crash;
+pub mod dice;
+pub mod flip;
+pub mod game;
+pub mod gas;
+pub mod genesis;
+pub mod lottery;
+pub mod mempool;
+pub mod mines;
+pub mod params;
+pub mod plinko;
+pub mod proof;
+pub mod roulette;
+pub mod session;
+pub mod slots;
+pub mod vote_extension;
 pub mod vrf;
 
+#[cfg(test)]
+mod testing;
+
 use anyhow::{Context, Result};
-use mychain_storage::Storage;
-use mychain_types::{BetRecord, TxFlip};
+use bytes::Bytes;
+use codes::TxRejection;
+use game::GameRegistry;
+use genesis::GenesisState;
+use mempool::CheckState;
+use mychain_storage::merkle::StateProof;
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{SignedTx, Tx, TxError, TxKind};
+use std::cmp::Ordering;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use tower::service_fn;
 use tower_abci::v038::ServerBuilder;
+use tendermint::abci::types::{BlockSignatureInfo, ExecTxResult, ExtendedCommitInfo};
+use tendermint::abci::Event;
+use tendermint::block::BlockIdFlag;
 use tendermint::v0_38::abci::{request, response};
 use tendermint::AppHash;
+use vote_extension::{
+    contributions_expected, RandomnessTx, ValidatorContribution, VrfContribution,
+    VALIDATOR_ADDRESS_LENGTH,
+};
 use vrf::VrfEngine;
 use tracing::{info, warn, error};
 
@@ -15,6 +52,18 @@ use tracing::{info, warn, error};
 #[derive(Clone)]
 pub struct MyChainApp {
     storage_path: String,
+    vrf_engine: Arc<VrfEngine>,
+    validator_vrf: Option<Arc<VrfEngine>>,
+    check_state: Arc<Mutex<CheckState>>,
+    games: Arc<GameRegistry>,
+}
+
+/// What a transaction can see of the block executing it
+pub struct BlockContext {
+    pub height: u64,
+    /// Block randomness, see [`VrfEngine::compute_block_random`]
+    pub block_random: [u8; 32],
+    pub chain_id: String,
 }
 
 // Ensure MyChainApp is Send + Sync
@@ -22,14 +71,85 @@ unsafe impl Send for MyChainApp {}
 unsafe impl Sync for MyChainApp {}
 
 impl MyChainApp {
-    pub fn new<P: AsRef<Path>>(storage_path: P) -> Result<Self> {
+    pub fn new<P: AsRef<Path>>(storage_path: P, vrf_engine: VrfEngine) -> Result<Self> {
         let storage_path = storage_path.as_ref().to_string_lossy().to_string();
         
         // Test storage connection
-        let _storage = Storage::open(&storage_path)
+        let storage = Storage::open(&storage_path)
             .context("Failed to open storage")?;
+
+        // Refuse to run with a key that differs from the one committed at InitChain,
+        // otherwise every proof would fail verification against the stored key
+        if let Some(stored_pk) = storage.get_vrf_public_key()? {
+            if stored_pk != vrf_engine.public_key() {
+                anyhow::bail!(
+                    "VRF key mismatch: storage has {}, key file has {}",
+                    hex::encode(&stored_pk),
+                    hex::encode(vrf_engine.public_key())
+                );
+            }
+        }
         
-        Ok(Self { storage_path })
+        Ok(Self {
+            storage_path,
+            vrf_engine: Arc::new(vrf_engine),
+            validator_vrf: None,
+            check_state: Arc::new(Mutex::new(CheckState::default())),
+            games: Arc::new(GameRegistry::builtin()),
+        })
+    }
+
+    /// Sign vote extensions with this validator VRF key
+    ///
+    /// Without it the node still follows consensus but contributes nothing to the
+    /// block randomness, which is the right setup for non-validator full nodes.
+    pub fn with_validator_vrf_key(mut self, validator_vrf: VrfEngine) -> Self {
+        self.validator_vrf = Some(Arc::new(validator_vrf));
+        self
+    }
+
+    /// Run these games instead of the builtin ones
+    ///
+    /// Every node of a chain must run the same games, registered in the same order.
+    pub fn with_games(mut self, games: GameRegistry) -> Self {
+        self.games = Arc::new(games);
+        self
+    }
+
+    /// Write the genesis `app_state` into the initial state
+    fn init_genesis(&self, storage: &Storage, app_state_bytes: &[u8], batch: &mut StorageBatch) -> Result<()> {
+        let genesis = GenesisState::from_app_state_bytes(app_state_bytes)?;
+
+        // Game outcomes are proven under this key, so a node running any other key
+        // would compute outcomes the rest of the chain disagrees with
+        let vrf_public_key = genesis.vrf_public_key()?;
+        if vrf_public_key != self.vrf_engine.public_key() {
+            anyhow::bail!(
+                "VRF key mismatch: genesis has {}, key file has {}",
+                hex::encode(&vrf_public_key),
+                hex::encode(self.vrf_engine.public_key())
+            );
+        }
+        storage.set_vrf_public_key(&vrf_public_key, batch)?;
+
+        let validator_vrf_keys = genesis.validator_vrf_keys()?;
+        if validator_vrf_keys.is_empty() {
+            warn!("No validator VRF keys in genesis, block randomness falls back to block hashes");
+        }
+        for (address, vrf_pk) in &validator_vrf_keys {
+            storage.set_validator_vrf_key(address, vrf_pk, batch)?;
+        }
+
+        for (wallet, balance) in genesis.balances()? {
+            storage.set_balance(&wallet, balance, batch)?;
+        }
+        match genesis.minter_public_key()? {
+            Some(public_key) => storage.set_minter_public_key(&public_key, batch)?,
+            None => warn!("No minter key in genesis, deposits are disabled"),
+        }
+        storage.set_params(&genesis.params()?, batch)?;
+
+        Ok(())
     }
 
     /// Get a storage instance (for per-request access)
@@ -38,50 +158,325 @@ impl MyChainApp {
             .context("Failed to open storage")
     }
 
-    /// Process a flip transaction and generate VRF result
-    fn process_flip(
+    /// Validate a user transaction before it enters the mempool
+    ///
+    /// New transactions and rechecks after a commit go through the same checks,
+    /// against the committed state plus the mempool's pending effects (see [`CheckState`]).
+    fn check_tx(&self, tx_bytes: &[u8]) -> response::CheckTx {
+        match self.validate_tx(tx_bytes) {
+            Ok(kind) => response::CheckTx {
+                code: codes::OK.into(),
+                log: "Transaction valid".to_string(),
+                gas_wanted: gas::tx_gas(tx_bytes.len(), Some(kind)),
+                ..Default::default()
+            },
+            Err(rejection) => response::CheckTx {
+                code: rejection.code.into(),
+                log: rejection.log,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn validate_tx(&self, tx_bytes: &[u8]) -> Result<TxKind, TxRejection> {
+        // Randomness txs are only ever injected by the block proposer
+        if RandomnessTx::is_randomness_tx(tx_bytes) {
+            return Err(TxRejection::new(
+                codes::RESERVED_TX,
+                "Randomness transactions cannot be submitted",
+            ));
+        }
+
+        let storage = self.storage().map_err(TxRejection::internal)?;
+        let chain_id = storage.get_chain_id().map_err(TxRejection::internal)?.unwrap_or_default();
+        let signed = decode_tx(tx_bytes, &chain_id, &self.games)?;
+        let wallet = *signed.tx.wallet();
+        let nonce = signed.tx.nonce();
+
+        // Hold the lock across check and update so concurrent checks see each other
+        let mut check_state = self.check_state.lock().expect("check state lock poisoned");
+        let committed_nonce = storage.get_nonce(&wallet).map_err(TxRejection::internal)?;
+        check_nonce(nonce, check_state.next_nonce(&wallet, committed_nonce))?;
+
+        // Deposits are minted; everything else spends from the signer's balance
+        let spend = match &signed.tx {
+            Tx::Deposit(_) => {
+                accounts::check_minter(&storage, &signed.public_key)?;
+                0
+            }
+            tx @ (Tx::Withdraw(_) | Tx::Transfer(_)) => tx.amount(),
+            tx => self.games.game_for(tx.kind())?.exposure(&storage, &signed)?,
+        };
+
+        // Amounts already in the mempool are spent as far as this tx is concerned
+        let balance = storage.get_balance(&wallet).map_err(TxRejection::internal)?;
+        let available = balance.saturating_sub(check_state.spent(&wallet));
+        accounts::debit(available, spend)?;
+
+        check_state.accept_nonce(wallet, nonce);
+        check_state.accept_spend(wallet, spend);
+
+        Ok(signed.tx.kind())
+    }
+
+    /// Execute a transaction of the block on top of its pending writes
+    ///
+    /// Every transaction gets a result, so CometBFT can report failed ones in `/tx`
+    /// and `broadcast_tx_commit`. A failed transaction writes nothing and only pays
+    /// the base gas.
+    fn deliver_tx(
         &self,
-        tx: &TxFlip,
-        height: u64,
-        vrf_engine: &VrfEngine,
-        chain_id: &str,
-    ) -> Result<BetRecord> {
-        // Create VRF message from transaction data
-        let tx_hash = tx.hash()?;
-        let block_random = height.to_le_bytes(); // Simplified for POC
-        
-        // Process VRF computation
-        let (vrf_message, vrf_proof, vrf_output, flip_result) = vrf_engine.process_flip(
-            chain_id,
-            height,
-            &block_random,
-            &tx_hash,
-            &tx.wallet,
-            tx.nonce,
-        )?;
-
-        // Create bet record
-        let record = BetRecord {
-            wallet: tx.wallet,
-            amount: tx.amount,
-            nonce: tx.nonce,
-            vrf_message,
-            vrf_proof,
-            vrf_output,
-            result: flip_result,
-            height,
-            tx_hash,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        block: &BlockContext,
+        tx_bytes: &[u8],
+    ) -> ExecTxResult {
+        // Randomness was already folded into the block context
+        if RandomnessTx::is_randomness_tx(tx_bytes) {
+            return ExecTxResult {
+                code: codes::OK.into(),
+                info: "randomness".to_string(),
+                ..Default::default()
+            };
+        }
+
+        match self.execute_tx(storage, batch, block, tx_bytes) {
+            Ok((kind, events)) => {
+                let gas = gas::tx_gas(tx_bytes.len(), Some(kind));
+                ExecTxResult {
+                    code: codes::OK.into(),
+                    gas_wanted: gas,
+                    gas_used: gas,
+                    events,
+                    ..Default::default()
+                }
+            }
+            Err(rejection) => {
+                let gas = gas::tx_gas(tx_bytes.len(), None);
+                ExecTxResult {
+                    code: rejection.code.into(),
+                    log: rejection.log,
+                    gas_wanted: gas,
+                    gas_used: gas,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Execute a user transaction, returning its kind and events
+    fn execute_tx(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        block: &BlockContext,
+        tx_bytes: &[u8],
+    ) -> Result<(TxKind, Vec<Event>), TxRejection> {
+        // Proposers may include txs that never passed CheckTx, so check everything again
+        let signed = decode_tx(tx_bytes, &block.chain_id, &self.games)?;
+        let wallet = *signed.tx.wallet();
+        let nonce = signed.tx.nonce();
+        let expected_nonce = storage.get_pending_nonce(&wallet, batch)
+            .map_err(TxRejection::internal)?;
+        check_nonce(nonce, expected_nonce)?;
+
+        let tx_hash = *blake3::hash(tx_bytes).as_bytes();
+        let event = match &signed.tx {
+            Tx::Deposit(tx) => {
+                accounts::execute_deposit(storage, batch, &signed.public_key, tx, block.height, tx_hash)?
+            }
+            Tx::Withdraw(tx) => accounts::execute_withdraw(storage, batch, tx, block.height, tx_hash)?,
+            Tx::Transfer(tx) => accounts::execute_transfer(storage, batch, tx, block.height, tx_hash)?,
+            tx => self.games.game_for(tx.kind())?.settle(storage, batch, &self.vrf_engine, block, &signed, tx_hash)?,
+        };
+
+        storage.store_tx_height(&tx_hash, block.height, batch).map_err(TxRejection::internal)?;
+        storage.set_nonce(&wallet, nonce + 1, batch).map_err(TxRejection::internal)?;
+
+        Ok((signed.tx.kind(), vec![event]))
+    }
+
+    /// Block randomness agreed on by all nodes for a block with these transactions
+    ///
+    /// Folds the aggregated vote extension contributions injected by the proposer (if
+    /// any) into the running VRF accumulator and chains it with the previous block
+    /// hash. Returns `(block_random, vrf_accum)`.
+    fn derive_block_random(&self, storage: &Storage, txs: &[Bytes]) -> Result<([u8; 32], [u8; 32])> {
+        let contributions = match txs.first().filter(|tx| RandomnessTx::is_randomness_tx(tx)) {
+            Some(tx) => match RandomnessTx::from_bytes(tx) {
+                Ok(randomness_tx) => Some(randomness_tx.aggregate()),
+                Err(e) => {
+                    error!("Failed to decode randomness tx: {}", e);
+                    None
+                }
+            },
+            None => None,
         };
 
-        Ok(record)
+        let prev_vrf_accum = storage.get_vrf_accumulator()?.unwrap_or([0u8; 32]);
+        let vrf_accum = VrfEngine::accumulate(&prev_vrf_accum, contributions.as_ref());
+
+        let prev_block_hash = storage.get_last_block_hash()?.unwrap_or_default();
+        let block_random = VrfEngine::compute_block_random(&prev_block_hash, &vrf_accum);
+
+        Ok((block_random, vrf_accum))
+    }
+
+    /// Build this validator's vote extension for the block being voted on
+    fn extend_vote(&self, req: &request::ExtendVote) -> Result<Bytes> {
+        let Some(validator_vrf) = &self.validator_vrf else {
+            return Ok(Bytes::new());
+        };
+        let storage = self.storage()?;
+        let chain_id = storage.get_chain_id()?.unwrap_or_default();
+
+        let message = VrfEngine::compute_extension_message(
+            &chain_id,
+            req.height.value(),
+            req.hash.as_bytes(),
+        );
+        let (vrf_output, vrf_proof) = validator_vrf.prove(&message)?;
+        Ok(VrfContribution { vrf_proof, vrf_output }.to_bytes()?.into())
+    }
+
+    /// Check another validator's vote extension against its registered VRF key
+    fn verify_vote_extension(&self, req: &request::VerifyVoteExtension) -> Result<bool> {
+        let storage = self.storage()?;
+        let Some(vrf_pk) = storage.get_validator_vrf_key(req.validator_address.as_bytes())? else {
+            // Extensions of unregistered validators are never aggregated
+            return Ok(true);
+        };
+        let Ok(contribution) = VrfContribution::from_bytes(&req.vote_extension) else {
+            return Ok(false);
+        };
+        let chain_id = storage.get_chain_id()?.unwrap_or_default();
+        Ok(contribution
+            .verify(&vrf_pk, &chain_id, req.height.value(), req.hash.as_bytes())
+            .unwrap_or(false))
+    }
+
+    /// Collect the valid contributions of the last commit into a randomness tx
+    fn collect_contributions(
+        &self,
+        storage: &Storage,
+        height: u64,
+        last_commit: Option<&ExtendedCommitInfo>,
+    ) -> Result<RandomnessTx> {
+        let chain_id = storage.get_chain_id()?.unwrap_or_default();
+        let block_hash = storage.get_last_block_hash()?.unwrap_or_default();
+
+        let mut contributions = Vec::new();
+        for vote in last_commit.iter().flat_map(|commit| commit.votes.iter()) {
+            if !signed_last_commit(&vote.sig_info) {
+                continue;
+            }
+            let validator = vote.validator.address;
+            let Some(vrf_pk) = storage.get_validator_vrf_key(&validator)? else {
+                continue;
+            };
+            let contribution = match VrfContribution::from_bytes(&vote.vote_extension) {
+                Ok(contribution) => contribution,
+                Err(e) => {
+                    warn!("Undecodable vote extension from {}: {}", hex::encode(validator), e);
+                    continue;
+                }
+            };
+            if !contribution.verify(&vrf_pk, &chain_id, height, &block_hash).unwrap_or(false) {
+                warn!("Invalid vote extension from {}", hex::encode(validator));
+                continue;
+            }
+            contributions.push(ValidatorContribution { validator, contribution });
+        }
+        contributions.sort_by_key(|c| c.validator);
+
+        Ok(RandomnessTx { height, contributions })
+    }
+
+    /// Build the proposal: randomness tx first, then mempool txs that fit
+    fn prepare_proposal(&self, req: request::PrepareProposal) -> Result<Vec<Bytes>> {
+        let storage = self.storage()?;
+        let height = req.height.value();
+        let max_tx_bytes = usize::try_from(req.max_tx_bytes).unwrap_or(0);
+
+        let mut txs = Vec::with_capacity(req.txs.len() + 1);
+        let mut total_bytes = 0;
+
+        if contributions_expected(storage.get_vote_extensions_enable_height()?, height)
+            && storage.has_validator_vrf_keys()?
+        {
+            let randomness_tx = self.collect_contributions(
+                &storage,
+                height - 1,
+                req.local_last_commit.as_ref(),
+            )?;
+            let tx_bytes = randomness_tx.to_bytes()?;
+            total_bytes += tx_proto_size(tx_bytes.len());
+            txs.push(tx_bytes.into());
+        }
+
+        for tx in req.txs {
+            if RandomnessTx::is_randomness_tx(&tx) {
+                continue;
+            }
+            let tx_size = tx_proto_size(tx.len());
+            if total_bytes + tx_size > max_tx_bytes {
+                break;
+            }
+            total_bytes += tx_size;
+            txs.push(tx);
+        }
+
+        Ok(txs)
+    }
+
+    /// Validate the randomness tx of a proposal
+    fn process_proposal(&self, req: &request::ProcessProposal) -> Result<()> {
+        let storage = self.storage()?;
+        let height = req.height.value();
+
+        let randomness_positions: Vec<usize> = req.txs.iter()
+            .enumerate()
+            .filter(|(_, tx)| RandomnessTx::is_randomness_tx(tx))
+            .map(|(index, _)| index)
+            .collect();
+
+        let expected = contributions_expected(storage.get_vote_extensions_enable_height()?, height)
+            && storage.has_validator_vrf_keys()?;
+        if !expected {
+            if !randomness_positions.is_empty() {
+                anyhow::bail!("Unexpected randomness tx at height {}", height);
+            }
+            return Ok(());
+        }
+        if randomness_positions != [0] {
+            anyhow::bail!("Proposal must contain exactly one randomness tx, as the first tx");
+        }
+
+        let randomness_tx = RandomnessTx::from_bytes(&req.txs[0])?;
+        if randomness_tx.height + 1 != height {
+            anyhow::bail!(
+                "Randomness tx for height {} proposed at height {}",
+                randomness_tx.height, height
+            );
+        }
+
+        let signers: Vec<[u8; VALIDATOR_ADDRESS_LENGTH]> = req.proposed_last_commit.iter()
+            .flat_map(|commit| commit.votes.iter())
+            .filter(|vote| signed_last_commit(&vote.sig_info))
+            .map(|vote| vote.validator.address)
+            .collect();
+        let chain_id = storage.get_chain_id()?.unwrap_or_default();
+        let block_hash = storage.get_last_block_hash()?.unwrap_or_default();
+
+        randomness_tx.verify(&storage, &chain_id, &block_hash, &signers)
     }
 
     /// Create the ABCI server using tower-abci v0.19 API
     pub async fn create_server(&self) -> Result<tower_abci::v038::Server<
-        impl tower::Service<tendermint::v0_38::abci::ConsensusRequest, Response = tendermint::v0_38::abci::ConsensusResponse, Error = tower_abci::BoxError> + Clone,
-        impl tower::Service<tendermint::v0_38::abci::MempoolRequest, Response = tendermint::v0_38::abci::MempoolResponse, Error = tower_abci::BoxError> + Clone,
-        impl tower::Service<tendermint::v0_38::abci::InfoRequest, Response = tendermint::v0_38::abci::InfoResponse, Error = tower_abci::BoxError> + Clone,
-        impl tower::Service<tendermint::v0_38::abci::SnapshotRequest, Response = tendermint::v0_38::abci::SnapshotResponse, Error = tower_abci::BoxError> + Clone,
+        impl tower::Service<tendermint::v0_38::abci::ConsensusRequest, Response = tendermint::v0_38::abci::ConsensusResponse, Error = tower_abci::BoxError, Future: Send + 'static> + Send + Clone + 'static,
+        impl tower::Service<tendermint::v0_38::abci::MempoolRequest, Response = tendermint::v0_38::abci::MempoolResponse, Error = tower_abci::BoxError, Future: Send + 'static> + Send + Clone + 'static,
+        impl tower::Service<tendermint::v0_38::abci::InfoRequest, Response = tendermint::v0_38::abci::InfoResponse, Error = tower_abci::BoxError, Future: Send + 'static> + Send + Clone + 'static,
+        impl tower::Service<tendermint::v0_38::abci::SnapshotRequest, Response = tendermint::v0_38::abci::SnapshotResponse, Error = tower_abci::BoxError, Future: Send + 'static> + Send + Clone + 'static,
     >> {
         let app = self.clone();
 
@@ -141,42 +536,13 @@ impl MyChainApp {
 
         // Mempool service (CheckTx)
         let mempool = {
-            service_fn(move |request: tendermint::v0_38::abci::MempoolRequest| async move {
-                // Basic transaction validation
-                let tx_bytes = match request {
-                    tendermint::v0_38::abci::MempoolRequest::CheckTx(ref req) => &req.tx,
-                };
-                match bincode::deserialize::<TxFlip>(tx_bytes) {
-                    Ok(tx) => {
-                        // Validate transaction format
-                        if tx.amount == 0 {
-                            return Ok(tendermint::v0_38::abci::MempoolResponse::CheckTx(response::CheckTx {
-                            code: 1u32.into(),
-                            log: "Invalid amount: must be greater than 0".to_string(),
-                            ..Default::default()
-                        }));
-                        }
-
-                        if tx.wallet == [0u8; 32] {
-                            return Ok(tendermint::v0_38::abci::MempoolResponse::CheckTx(response::CheckTx {
-                            code: 2u32.into(),
-                            log: "Invalid wallet: cannot be zero".to_string(),
-                            ..Default::default()
-                        }));
-                    }
-
-                    Ok(tendermint::v0_38::abci::MempoolResponse::CheckTx(response::CheckTx {
-                        code: 0u32.into(),
-                        log: "Transaction valid".to_string(),
-                        ..Default::default()
-                    }))
-                }
+            let app = app.clone();
+            service_fn(move |request: tendermint::v0_38::abci::MempoolRequest| {
+                let app = app.clone();
+                async move {
+                    let tendermint::v0_38::abci::MempoolRequest::CheckTx(req) = request;
+                    Ok(tendermint::v0_38::abci::MempoolResponse::CheckTx(app.check_tx(&req.tx)))
                 }
-                Err(e) => Ok(tendermint::v0_38::abci::MempoolResponse::CheckTx(response::CheckTx {
-                    code: 3u32.into(),
-                    log: format!("Failed to decode transaction: {}", e),
-                    ..Default::default()
-                }))
             })
         };
 
@@ -204,25 +570,45 @@ impl MyChainApp {
                                 }
                             };
 
-                            // Initialize VRF engine
-                            let vrf_engine = VrfEngine::generate();
-                            
                             // Store initial state
                             let mut batch = storage.batch();
                             if let Err(e) = storage.set_last_height(0, &mut batch) {
                                 error!("Failed to set initial height: {}", e);
                             }
-                            if let Err(e) = storage.set_vrf_public_key(&vrf_engine.public_key(), &mut batch) {
-                                error!("Failed to set VRF public key: {}", e);
+                            if let Err(e) = storage.set_chain_id(&req.chain_id, &mut batch) {
+                                error!("Failed to set chain id: {}", e);
+                            }
+                            if let Some(enable_height) = req.consensus_params.abci.vote_extensions_enable_height {
+                                if let Err(e) = storage.set_vote_extensions_enable_height(enable_height.value(), &mut batch) {
+                                    error!("Failed to set vote extensions enable height: {}", e);
+                                }
+                            }
+
+                            // Invalid genesis must stop the node rather than start a chain
+                            // from a state nobody intended
+                            if let Err(e) = app.init_genesis(&storage, &req.app_state_bytes, &mut batch) {
+                                error!("Invalid genesis state: {:#}", e);
+                                return Err(tower_abci::BoxError::from(e));
+                            }
+
+                            // Genesis app hash is the root of the initial state
+                            let app_hash = storage.compute_app_hash(&batch).unwrap_or_else(|e| {
+                                error!("Failed to compute genesis app hash: {}", e);
+                                [0u8; 32]
+                            });
+                            if let Err(e) = storage.store_app_hash(0, &app_hash, &mut batch) {
+                                error!("Failed to store genesis app hash: {}", e);
                             }
                             if let Err(e) = storage.apply_batch(batch) {
                                 error!("Failed to apply initial batch: {}", e);
                             }
 
+                            info!("Genesis app_hash={}", hex::encode(app_hash));
+
                             Ok(ConsensusResponse::InitChain(response::InitChain {
                                 consensus_params: Some(req.consensus_params),
                                 validators: req.validators,
-                                app_hash: AppHash::try_from(vec![0u8; 32]).unwrap_or_default(),
+                                app_hash: AppHash::try_from(app_hash.to_vec()).unwrap_or_default(),
                             }))
                         }
                         ConsensusRequest::FinalizeBlock(req) => {
@@ -243,15 +629,11 @@ impl MyChainApp {
                                 }
                             };
 
-                            // Initialize VRF engine for this block
-                            let vrf_engine = match storage.get_vrf_public_key() {
-                                Ok(Some(_)) => VrfEngine::generate(), // Simplified: generate new each time
-                                Ok(None) => {
-                                    warn!("No VRF key found, generating new one");
-                                    VrfEngine::generate()
-                                }
+                            let chain_id = storage.get_chain_id().unwrap_or(None).unwrap_or_default();
+                            let (block_random, vrf_accum) = match app.derive_block_random(&storage, &req.txs) {
+                                Ok(randomness) => randomness,
                                 Err(e) => {
-                                    error!("Failed to get VRF key: {}", e);
+                                    error!("Failed to derive block randomness: {}", e);
                                     return Ok(ConsensusResponse::FinalizeBlock(response::FinalizeBlock {
                                         events: vec![],
                                         tx_results: vec![],
@@ -262,61 +644,48 @@ impl MyChainApp {
                                 }
                             };
 
-                            let mut all_events = Vec::new();
-                            let mut bet_records = Vec::new();
+                            let block = BlockContext { height, block_random, chain_id };
+                            let mut batch = storage.batch();
 
                             // Process each transaction
-                            for (tx_index, tx_bytes) in req.txs.iter().enumerate() {
-                                match bincode::deserialize::<TxFlip>(tx_bytes) {
-                                    Ok(tx) => {
-                                        match app.process_flip(&tx, height, &vrf_engine, "mychain") {
-                                            Ok(record) => {
-                                                bet_records.push((tx_bytes.clone(), record.clone()));
-
-                                                // Create event
-                                                let event = tendermint::abci::Event {
-                                                    kind: "flip".to_string(),
-                                                    attributes: vec![
-                                                        ("wallet".to_string(), hex::encode(record.wallet)).into(),
-                                                        ("amount".to_string(), record.amount.to_string()).into(),
-                                                        ("result".to_string(), if record.result { "heads" } else { "tails" }.to_string()).into(),
-                                                        ("tx_hash".to_string(), hex::encode(record.tx_hash)).into(),
-                                                        ("vrf_proof".to_string(), hex::encode(&record.vrf_proof)).into(),
-                                                        ("vrf_output".to_string(), hex::encode(&record.vrf_output)).into(),
-                                                    ],
-                                                };
-                                                all_events.push(event);
-                                            }
-                                            Err(e) => {
-                                                error!("Failed to process flip {}: {}", tx_index, e);
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to parse transaction {}: {}", tx_index, e);
+                            let tx_results: Vec<ExecTxResult> = req.txs.iter()
+                                .enumerate()
+                                .map(|(tx_index, tx_bytes)| {
+                                    let result = app.deliver_tx(&storage, &mut batch, &block, tx_bytes);
+                                    if result.code.is_err() {
+                                        warn!("Rejected transaction {}: {}", tx_index, result.log);
                                     }
-                                }
-                            }
+                                    result
+                                })
+                                .collect();
 
-                            // Store records
-                            let mut batch = storage.batch();
-                            for (tx_bytes, record) in bet_records {
-                                let tx_hash = blake3::hash(&tx_bytes);
-                                if let Err(e) = storage.store_bet(tx_hash.as_bytes(), &record, &mut batch) {
-                                    error!("Failed to store bet record: {}", e);
-                                }
-                                if let Err(e) = storage.store_tx_height(tx_hash.as_bytes(), height, &mut batch) {
-                                    error!("Failed to store tx height: {}", e);
-                                }
+                            // Games settle what the block's transactions left open
+                            let mut events = app.games.end_block(&storage, &mut batch, &app.vrf_engine, &block);
+                            // Then sessions nobody acted on time out
+                            match session::end_block(&storage, &mut batch, &app.vrf_engine, &block, &app.games) {
+                                Ok(session_events) => events.extend(session_events),
+                                Err(e) => error!("Failed to time out game sessions: {:#}", e),
                             }
 
                             // Update height
                             if let Err(e) = storage.set_last_height(height, &mut batch) {
                                 error!("Failed to set height: {}", e);
                             }
+                            if let Err(e) = storage.set_last_block_hash(req.hash.as_bytes(), &mut batch) {
+                                error!("Failed to set block hash: {}", e);
+                            }
+                            if let Err(e) = storage.store_block_random(height, &block_random, &mut batch) {
+                                error!("Failed to store block random: {}", e);
+                            }
+                            if let Err(e) = storage.set_vrf_accumulator(&vrf_accum, &mut batch) {
+                                error!("Failed to set VRF accumulator: {}", e);
+                            }
 
                             // Compute and store app hash
-                            let app_hash = storage.compute_app_hash(height).unwrap_or([0u8; 32]);
+                            let app_hash = storage.compute_app_hash(&batch).unwrap_or_else(|e| {
+                                error!("Failed to compute app hash: {}", e);
+                                [0u8; 32]
+                            });
                             if let Err(e) = storage.store_app_hash(height, &app_hash, &mut batch) {
                                 error!("Failed to store app hash: {}", e);
                             }
@@ -330,8 +699,8 @@ impl MyChainApp {
                                   height, hex::encode(&app_hash));
 
                             Ok(ConsensusResponse::FinalizeBlock(response::FinalizeBlock {
-                                events: all_events,
-                                tx_results: vec![], // Will be filled by CometBFT
+                                events,
+                                tx_results,
                                 validator_updates: vec![],
                                 consensus_param_updates: None,
                                 app_hash: AppHash::try_from(app_hash.to_vec()).unwrap_or_default(),
@@ -339,7 +708,9 @@ impl MyChainApp {
                         }
                         ConsensusRequest::Commit => {
                             info!("Commit");
-                            // Storage is already committed in finalize_block
+                            // Storage is already committed in finalize_block; the mempool
+                            // is rechecked against it from scratch
+                            app.check_state.lock().expect("check state lock poisoned").reset();
                             Ok(ConsensusResponse::Commit(response::Commit {
                                 retain_height: 0u32.into(),
                                 data: vec![].into(),
@@ -347,28 +718,52 @@ impl MyChainApp {
                         }
                         // ABCI++ methods
                         ConsensusRequest::PrepareProposal(req) => {
-                            info!("PrepareProposal: tx_count={}", req.txs.len());
-                            // Pass through transactions unchanged for POC
-                            Ok(ConsensusResponse::PrepareProposal(response::PrepareProposal {
-                                txs: req.txs,
-                            }))
+                            info!("PrepareProposal: height={}, tx_count={}", req.height, req.txs.len());
+                            let fallback_txs = req.txs.clone();
+                            let txs = match app.prepare_proposal(req) {
+                                Ok(txs) => txs,
+                                Err(e) => {
+                                    // A proposal without the randomness tx would be rejected,
+                                    // but proposing it keeps the round moving
+                                    error!("Failed to prepare proposal: {:#}", e);
+                                    fallback_txs.into_iter()
+                                        .filter(|tx| !RandomnessTx::is_randomness_tx(tx))
+                                        .collect()
+                                }
+                            };
+                            Ok(ConsensusResponse::PrepareProposal(response::PrepareProposal { txs }))
                         }
-                        ConsensusRequest::ProcessProposal(_req) => {
-                            info!("ProcessProposal");
-                            // Accept all proposals for POC
-                            Ok(ConsensusResponse::ProcessProposal(response::ProcessProposal::Accept))
+                        ConsensusRequest::ProcessProposal(req) => {
+                            info!("ProcessProposal: height={}", req.height);
+                            match app.process_proposal(&req) {
+                                Ok(()) => Ok(ConsensusResponse::ProcessProposal(response::ProcessProposal::Accept)),
+                                Err(e) => {
+                                    warn!("Rejecting proposal at height {}: {:#}", req.height, e);
+                                    Ok(ConsensusResponse::ProcessProposal(response::ProcessProposal::Reject))
+                                }
+                            }
                         }
-                        ConsensusRequest::ExtendVote(_req) => {
-                            info!("ExtendVote");
-                            // No vote extensions for POC
-                            Ok(ConsensusResponse::ExtendVote(response::ExtendVote {
-                                vote_extension: bytes::Bytes::new(),
-                            }))
+                        ConsensusRequest::ExtendVote(req) => {
+                            info!("ExtendVote: height={}", req.height);
+                            let vote_extension = app.extend_vote(&req).unwrap_or_else(|e| {
+                                error!("Failed to extend vote: {:#}", e);
+                                Bytes::new()
+                            });
+                            Ok(ConsensusResponse::ExtendVote(response::ExtendVote { vote_extension }))
                         }
-                        ConsensusRequest::VerifyVoteExtension(_req) => {
-                            info!("VerifyVoteExtension");
-                            // Accept all vote extensions for POC
-                            Ok(ConsensusResponse::VerifyVoteExtension(response::VerifyVoteExtension::Accept))
+                        ConsensusRequest::VerifyVoteExtension(req) => {
+                            info!("VerifyVoteExtension: height={}, validator={}", req.height, req.validator_address);
+                            match app.verify_vote_extension(&req) {
+                                Ok(true) => Ok(ConsensusResponse::VerifyVoteExtension(response::VerifyVoteExtension::Accept)),
+                                Ok(false) => {
+                                    warn!("Rejecting vote extension from {}", req.validator_address);
+                                    Ok(ConsensusResponse::VerifyVoteExtension(response::VerifyVoteExtension::Reject))
+                                }
+                                Err(e) => {
+                                    error!("Failed to verify vote extension: {:#}", e);
+                                    Ok(ConsensusResponse::VerifyVoteExtension(response::VerifyVoteExtension::Reject))
+                                }
+                            }
                         }
                     }
                 }
@@ -423,14 +818,15 @@ impl MyChainApp {
                     });
                 }
 
+                let prove = || storage.prove_bet(&request.data);
                 match storage.get_bet(&request.data) {
                     Ok(Some(bet)) => {
                         match bincode::serialize(&bet) {
-                            Ok(data) => Ok(response::Query {
+                            Ok(data) => Ok(with_proof(&storage, &request, response::Query {
                                 code: 0u32.into(),
                                 value: data.into(),
                                 ..Default::default()
-                            }),
+                            }, prove)),
                             Err(e) => Ok(response::Query {
                                 code: 3u32.into(),
                                 log: format!("Failed to serialize bet: {}", e),
@@ -438,11 +834,477 @@ impl MyChainApp {
                             })
                         }
                     }
-                    Ok(None) => Ok(response::Query {
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
                         code: 4u32.into(),
                         log: "Bet not found".to_string(),
                         ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/receipt" => {
+                // Query a deposit, withdrawal or transfer receipt by transaction hash
+                if request.data.len() < 32 {
+                    return Ok(response::Query {
+                        code: 2u32.into(),
+                        log: "Invalid tx hash length".to_string(),
+                        ..Default::default()
+                    });
+                }
+
+                let prove = || storage.prove_receipt(&request.data);
+                match storage.get_receipt(&request.data) {
+                    Ok(Some(receipt)) => match receipt.to_bytes() {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize receipt: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Receipt not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/block_random" => {
+                // Query block randomness by height (u64 little-endian)
+                let height_bytes: [u8; 8] = match request.data.as_ref().try_into() {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid height length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let height = u64::from_le_bytes(height_bytes);
+                let prove = || storage.prove_block_random(height);
+                match storage.get_block_random(height) {
+                    Ok(Some(block_random)) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 0u32.into(),
+                        value: block_random.to_vec().into(),
+                        ..Default::default()
+                    }, prove)),
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Block random not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/balance" => {
+                // Query the balance of a wallet (u64 little-endian)
+                let wallet: [u8; 32] = match request.data.as_ref().try_into() {
+                    Ok(wallet) => wallet,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid wallet length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let prove = || storage.prove_balance(&wallet);
+                match storage.get_balance(&wallet) {
+                    Ok(balance) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 0u32.into(),
+                        value: balance.to_le_bytes().to_vec().into(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/params" => {
+                // Query the chain params (JSON ChainParams)
+                let prove = || storage.prove_params();
+                match params::load(&storage).and_then(|chain_params| Ok(chain_params.to_bytes()?)) {
+                    Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 0u32.into(),
+                        value: data.into(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/games" => {
+                // Query the games this node runs, as bincode Vec<GameInfo>; they are
+                // part of the release rather than the state, so there is no proof
+                match bincode::serialize(&self.games.infos()) {
+                    Ok(data) => Ok(response::Query {
+                        code: 0u32.into(),
+                        value: data.into(),
+                        ..Default::default()
                     }),
+                    Err(e) => Ok(response::Query {
+                        code: 3u32.into(),
+                        log: format!("Failed to serialize games: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/roulette_spin" => {
+                // Query the spin of a table: height (u64 little-endian) || table (u32 little-endian)
+                if request.data.len() != 12 {
+                    return Ok(response::Query {
+                        code: 2u32.into(),
+                        log: "Invalid height and table length".to_string(),
+                        ..Default::default()
+                    });
+                }
+
+                let height = u64::from_le_bytes(request.data[..8].try_into().expect("length is checked"));
+                let table = u32::from_le_bytes(request.data[8..].try_into().expect("length is checked"));
+                let prove = || storage.prove_roulette_spin(height, table);
+                match storage.get_roulette_spin(height, table) {
+                    Ok(Some(spin)) => match spin.to_bytes() {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize roulette spin: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Roulette spin not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/crash_round" => {
+                // Query a crash round by id (u64 little-endian)
+                let id_bytes: [u8; 8] = match request.data.as_ref().try_into() {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid round id length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let id = u64::from_le_bytes(id_bytes);
+                let prove = || storage.prove_crash_round(id);
+                match storage.get_crash_round(id) {
+                    Ok(Some(round)) => match round.to_bytes() {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize crash round: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Crash round not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/lottery_draw" => {
+                // Query a lottery draw by draw id (u64 little-endian)
+                let id_bytes: [u8; 8] = match request.data.as_ref().try_into() {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid draw id length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let id = u64::from_le_bytes(id_bytes);
+                let prove = || storage.prove_lottery_draw(id);
+                match storage.get_lottery_draw(id) {
+                    Ok(Some(draw)) => match draw.to_bytes() {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize lottery draw: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Lottery draw not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/lottery_tickets" => {
+                // Query the tickets a wallet holds in a draw (u64 little-endian):
+                // draw id (u64 little-endian) || wallet
+                if request.data.len() != 40 {
+                    return Ok(response::Query {
+                        code: 2u32.into(),
+                        log: "Invalid draw id and wallet length".to_string(),
+                        ..Default::default()
+                    });
+                }
+
+                let id = u64::from_le_bytes(request.data[..8].try_into().expect("length is checked"));
+                let wallet: [u8; 32] = request.data[8..].try_into().expect("length is checked");
+                let prove = || storage.prove_lottery_tickets(id, &wallet);
+                match storage.get_lottery_tickets(id, &wallet) {
+                    Ok(tickets) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 0u32.into(),
+                        value: tickets.to_le_bytes().to_vec().into(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/lottery_winners" => {
+                // Query the winners of a lottery draw by draw id (u64 little-endian),
+                // as bincode Vec<LotteryWinner>
+                let id_bytes: [u8; 8] = match request.data.as_ref().try_into() {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid draw id length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let id = u64::from_le_bytes(id_bytes);
+                let prove = || storage.prove_lottery_winners(id);
+                match storage.get_lottery_winners(id) {
+                    Ok(Some(winners)) => match bincode::serialize(&winners) {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize lottery winners: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Lottery draw not drawn".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/blackjack_session" => {
+                // Query the latest blackjack session of a wallet
+                let wallet: [u8; 32] = match request.data.as_ref().try_into() {
+                    Ok(wallet) => wallet,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid wallet length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let prove = || storage.prove_blackjack_session(&wallet);
+                match storage.get_blackjack_session(&wallet) {
+                    Ok(Some(session)) => match session.to_bytes() {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize blackjack session: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Blackjack session not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/mines_game" => {
+                // Query the latest mines game of a wallet; the board is published once it ends
+                let wallet: [u8; 32] = match request.data.as_ref().try_into() {
+                    Ok(wallet) => wallet,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid wallet length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let prove = || storage.prove_mines_game(&wallet);
+                match storage.get_mines_game(&wallet) {
+                    Ok(Some(game)) => match game.to_bytes() {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize mines game: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Mines game not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/game_session" => {
+                // Query a game session by id (u64 little-endian)
+                let id_bytes: [u8; 8] = match request.data.as_ref().try_into() {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid session id length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let id = u64::from_le_bytes(id_bytes);
+                let prove = || storage.prove_game_session(id);
+                match storage.get_game_session(id) {
+                    Ok(Some(session)) => match session.to_bytes() {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize game session: {}", e),
+                            ..Default::default()
+                        })
+                    },
+                    Ok(None) => Ok(with_proof(&storage, &request, response::Query {
+                        code: 4u32.into(),
+                        log: "Game session not found".to_string(),
+                        ..Default::default()
+                    }, prove)),
+                    Err(e) => Ok(response::Query {
+                        code: 5u32.into(),
+                        log: format!("Storage error: {}", e),
+                        ..Default::default()
+                    })
+                }
+            }
+            "/active_sessions" => {
+                // Query the ids of the game sessions a wallet has in play, as bincode Vec<u64>
+                let wallet: [u8; 32] = match request.data.as_ref().try_into() {
+                    Ok(wallet) => wallet,
+                    Err(_) => {
+                        return Ok(response::Query {
+                            code: 2u32.into(),
+                            log: "Invalid wallet length".to_string(),
+                            ..Default::default()
+                        });
+                    }
+                };
+
+                let prove = || storage.prove_active_sessions(&wallet);
+                match storage.get_active_sessions(&wallet) {
+                    Ok(ids) => match bincode::serialize(&ids) {
+                        Ok(data) => Ok(with_proof(&storage, &request, response::Query {
+                            code: 0u32.into(),
+                            value: data.into(),
+                            ..Default::default()
+                        }, prove)),
+                        Err(e) => Ok(response::Query {
+                            code: 3u32.into(),
+                            log: format!("Failed to serialize active sessions: {}", e),
+                            ..Default::default()
+                        })
+                    },
                     Err(e) => Ok(response::Query {
                         code: 5u32.into(),
                         log: format!("Storage error: {}", e),
@@ -457,4 +1319,113 @@ impl MyChainApp {
             })
         }
     }
-}
\ No newline at end of file
+}
+
+/// Attach an ICS23 proof to a query response when the client asked for one
+///
+/// Proofs are against the last committed state, so they check against the app
+/// hash in the header of block `response.height + 1`.
+fn with_proof<F>(
+    storage: &Storage,
+    request: &request::Query,
+    mut response: response::Query,
+    prove: F,
+) -> response::Query
+where
+    F: FnOnce() -> Result<StateProof>,
+{
+    if !request.prove {
+        return response;
+    }
+
+    let height = storage.get_last_height().and_then(|height| {
+        tendermint::block::Height::try_from(height).map_err(anyhow::Error::from)
+    });
+    match (height, prove()) {
+        (Ok(height), Ok(state_proof)) => {
+            response.height = height;
+            response.key = state_proof.key().to_vec().into();
+            response.proof = Some(proof::proof_ops(&state_proof));
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            response.code = 7u32.into();
+            response.log = format!("Failed to build proof: {}", e);
+            response.value = Bytes::new();
+        }
+    }
+    response
+}
+
+/// Decode a user transaction and run the checks that need no state
+fn decode_tx(tx_bytes: &[u8], chain_id: &str, games: &GameRegistry) -> Result<SignedTx, TxRejection> {
+    let signed = SignedTx::from_bytes(tx_bytes).map_err(|e| {
+        let code = match e {
+            TxError::UnsupportedVersion(_) => codes::UNSUPPORTED_VERSION,
+            TxError::UnknownKind(_) => codes::UNKNOWN_TX_KIND,
+            _ => codes::DECODE_ERROR,
+        };
+        TxRejection::new(code, format!("Failed to decode transaction: {}", e))
+    })?;
+
+    // Validate transaction format
+    let tx = &signed.tx;
+    let has_amount = !matches!(
+        tx,
+        Tx::CrashCashOut(_)
+            | Tx::LotteryOpen(_)
+            | Tx::BlackjackAction(_)
+            | Tx::MinesReveal(_)
+            | Tx::MinesCashOut(_)
+    );
+    if has_amount && tx.amount() == 0 {
+        return Err(TxRejection::new(codes::INVALID_AMOUNT, "Invalid amount: must be greater than 0"));
+    }
+    if tx.wallet() == &[0u8; 32] {
+        return Err(TxRejection::new(codes::INVALID_WALLET, "Invalid wallet: cannot be zero"));
+    }
+    match tx {
+        Tx::Deposit(deposit) => accounts::check_recipient(&deposit.wallet, &deposit.recipient)?,
+        Tx::Withdraw(withdraw) => accounts::check_destination(&withdraw.destination)?,
+        Tx::Transfer(transfer) => accounts::check_recipient(&transfer.wallet, &transfer.recipient)?,
+        tx => games.game_for(tx.kind())?.validate(tx)?,
+    }
+
+    signed.verify(chain_id).map_err(|e| {
+        TxRejection::new(codes::INVALID_SIGNATURE, format!("Invalid signature: {}", e))
+    })?;
+
+    Ok(signed)
+}
+
+/// Require `nonce` to be exactly the next one expected from the wallet, so every
+/// transaction executes at most once and in the order it was signed
+fn check_nonce(nonce: u64, expected: u64) -> Result<(), TxRejection> {
+    match nonce.cmp(&expected) {
+        Ordering::Equal => Ok(()),
+        Ordering::Less => Err(TxRejection::new(
+            codes::INVALID_NONCE,
+            format!("Stale nonce: expected {}, got {}", expected, nonce),
+        )),
+        Ordering::Greater => Err(TxRejection::new(
+            codes::INVALID_NONCE,
+            format!("Nonce too high: expected {}, got {}", expected, nonce),
+        )),
+    }
+}
+
+/// Whether a validator's precommit for the previous block is part of the commit
+fn signed_last_commit(sig_info: &BlockSignatureInfo) -> bool {
+    matches!(sig_info, BlockSignatureInfo::Flag(BlockIdFlag::Commit) | BlockSignatureInfo::LegacySigned)
+}
+
+/// Size of a transaction inside the protobuf-encoded block data, which is what
+/// CometBFT counts against `max_tx_bytes` (field tag + length varint + bytes)
+fn tx_proto_size(len: usize) -> usize {
+    let mut varint_len = 1;
+    let mut value = len >> 7;
+    while value > 0 {
+        varint_len += 1;
+        value >>= 7;
+    }
+    1 + varint_len + len
+}
@@ -0,0 +1,491 @@
+//! Lottery: the minter opens a draw, players buy tickets until the draw height,
+//! and winners are drawn at the end of that block
+//!
+//! Winning tickets are distinct ticket numbers sampled from a VRF output proven
+//! over a message fixed by the draw block, so no ticket can be bought once the
+//! randomness is known. The pot minus the house cut is shared equally between the
+//! winning tickets; what does not divide evenly stays with the house.
+//!
+//! A draw that cannot be made is refunded ticket for ticket instead, and one that
+//! cannot be refunded either is retried at the end of the next block.
+
+use anyhow::{ensure, Context, Result};
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    LotteryDraw, LotteryWinner, SignedTx, Tx, TxKind, TxLotteryOpen, TxLotteryTicket, BPS_DENOMINATOR,
+    MAX_LOTTERY_WINNERS,
+};
+use tendermint::abci::Event;
+use tracing::error;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::{VrfEngine, VrfRng};
+use crate::{accounts, params, BlockContext};
+
+/// Lottery draws opened by the minter
+pub struct Lottery;
+
+impl Game for Lottery {
+    fn id(&self) -> &'static str {
+        "lottery"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::LotteryOpen, TxKind::LotteryTicket]
+    }
+
+    fn validate(&self, tx: &Tx) -> Result<(), TxRejection> {
+        match tx {
+            Tx::LotteryOpen(open) => check_open(open),
+            _ => Ok(()),
+        }
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        match &signed.tx {
+            Tx::LotteryOpen(_) => check_operator(storage, &signed.public_key).map(|_| 0),
+            Tx::LotteryTicket(tickets) => game::stake(storage, tickets.amount),
+            tx => Ok(tx.amount()),
+        }
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        _vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::LotteryOpen(tx) => execute_open(storage, batch, &signed.public_key, block, tx, tx_hash),
+            Tx::LotteryTicket(tx) => execute_tickets(storage, batch, block, tx, tx_hash),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+
+    fn end_block(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+    ) -> Result<Vec<Event>> {
+        end_block(storage, batch, vrf_engine, block)
+    }
+}
+
+/// Require the signer of a draw opening to be the registered minter
+pub fn check_operator(storage: &Storage, public_key: &[u8; 32]) -> Result<(), TxRejection> {
+    match storage.get_minter_public_key().map_err(TxRejection::internal)? {
+        Some(minter) if &minter == public_key => Ok(()),
+        Some(_) => Err(TxRejection::new(codes::UNAUTHORIZED, "Lottery draws must be opened by the minter")),
+        None => Err(TxRejection::new(codes::UNAUTHORIZED, "Lottery is disabled: no minter registered")),
+    }
+}
+
+/// Require a ticket price and a winner count a draw can be opened with
+pub fn check_open(tx: &TxLotteryOpen) -> Result<(), TxRejection> {
+    if tx.ticket_price == 0 {
+        return Err(TxRejection::new(codes::INVALID_AMOUNT, "Invalid ticket price: must be greater than 0"));
+    }
+    if !(1..=MAX_LOTTERY_WINNERS).contains(&tx.winners) {
+        return Err(TxRejection::new(
+            codes::INVALID_DRAW,
+            format!("Invalid winner count: must be 1 to {}", MAX_LOTTERY_WINNERS),
+        ));
+    }
+    Ok(())
+}
+
+/// Prize of each winning ticket of a draw, and how many tickets win
+///
+/// The house cut is rounded down, the prize too.
+pub fn prizes(draw: &LotteryDraw) -> Result<(u64, u64)> {
+    let pot = draw.pot().context("Lottery pot overflow")?;
+    let winners = draw.tickets_sold.min(draw.winners as u64);
+    if winners == 0 {
+        return Ok((0, 0));
+    }
+    let cut = pot as u128 * draw.house_cut_bps.min(BPS_DENOMINATOR) as u128 / BPS_DENOMINATOR as u128;
+    Ok(((pot - cut as u64) / winners, winners))
+}
+
+/// Open a draw selling tickets until `draw_height`
+pub fn execute_open(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    public_key: &[u8; 32],
+    block: &BlockContext,
+    tx: &TxLotteryOpen,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    check_operator(storage, public_key)?;
+    check_open(tx)?;
+    if tx.draw_height <= block.height {
+        return Err(TxRejection::new(
+            codes::INVALID_DRAW,
+            format!("Invalid draw height: must be after {}", block.height),
+        ));
+    }
+
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    let latest = storage.get_pending_latest_lottery_draw_id(batch).map_err(TxRejection::internal)?;
+    let draw = LotteryDraw {
+        id: latest.map_or(1, |id| id + 1),
+        ticket_price: tx.ticket_price,
+        draw_height: tx.draw_height,
+        winners: tx.winners,
+        house_cut_bps: chain_params.house_edge_bps,
+        tickets_sold: 0,
+        buyers: Vec::new(),
+        drawn: false,
+        vrf_message: Vec::new(),
+        vrf_proof: Vec::new(),
+        vrf_output: Vec::new(),
+    };
+    storage.store_lottery_draw(&draw, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "lottery_open".to_string(),
+        attributes: vec![
+            ("draw".to_string(), draw.id.to_string()).into(),
+            ("ticket_price".to_string(), draw.ticket_price.to_string()).into(),
+            ("draw_height".to_string(), draw.draw_height.to_string()).into(),
+            ("winners".to_string(), draw.winners.to_string()).into(),
+            ("house_cut_bps".to_string(), draw.house_cut_bps.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(tx_hash)).into(),
+        ],
+    })
+}
+
+/// Buy tickets for a draw that has not reached its draw height
+pub fn execute_tickets(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    block: &BlockContext,
+    tx: &TxLotteryTicket,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+
+    let mut draw = storage.get_pending_lottery_draw(tx.draw, batch)
+        .map_err(TxRejection::internal)?
+        .ok_or_else(|| TxRejection::new(codes::ROUND_CLOSED, format!("Unknown lottery draw {}", tx.draw)))?;
+    if block.height >= draw.draw_height {
+        return Err(TxRejection::new(
+            codes::ROUND_CLOSED,
+            format!("Lottery draw {} stopped selling tickets at height {}", draw.id, draw.draw_height),
+        ));
+    }
+    if !tx.amount.is_multiple_of(draw.ticket_price) {
+        return Err(TxRejection::new(
+            codes::INVALID_AMOUNT,
+            format!("Invalid amount: must be a multiple of the ticket price {}", draw.ticket_price),
+        ));
+    }
+    let tickets = tx.amount / draw.ticket_price;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    // The pot must stay payable in full
+    let overflow = || TxRejection::new(codes::BALANCE_OVERFLOW, "Lottery pot overflow");
+    draw.tickets_sold = draw.tickets_sold.checked_add(tickets).ok_or_else(overflow)?;
+    draw.pot().ok_or_else(overflow)?;
+    let held = storage.get_pending_lottery_tickets(draw.id, &tx.wallet, batch).map_err(TxRejection::internal)?;
+    if held == 0 {
+        draw.buyers.push(tx.wallet);
+    }
+    let held = held + tickets;
+
+    storage.store_lottery_draw(&draw, batch).map_err(TxRejection::internal)?;
+    storage.set_lottery_tickets(draw.id, &tx.wallet, held, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "lottery_tickets".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(tx.wallet)).into(),
+            ("draw".to_string(), draw.id.to_string()).into(),
+            ("tickets".to_string(), tickets.to_string()).into(),
+            ("held".to_string(), held.to_string()).into(),
+            ("amount".to_string(), tx.amount.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(tx_hash)).into(),
+        ],
+    })
+}
+
+/// Make the draws due at this block and pay their winners
+///
+/// A draw that fails is refunded instead, and one that cannot be refunded either
+/// is due again at the next block. Each draw's writes are kept only if it settles.
+pub fn end_block(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for id in storage.get_pending_lottery_draws_due(block.height, batch)? {
+        let drawn = batch.atomically(|batch| draw(storage, batch, vrf_engine, block, id));
+        let refunded = drawn.or_else(|e| {
+            error!("Failed to make lottery draw {}: {:#}", id, e);
+            batch.atomically(|batch| refund(storage, batch, id))
+        });
+        match refunded {
+            Ok(draw_events) => events.extend(draw_events),
+            Err(e) => {
+                error!("Failed to refund lottery draw {}: {:#}", id, e);
+                storage.schedule_lottery_draw(id, block.height + 1, batch)?;
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Draw the winners of a draw and pay them
+fn draw(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    id: u64,
+) -> Result<Vec<Event>> {
+    let Some(mut draw) = storage.get_pending_lottery_draw(id, batch)? else {
+        anyhow::bail!("Missing lottery draw {}", id);
+    };
+    if draw.drawn {
+        return Ok(vec![]);
+    }
+
+    let (prize, winner_count) = prizes(&draw)?;
+    let vrf_message = VrfEngine::compute_lottery_message(&block.chain_id, draw.id, block.height, &block.block_random);
+    let (vrf_output, vrf_proof) = vrf_engine.prove(&vrf_message)?;
+    let ticket_count = usize::try_from(draw.tickets_sold).context("Too many lottery tickets")?;
+    let tickets = VrfRng::new(&vrf_output).sample(ticket_count, winner_count as usize);
+
+    // Buyers' tickets are numbered consecutively in buying order
+    let mut holdings = Vec::with_capacity(draw.buyers.len());
+    let mut next = 0u64;
+    for wallet in &draw.buyers {
+        next += storage.get_pending_lottery_tickets(draw.id, wallet, batch)?;
+        holdings.push((next, *wallet));
+    }
+    ensure!(next == draw.tickets_sold, "Tickets of lottery draw {} do not add up", draw.id);
+
+    let mut winners = Vec::with_capacity(tickets.len());
+    for ticket in tickets {
+        let ticket = ticket as u64;
+        let (_, wallet) = holdings[holdings.partition_point(|(end, _)| *end <= ticket)];
+
+        let balance = storage.get_pending_balance(&wallet, batch)?;
+        storage.set_balance(&wallet, accounts::credit(balance, prize)?, batch)?;
+        winners.push(LotteryWinner { ticket, wallet, prize });
+    }
+
+    draw.drawn = true;
+    draw.vrf_message = vrf_message;
+    draw.vrf_proof = vrf_proof;
+    draw.vrf_output = vrf_output;
+    storage.store_lottery_draw(&draw, batch)?;
+    storage.store_lottery_winners(draw.id, &winners, batch)?;
+
+    let winning_tickets: Vec<String> = winners.iter().map(|winner| winner.ticket.to_string()).collect();
+    let mut events = vec![Event {
+        kind: "lottery_draw".to_string(),
+        attributes: vec![
+            ("draw".to_string(), draw.id.to_string()).into(),
+            ("tickets_sold".to_string(), draw.tickets_sold.to_string()).into(),
+            ("winning_tickets".to_string(), winning_tickets.join(",")).into(),
+            ("prize".to_string(), prize.to_string()).into(),
+            ("vrf_proof".to_string(), hex::encode(&draw.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&draw.vrf_output)).into(),
+        ],
+    }];
+    events.extend(winners.iter().map(|winner| Event {
+        kind: "lottery_win".to_string(),
+        attributes: vec![
+            ("draw".to_string(), draw.id.to_string()).into(),
+            ("ticket".to_string(), winner.ticket.to_string()).into(),
+            ("wallet".to_string(), hex::encode(winner.wallet)).into(),
+            ("prize".to_string(), winner.prize.to_string()).into(),
+        ],
+    }));
+    Ok(events)
+}
+
+/// Settle a draw that could not be made by paying every ticket back, with no winners
+fn refund(storage: &Storage, batch: &mut StorageBatch, id: u64) -> Result<Vec<Event>> {
+    let Some(mut draw) = storage.get_pending_lottery_draw(id, batch)? else {
+        anyhow::bail!("Missing lottery draw {}", id);
+    };
+    let mut refunded = 0u64;
+    for wallet in &draw.buyers {
+        let tickets = storage.get_pending_lottery_tickets(draw.id, wallet, batch)?;
+        let amount = tickets.checked_mul(draw.ticket_price).context("Lottery refund overflow")?;
+        let balance = storage.get_pending_balance(wallet, batch)?;
+        storage.set_balance(wallet, accounts::credit(balance, amount)?, batch)?;
+        refunded = refunded.checked_add(amount).context("Lottery refund overflow")?;
+    }
+
+    draw.drawn = true;
+    storage.store_lottery_draw(&draw, batch)?;
+    storage.store_lottery_winners(draw.id, &[], batch)?;
+
+    Ok(vec![Event {
+        kind: "lottery_refund".to_string(),
+        attributes: vec![
+            ("draw".to_string(), draw.id.to_string()).into(),
+            ("tickets_sold".to_string(), draw.tickets_sold.to_string()).into(),
+            ("refunded".to_string(), refunded.to_string()).into(),
+        ],
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+    use mychain_types::ChainParams;
+
+    #[test]
+    fn test_prizes_split_pot_after_cut() -> Result<()> {
+        let draw = LotteryDraw {
+            id: 1,
+            ticket_price: 10,
+            draw_height: 20,
+            winners: 3,
+            house_cut_bps: 1_000,
+            tickets_sold: 10,
+            buyers: Vec::new(),
+            drawn: false,
+            vrf_message: Vec::new(),
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+        };
+        // 100 pot, 10 cut, 90 shared by 3
+        assert_eq!(prizes(&draw)?, (30, 3));
+        // Fewer tickets than winners: every ticket wins
+        assert_eq!(prizes(&LotteryDraw { tickets_sold: 2, ..draw.clone() })?, (9, 2));
+        assert_eq!(prizes(&LotteryDraw { tickets_sold: 0, ..draw.clone() })?, (0, 0));
+        assert!(prizes(&LotteryDraw { tickets_sold: u64::MAX, ..draw }).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_draw_pays_winning_tickets() -> Result<()> {
+        let minter = [5u8; 32];
+        let wallets = [[1u8; 32], [2u8; 32]];
+        let (_temp_dir, storage, vrf_engine) = setup(&wallets, 1_000)?;
+        let mut batch = storage.batch();
+        storage.set_minter_public_key(&minter, &mut batch)?;
+        storage.set_params(&ChainParams { house_edge_bps: 1_000, ..Default::default() }, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        // Only the minter opens draws, and only for a later height
+        let open = TxLotteryOpen { wallet: [9u8; 32], ticket_price: 10, draw_height: 12, winners: 2, nonce: 0 };
+        let mut batch = storage.batch();
+        let rejection = execute_open(&storage, &mut batch, &[8u8; 32], &block(10), &open, [0u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::UNAUTHORIZED);
+        let rejection = execute_open(&storage, &mut batch, &minter, &block(12), &open, [0u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_DRAW);
+        execute_open(&storage, &mut batch, &minter, &block(10), &open, [0u8; 32])?;
+
+        // 3 tickets for the first wallet, 1 for the second
+        let tx = TxLotteryTicket { wallet: wallets[0], draw: 1, amount: 20, nonce: 0 };
+        execute_tickets(&storage, &mut batch, &block(10), &tx, [1u8; 32])?;
+        let tx = TxLotteryTicket { wallet: wallets[1], draw: 1, amount: 10, nonce: 0 };
+        execute_tickets(&storage, &mut batch, &block(11), &tx, [2u8; 32])?;
+        let tx = TxLotteryTicket { wallet: wallets[0], draw: 1, amount: 10, nonce: 1 };
+        execute_tickets(&storage, &mut batch, &block(11), &tx, [3u8; 32])?;
+        let uneven = TxLotteryTicket { amount: 15, ..tx.clone() };
+        let rejection = execute_tickets(&storage, &mut batch, &block(11), &uneven, [4u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_AMOUNT);
+        let rejection = execute_tickets(&storage, &mut batch, &block(12), &tx, [4u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::ROUND_CLOSED);
+
+        // Nothing is drawn before the draw height
+        assert!(end_block(&storage, &mut batch, &vrf_engine, &block(11))?.is_empty());
+        let events = end_block(&storage, &mut batch, &vrf_engine, &block(12))?;
+        storage.apply_batch(batch)?;
+        assert_eq!(events.len(), 3);
+
+        let draw = storage.get_lottery_draw(1)?.unwrap();
+        assert!(draw.drawn);
+        assert_eq!(draw.tickets_sold, 4);
+        assert_eq!(draw.buyers, wallets.to_vec());
+        assert!(VrfEngine::verify(&vrf_engine.public_key(), &draw.vrf_message, &draw.vrf_proof, &draw.vrf_output)?);
+
+        // 40 pot, 4 cut, 18 per winning ticket; tickets 0-2 are the first wallet's
+        let winners = storage.get_lottery_winners(1)?.unwrap();
+        let expected: Vec<u64> = VrfRng::new(&draw.vrf_output).sample(4, 2).into_iter().map(|t| t as u64).collect();
+        assert_eq!(winners.iter().map(|winner| winner.ticket).collect::<Vec<_>>(), expected);
+        let mut balances = [970, 990];
+        for winner in &winners {
+            assert_eq!(winner.prize, 18);
+            assert_eq!(winner.wallet, wallets[usize::from(winner.ticket == 3)]);
+            balances[usize::from(winner.ticket == 3)] += 18;
+        }
+        assert_eq!(storage.get_balance(&wallets[0])?, balances[0]);
+        assert_eq!(storage.get_balance(&wallets[1])?, balances[1]);
+
+        // A drawn draw is not drawn again
+        let mut batch = storage.batch();
+        assert!(end_block(&storage, &mut batch, &vrf_engine, &block(12))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_draws_are_refunded_or_retried() -> Result<()> {
+        let minter = [5u8; 32];
+        let wallets = [[1u8; 32], [2u8; 32]];
+        let (_temp_dir, storage, vrf_engine) = setup(&wallets, 1_000)?;
+        let mut batch = storage.batch();
+        storage.set_minter_public_key(&minter, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        // Two draws at the same height, one ticket each
+        let mut batch = storage.batch();
+        let open = TxLotteryOpen { wallet: minter, ticket_price: 10, draw_height: 12, winners: 1, nonce: 0 };
+        execute_open(&storage, &mut batch, &minter, &block(10), &open, [0u8; 32])?;
+        execute_open(&storage, &mut batch, &minter, &block(10), &TxLotteryOpen { nonce: 1, ..open }, [1u8; 32])?;
+        for (i, wallet) in wallets.iter().enumerate() {
+            let tx = TxLotteryTicket { wallet: *wallet, draw: i as u64 + 1, amount: 10, nonce: 0 };
+            execute_tickets(&storage, &mut batch, &block(11), &tx, [i as u8 + 2; 32])?;
+        }
+
+        // The first draw's tickets do not add up; the second's prize overflows its winner's balance
+        let mut draw = storage.get_pending_lottery_draw(1, &batch)?.unwrap();
+        draw.tickets_sold = 2;
+        storage.store_lottery_draw(&draw, &mut batch)?;
+        storage.set_balance(&wallets[1], u64::MAX - 5, &mut batch)?;
+        let events = end_block(&storage, &mut batch, &vrf_engine, &block(12))?;
+        storage.apply_batch(batch)?;
+
+        // The first is refunded, the second left for the next block untouched
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, "lottery_refund");
+        assert!(storage.get_lottery_draw(1)?.unwrap().drawn);
+        assert_eq!(storage.get_lottery_winners(1)?, Some(Vec::new()));
+        assert_eq!(storage.get_balance(&wallets[0])?, 1_000);
+        assert!(!storage.get_lottery_draw(2)?.unwrap().drawn);
+        assert_eq!(storage.get_lottery_winners(2)?, None);
+        assert_eq!(storage.get_balance(&wallets[1])?, u64::MAX - 5);
+        assert_eq!(storage.get_lottery_draws_due(13)?, vec![2]);
+
+        let mut batch = storage.batch();
+        storage.set_balance(&wallets[1], 990, &mut batch)?;
+        let events = end_block(&storage, &mut batch, &vrf_engine, &block(13))?;
+        storage.apply_batch(batch)?;
+        assert_eq!(events.iter().map(|event| event.kind.as_str()).collect::<Vec<_>>(), ["lottery_draw", "lottery_win"]);
+        assert_eq!(storage.get_balance(&wallets[1])?, 1_000);
+        assert_eq!(storage.get_lottery_draw(2)?.unwrap().draw_height, 12);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+/// Effects of transactions accepted into the mempool but not yet committed
+///
+/// CheckTx validates against the committed state with these effects applied, so
+/// a wallet can have several transactions in flight with consecutive nonces,
+/// and cannot put more stakes in flight than its balance covers (payouts are not
+/// known before execution, so they are never counted).
+/// The state is reset on Commit; CometBFT then rechecks the remaining mempool
+/// transactions in order (`CheckTxKind::Recheck`), which rebuilds it on top of
+/// the new committed state and evicts the ones that no longer apply.
+#[derive(Debug, Default)]
+pub struct CheckState {
+    nonces: HashMap<[u8; 32], u64>,
+    spent: HashMap<[u8; 32], u64>,
+}
+
+impl CheckState {
+    /// Next nonce expected from a wallet, given the committed one
+    pub fn next_nonce(&self, wallet: &[u8; 32], committed: u64) -> u64 {
+        self.nonces.get(wallet).copied().unwrap_or(committed).max(committed)
+    }
+
+    /// Record that the mempool holds a transaction with `nonce` from a wallet
+    pub fn accept_nonce(&mut self, wallet: [u8; 32], nonce: u64) {
+        self.nonces.insert(wallet, nonce + 1);
+    }
+
+    /// Total stakes of a wallet's transactions in the mempool
+    pub fn spent(&self, wallet: &[u8; 32]) -> u64 {
+        self.spent.get(wallet).copied().unwrap_or(0)
+    }
+
+    /// Record a stake put in flight by a wallet
+    pub fn accept_spend(&mut self, wallet: [u8; 32], amount: u64) {
+        let spent = self.spent.entry(wallet).or_default();
+        *spent = spent.saturating_add(amount);
+    }
+
+    /// Drop all pending effects, after the mempool's transactions were committed
+    pub fn reset(&mut self) {
+        self.nonces.clear();
+        self.spent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_state_nonces() {
+        let mut state = CheckState::default();
+        let wallet = [1u8; 32];
+
+        assert_eq!(state.next_nonce(&wallet, 5), 5);
+        state.accept_nonce(wallet, 5);
+        state.accept_nonce(wallet, 6);
+        assert_eq!(state.next_nonce(&wallet, 5), 7);
+
+        // Committed state moving past the pending nonces wins
+        assert_eq!(state.next_nonce(&wallet, 9), 9);
+
+        state.reset();
+        assert_eq!(state.next_nonce(&wallet, 7), 7);
+    }
+
+    #[test]
+    fn test_check_state_spent() {
+        let mut state = CheckState::default();
+        let wallet = [1u8; 32];
+
+        assert_eq!(state.spent(&wallet), 0);
+        state.accept_spend(wallet, 30);
+        state.accept_spend(wallet, 20);
+        assert_eq!(state.spent(&wallet), 50);
+        assert_eq!(state.spent(&[2u8; 32]), 0);
+
+        state.reset();
+        assert_eq!(state.spent(&wallet), 0);
+    }
+}
@@ -0,0 +1,462 @@
+//! Mines: the player picks a board size and a number of mines, then reveals tiles
+//! one transaction at a time
+//!
+//! Every gem raises the payout to the fair odds of finding that many gems, less
+//! the house edge; a mine loses the stake. Cashing out, or finding every gem, pays
+//! the gems found so far. The mines are placed by a VRF output proven when the
+//! game opens; while it is in play only the commitment to that output is stored,
+//! and each move proves the opening message again to check tiles against it.
+//!
+//! The stake is held by a [`session`](crate::session): a game left alone until it
+//! times out cashes out the gems found, or returns the stake if none were.
+
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    BetKind, BetRecord, ChainParams, GameSession, MinesGame, SignedTx, Tx, TxKind, TxMinesCashOut, TxMinesOpen,
+    TxMinesReveal, BPS_DENOMINATOR, MAX_MINES_GRID, MIN_MINES_GRID,
+};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::{VrfEngine, VrfRng};
+use crate::{accounts, params, session, BlockContext};
+
+/// Session state before the first tile is revealed
+const OPEN: &str = "open";
+
+/// Session state once a gem is found, which can be cashed out
+const PLAYING: &str = "playing";
+
+/// The mines game
+pub struct Mines;
+
+impl Game for Mines {
+    fn id(&self) -> &'static str {
+        "mines"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::MinesOpen, TxKind::MinesReveal, TxKind::MinesCashOut]
+    }
+
+    fn validate(&self, tx: &Tx) -> Result<(), TxRejection> {
+        match tx {
+            Tx::MinesOpen(open) => check_board(open.grid, open.mines),
+            _ => Ok(()),
+        }
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        match &signed.tx {
+            Tx::MinesOpen(open) => game::stake(storage, open.amount),
+            tx => Ok(tx.amount()),
+        }
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::MinesOpen(tx) => execute_open(storage, batch, vrf_engine, block, tx, tx_hash),
+            Tx::MinesReveal(tx) => execute_reveal(storage, batch, vrf_engine, block, tx),
+            Tx::MinesCashOut(tx) => execute_cash_out(storage, batch, vrf_engine, block, tx),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+
+    fn session_actions(&self, state: &str) -> &'static [TxKind] {
+        match state {
+            OPEN => &[TxKind::MinesReveal],
+            PLAYING => &[TxKind::MinesReveal, TxKind::MinesCashOut],
+            _ => &[],
+        }
+    }
+
+    fn time_out(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        session: GameSession,
+    ) -> anyhow::Result<Vec<Event>> {
+        let game = game_in_play(storage, batch, &session.wallet)?;
+        let vrf = prove_board(vrf_engine, &game)?;
+        let payout = match game.revealed.len() {
+            0 => game.amount,
+            gems => {
+                let chain_params = params::load(storage)?;
+                payout(&chain_params, game.amount, game.grid, game.mines, gems)?
+            }
+        };
+        Ok(vec![finish(storage, batch, block, (game, session), vrf, payout, "timeout")?])
+    }
+}
+
+/// Require a board size in range and a mine count leaving at least one gem
+pub fn check_board(grid: u8, mines: u8) -> Result<(), TxRejection> {
+    if !(MIN_MINES_GRID..=MAX_MINES_GRID).contains(&grid) {
+        return Err(TxRejection::new(
+            codes::INVALID_BET,
+            format!("Invalid grid: must be {} to {}", MIN_MINES_GRID, MAX_MINES_GRID),
+        ));
+    }
+    if mines == 0 || mines >= grid * grid {
+        return Err(TxRejection::new(
+            codes::INVALID_BET,
+            format!("Invalid mine count: must be 1 to {}", grid * grid - 1),
+        ));
+    }
+    Ok(())
+}
+
+/// Mine tiles of a board, in ascending order
+pub fn board(vrf_output: &[u8], grid: u8, mines: u8) -> Vec<u8> {
+    let tiles = (grid as usize) * (grid as usize);
+    let mut board: Vec<u8> = VrfRng::new(vrf_output)
+        .sample(tiles, mines as usize)
+        .into_iter()
+        .map(|tile| tile as u8)
+        .collect();
+    board.sort_unstable();
+    board
+}
+
+/// Payout of a cash-out after finding `gems` gems, rounded down
+///
+/// The fair multiplier is the inverse of the chance of finding them,
+/// `C(tiles, gems) / C(tiles - mines, gems)`.
+pub fn payout(params: &ChainParams, amount: u64, grid: u8, mines: u8, gems: usize) -> Result<u64, TxRejection> {
+    check_board(grid, mines)?;
+    let tiles = grid as u128 * grid as u128;
+    let net_bps = BPS_DENOMINATOR.saturating_sub(params.house_edge_bps) as u128;
+    let payout = amount as u128 * binomial(tiles, gems as u128) * net_bps
+        / (binomial(tiles - mines as u128, gems as u128) * BPS_DENOMINATOR as u128);
+    u64::try_from(payout).map_err(|_| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+/// Open a game: debit the stake and place the mines with the VRF
+pub fn execute_open(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxMinesOpen,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+    check_board(tx.grid, tx.mines)?;
+    let session = session::open(storage, batch, block, &Mines, &tx.wallet, tx.amount, OPEN)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    // The most a stake can return: a cash-out once every gem is found
+    let gems = (tx.grid * tx.grid - tx.mines) as usize;
+    payout(&chain_params, tx.amount, tx.grid, tx.mines, gems)?;
+
+    let vrf_message = VrfEngine::compute_mines_message(
+        &block.chain_id,
+        block.height,
+        &block.block_random,
+        &tx_hash,
+        &tx.wallet,
+        tx.nonce,
+    );
+    let (vrf_output, _) = vrf_engine.prove(&vrf_message).map_err(TxRejection::internal)?;
+    let game = MinesGame {
+        wallet: tx.wallet,
+        height: block.height,
+        tx_hash,
+        nonce: tx.nonce,
+        amount: tx.amount,
+        grid: tx.grid,
+        mines: tx.mines,
+        vrf_message,
+        commitment: *blake3::hash(&vrf_output).as_bytes(),
+        revealed: Vec::new(),
+        finished: false,
+        board: Vec::new(),
+        vrf_proof: Vec::new(),
+        vrf_output: Vec::new(),
+    };
+
+    storage.store_game_session(&session, batch).map_err(TxRejection::internal)?;
+    storage.store_mines_game(&game, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+    Ok(game_event(&game, &session, "open", None))
+}
+
+/// Reveal a tile: a mine ends the game, the last gem cashes it out
+pub fn execute_reveal(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxMinesReveal,
+) -> Result<Event, TxRejection> {
+    let mut session = session::in_play(storage, batch, &Mines, &tx.wallet, TxKind::MinesReveal)?;
+    let mut game = game_in_play(storage, batch, &tx.wallet)?;
+    if tx.tile >= game.grid * game.grid {
+        return Err(TxRejection::new(
+            codes::INVALID_MOVE,
+            format!("Invalid tile: must be below {}", game.grid * game.grid),
+        ));
+    }
+    if game.revealed.contains(&tx.tile) {
+        return Err(TxRejection::new(codes::INVALID_MOVE, format!("Tile {} is already revealed", tx.tile)));
+    }
+
+    let (vrf_output, vrf_proof) = prove_board(vrf_engine, &game)?;
+    let board = board(&vrf_output, game.grid, game.mines);
+    game.revealed.push(tx.tile);
+
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    let gems = game.revealed.len();
+    if board.contains(&tx.tile) {
+        return finish(storage, batch, block, (game, session), (vrf_output, vrf_proof), 0, "reveal");
+    }
+    if gems == (game.grid * game.grid - game.mines) as usize {
+        let payout = payout(&chain_params, game.amount, game.grid, game.mines, gems)?;
+        return finish(storage, batch, block, (game, session), (vrf_output, vrf_proof), payout, "reveal");
+    }
+
+    let cash_out = payout(&chain_params, game.amount, game.grid, game.mines, gems)?;
+    session::advance(storage, block, &mut session, PLAYING)?;
+    storage.store_game_session(&session, batch).map_err(TxRejection::internal)?;
+    storage.store_mines_game(&game, batch).map_err(TxRejection::internal)?;
+    Ok(game_event(&game, &session, "reveal", Some(cash_out)))
+}
+
+/// Cash out the gems found so far
+pub fn execute_cash_out(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxMinesCashOut,
+) -> Result<Event, TxRejection> {
+    // Cashing out is only allowed once a gem is found
+    let session = session::in_play(storage, batch, &Mines, &tx.wallet, TxKind::MinesCashOut)?;
+    let game = game_in_play(storage, batch, &tx.wallet)?;
+
+    let vrf = prove_board(vrf_engine, &game)?;
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    let payout = payout(&chain_params, game.amount, game.grid, game.mines, game.revealed.len())?;
+    finish(storage, batch, block, (game, session), vrf, payout, "cash_out")
+}
+
+/// The wallet's game, which is in play while its session is
+fn game_in_play(storage: &Storage, batch: &StorageBatch, wallet: &[u8; 32]) -> Result<MinesGame, TxRejection> {
+    storage.get_pending_mines_game(wallet, batch)
+        .map_err(TxRejection::internal)?
+        .filter(|game| !game.finished)
+        .ok_or_else(|| TxRejection::internal(format!("Mines session of {} has no game in play", hex::encode(wallet))))
+}
+
+/// Prove the opening message again; the output must be the one committed to
+fn prove_board(vrf_engine: &VrfEngine, game: &MinesGame) -> Result<(Vec<u8>, Vec<u8>), TxRejection> {
+    let (vrf_output, vrf_proof) = vrf_engine.prove(&game.vrf_message).map_err(TxRejection::internal)?;
+    if *blake3::hash(&vrf_output).as_bytes() != game.commitment {
+        return Err(TxRejection::internal(format!(
+            "VRF output of the mines game of {} does not match its commitment",
+            hex::encode(game.wallet)
+        )));
+    }
+    Ok((vrf_output, vrf_proof))
+}
+
+/// End a game: pay the player, settle the session and publish the board
+fn finish(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    block: &BlockContext,
+    (mut game, mut session): (MinesGame, GameSession),
+    (vrf_output, vrf_proof): (Vec<u8>, Vec<u8>),
+    payout: u64,
+    action: &str,
+) -> Result<Event, TxRejection> {
+    let balance = storage.get_pending_balance(&game.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::credit(balance, payout)?;
+
+    game.finished = true;
+    game.board = board(&vrf_output, game.grid, game.mines);
+    game.vrf_proof = vrf_proof;
+    game.vrf_output = vrf_output;
+    let record = BetRecord {
+        wallet: game.wallet,
+        amount: game.amount,
+        kind: BetKind::Mines { grid: game.grid, revealed: game.revealed.clone(), board: game.board.clone() },
+        nonce: game.nonce,
+        vrf_message: game.vrf_message.clone(),
+        vrf_proof: game.vrf_proof.clone(),
+        vrf_output: game.vrf_output.clone(),
+        won: payout > game.amount,
+        payout,
+        height: block.height,
+        tx_hash: game.tx_hash,
+    };
+
+    session::settle(block, &mut session, payout);
+
+    storage.store_bet(&record.tx_hash, &record, batch).map_err(TxRejection::internal)?;
+    storage.store_game_session(&session, batch).map_err(TxRejection::internal)?;
+    storage.store_mines_game(&game, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&game.wallet, balance, batch).map_err(TxRejection::internal)?;
+    Ok(game_event(&game, &session, action, Some(payout)))
+}
+
+/// Event of a move, with the current cash-out value, or the payout once the game ended
+fn game_event(game: &MinesGame, session: &GameSession, action: &str, payout: Option<u64>) -> Event {
+    let tiles = |tiles: &[u8]| tiles.iter().map(u8::to_string).collect::<Vec<_>>().join(",");
+    let mut attributes = vec![
+        ("wallet".to_string(), hex::encode(game.wallet)).into(),
+        ("session".to_string(), session.id.to_string()).into(),
+        ("action".to_string(), action.to_string()).into(),
+        ("grid".to_string(), game.grid.to_string()).into(),
+        ("mines".to_string(), game.mines.to_string()).into(),
+        ("revealed".to_string(), tiles(&game.revealed)).into(),
+        ("finished".to_string(), game.finished.to_string()).into(),
+        ("commitment".to_string(), hex::encode(game.commitment)).into(),
+        ("tx_hash".to_string(), hex::encode(game.tx_hash)).into(),
+    ];
+    if !game.finished {
+        attributes.push(("expires_at".to_string(), session.expires_at.to_string()).into());
+    }
+    if let Some(payout) = payout {
+        let name = if game.finished { "payout" } else { "cash_out_value" };
+        attributes.push((name.to_string(), payout.to_string()).into());
+    }
+    if game.finished {
+        attributes.extend([
+            ("board".to_string(), tiles(&game.board)).into(),
+            ("vrf_proof".to_string(), hex::encode(&game.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&game.vrf_output)).into(),
+        ]);
+    }
+    Event { kind: "mines".to_string(), attributes }
+}
+
+/// `n` choose `k`, exact for the board sizes allowed
+fn binomial(n: u128, k: u128) -> u128 {
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+
+    #[test]
+    fn test_check_board_and_payout() {
+        assert!(check_board(5, 24).is_ok());
+        assert_eq!(check_board(5, 25).unwrap_err().code, codes::INVALID_BET);
+        assert_eq!(check_board(5, 0).unwrap_err().code, codes::INVALID_BET);
+        assert_eq!(check_board(MAX_MINES_GRID + 1, 1).unwrap_err().code, codes::INVALID_BET);
+
+        // 1 mine in 4 tiles: 4/3 after one gem, 4 after all three
+        let fair = ChainParams::default();
+        assert_eq!(payout(&fair, 300, 2, 1, 1), Ok(400));
+        assert_eq!(payout(&fair, 300, 2, 1, 3), Ok(1_200));
+        // 24 mines in 25 tiles: 25x for the only gem, less a 1% edge
+        let params = ChainParams { house_edge_bps: 100, ..Default::default() };
+        assert_eq!(payout(&params, 100, 5, 24, 1), Ok(2_475));
+        // 12 mines in 25 tiles pays C(25, 13) for all 13 gems
+        assert_eq!(payout(&fair, 1, 5, 12, 13), Ok(5_200_300));
+        assert_eq!(payout(&fair, u64::MAX, 5, 12, 13).unwrap_err().code, codes::BALANCE_OVERFLOW);
+    }
+
+    #[test]
+    fn test_board_is_committed_at_open() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let open = TxMinesOpen { wallet, amount: 100, grid: 3, mines: 2, nonce: 0 };
+        let mut batch = storage.batch();
+        execute_open(&storage, &mut batch, &vrf_engine, &block(10), &open, [9u8; 32])?;
+        storage.apply_batch(batch)?;
+
+        // Nothing about the board is stored while the game is in play
+        let game = storage.get_mines_game(&wallet)?.unwrap();
+        assert!(game.board.is_empty() && game.vrf_output.is_empty());
+        let mut batch = storage.batch();
+        let cash_out = TxMinesCashOut { wallet, nonce: 1 };
+        let rejection = execute_cash_out(&storage, &mut batch, &vrf_engine, &block(11), &cash_out).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_MOVE);
+
+        // Reveal tiles in order across blocks until the game ends
+        let mut tile = 0;
+        loop {
+            let reveal = TxMinesReveal { wallet, tile, nonce: 1 };
+            execute_reveal(&storage, &mut batch, &vrf_engine, &block(11 + tile as u64), &reveal)?;
+            storage.apply_batch(batch)?;
+            batch = storage.batch();
+            let game = storage.get_mines_game(&wallet)?.unwrap();
+            if game.finished {
+                break;
+            }
+            let reveal = TxMinesReveal { wallet, tile, nonce: 2 };
+            let rejection = execute_reveal(&storage, &mut batch, &vrf_engine, &block(11), &reveal).unwrap_err();
+            assert_eq!(rejection.code, codes::INVALID_MOVE);
+            tile += 1;
+        }
+
+        // The published board is the one the VRF output places, and decided the game
+        let game = storage.get_mines_game(&wallet)?.unwrap();
+        assert!(VrfEngine::verify(&vrf_engine.public_key(), &game.vrf_message, &game.vrf_proof, &game.vrf_output)?);
+        assert_eq!(*blake3::hash(&game.vrf_output).as_bytes(), game.commitment);
+        assert_eq!(game.board, board(&game.vrf_output, 3, 2));
+        let record = storage.get_bet(&[9u8; 32])?.unwrap();
+        let hit_mine = game.board.contains(&tile);
+        assert_eq!(game.revealed, (0..=tile).collect::<Vec<_>>());
+        if hit_mine {
+            assert_eq!(record.payout, 0);
+        } else {
+            assert_eq!(game.revealed.len(), 7);
+            assert_eq!(record.payout, payout(&ChainParams::default(), 100, 3, 2, 7)?);
+        }
+        assert_eq!(storage.get_balance(&wallet)?, 900 + record.payout);
+
+        // The next game can open once this one ended
+        let mut batch = storage.batch();
+        execute_open(&storage, &mut batch, &vrf_engine, &block(30), &open, [8u8; 32])?;
+        let rejection = execute_open(&storage, &mut batch, &vrf_engine, &block(30), &open, [7u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_MOVE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_game_times_out_with_its_stake() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let mut batch = storage.batch();
+        storage.set_params(&ChainParams { session_timeout_blocks: 3, ..Default::default() }, &mut batch)?;
+        storage.apply_batch(batch)?;
+        let open = TxMinesOpen { wallet, amount: 100, grid: 3, mines: 2, nonce: 0 };
+        let mut batch = storage.batch();
+        execute_open(&storage, &mut batch, &vrf_engine, &block(10), &open, [9u8; 32])?;
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_balance(&wallet)?, 900);
+
+        // No tile was revealed, so the stake comes back and the board is published
+        let mut batch = storage.batch();
+        let events = session::end_block(&storage, &mut batch, &vrf_engine, &block(13), &game::GameRegistry::builtin())?;
+        storage.apply_batch(batch)?;
+        assert_eq!(events.len(), 2);
+        let game = storage.get_mines_game(&wallet)?.unwrap();
+        assert!(game.finished);
+        assert_eq!(game.board, board(&game.vrf_output, 3, 2));
+        assert_eq!(storage.get_bet(&[9u8; 32])?.unwrap().payout, 100);
+        assert_eq!(storage.get_balance(&wallet)?, 1_000);
+        assert!(storage.get_active_sessions(&wallet)?.is_empty());
+        Ok(())
+    }
+}
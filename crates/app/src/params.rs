@@ -0,0 +1,169 @@
+//! Chain parameters: checks at genesis, and the bet limits and payouts they set
+//!
+//! Payouts are computed in u128 and rounded down, so the house edge is never
+//! rounded in the player's favour.
+
+use anyhow::{ensure, Context, Result};
+use mychain_storage::Storage;
+use mychain_types::{ChainParams, BPS_DENOMINATOR};
+
+use crate::codes::{self, TxRejection};
+use crate::{plinko, slots};
+
+/// Check parameters read from genesis
+pub fn validate(params: &ChainParams) -> Result<()> {
+    ensure!(
+        params.house_edge_bps < BPS_DENOMINATOR,
+        "House edge must be below {} bps, got {}",
+        BPS_DENOMINATOR,
+        params.house_edge_bps
+    );
+    ensure!(params.min_bet > 0, "Minimum bet must be positive");
+    ensure!(
+        params.min_bet <= params.max_bet,
+        "Minimum bet {} is above maximum bet {}",
+        params.min_bet,
+        params.max_bet
+    );
+    ensure!(params.payout_multiplier > 0, "Payout multiplier must be positive");
+    ensure!(params.session_timeout_blocks > 0, "Session timeout must be at least one block");
+    ensure!(
+        flip_rtp_bps(params) <= BPS_DENOMINATOR as u128,
+        "Coin flip would pay back {} bps of stakes, more than it takes in",
+        flip_rtp_bps(params)
+    );
+    ensure!(
+        params.max_slot_rtp_bps <= BPS_DENOMINATOR,
+        "Slot machine RTP cap must be at most {} bps, got {}",
+        BPS_DENOMINATOR,
+        params.max_slot_rtp_bps
+    );
+    // Paytables and Plinko multipliers are the only edge the house has on them,
+    // so they must keep at least the house edge
+    let max_rtp_bps = BPS_DENOMINATOR.saturating_sub(params.house_edge_bps);
+    let max_slot_rtp_bps = params.max_slot_rtp_bps.min(max_rtp_bps);
+    for (index, machine) in params.slot_machines.iter().enumerate() {
+        ensure!(
+            params.slot_machines[..index].iter().all(|other| other.id != machine.id),
+            "Slot machine id {} is used twice",
+            machine.id
+        );
+        slots::validate_machine(machine, max_slot_rtp_bps)
+            .with_context(|| format!("Invalid slot machine {}", machine.id))?;
+    }
+    for (index, table) in params.plinko_tables.iter().enumerate() {
+        ensure!(
+            params.plinko_tables[..index].iter().all(|other| (other.rows, other.risk) != (table.rows, table.risk)),
+            "Plinko board with {} rows and {:?} risk is defined twice",
+            table.rows,
+            table.risk
+        );
+        plinko::validate_table(table, max_rtp_bps)
+            .with_context(|| format!("Invalid Plinko board with {} rows and {:?} risk", table.rows, table.risk))?;
+    }
+    Ok(())
+}
+
+/// Params in effect; chains started before params existed run on the defaults
+pub fn load(storage: &Storage) -> Result<ChainParams> {
+    Ok(storage.get_params()?.unwrap_or_default())
+}
+
+/// Expected share of stakes a coin flip pays back (return to player), in basis points
+pub fn flip_rtp_bps(params: &ChainParams) -> u128 {
+    let net_bps = BPS_DENOMINATOR.saturating_sub(params.house_edge_bps) as u128;
+    params.payout_multiplier as u128 * net_bps / 2
+}
+
+/// Require a bet within the limits
+pub fn check_bet(params: &ChainParams, amount: u64) -> Result<(), TxRejection> {
+    if amount < params.min_bet || amount > params.max_bet {
+        return Err(TxRejection::new(
+            codes::BET_OUT_OF_RANGE,
+            format!("Bet out of range: must be {} to {}", params.min_bet, params.max_bet),
+        ));
+    }
+    Ok(())
+}
+
+/// Payout of a winning coin flip: the stake times the multiplier, less the house edge
+pub fn flip_payout(params: &ChainParams, amount: u64) -> Result<u64, TxRejection> {
+    let net_bps = BPS_DENOMINATOR.saturating_sub(params.house_edge_bps) as u128;
+    let payout = amount as u128 * params.payout_multiplier as u128 * net_bps / BPS_DENOMINATOR as u128;
+    u64::try_from(payout).map_err(|_| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mychain_types::{PlinkoRisk, PlinkoTable, SlotMachine, SlotPay};
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&ChainParams::default()).is_ok());
+        assert!(validate(&ChainParams { house_edge_bps: 250, ..Default::default() }).is_ok());
+
+        assert!(validate(&ChainParams { house_edge_bps: BPS_DENOMINATOR, ..Default::default() }).is_err());
+        assert!(validate(&ChainParams { min_bet: 0, ..Default::default() }).is_err());
+        assert!(validate(&ChainParams { min_bet: 10, max_bet: 9, ..Default::default() }).is_err());
+        assert!(validate(&ChainParams { payout_multiplier: 0, ..Default::default() }).is_err());
+        assert!(validate(&ChainParams { session_timeout_blocks: 0, ..Default::default() }).is_err());
+        // 3x on a fair coin pays out more than it takes in, unless the edge makes up for it
+        assert!(validate(&ChainParams { payout_multiplier: 3, ..Default::default() }).is_err());
+        assert!(validate(&ChainParams { payout_multiplier: 3, house_edge_bps: 4_000, ..Default::default() }).is_ok());
+
+        // Slot machines are priced against the RTP cap, and ids must be unique
+        let machine = SlotMachine {
+            id: 1,
+            reels: vec![vec![1, 2]],
+            rows: 1,
+            paylines: vec![vec![0]],
+            paytable: vec![SlotPay { symbol: 1, count: 1, pays: 190 }],
+        };
+        let params = ChainParams { slot_machines: vec![machine.clone()], ..Default::default() };
+        assert!(validate(&params).is_ok());
+        assert!(validate(&ChainParams { max_slot_rtp_bps: 9_000, ..params.clone() }).is_err());
+        assert!(validate(&ChainParams { max_slot_rtp_bps: BPS_DENOMINATOR + 1, ..params.clone() }).is_err());
+        // The machine pays back 9_500 bps: fine under a 5% edge, not a 6% one
+        assert!(validate(&ChainParams { house_edge_bps: 500, ..params.clone() }).is_ok());
+        assert!(validate(&ChainParams { house_edge_bps: 600, ..params.clone() }).is_err());
+        let twice = ChainParams { slot_machines: vec![machine.clone(), machine.clone()], ..params.clone() };
+        assert!(validate(&twice).is_err());
+        let other = SlotMachine { id: 2, ..machine };
+        assert!(validate(&ChainParams { slot_machines: vec![params.slot_machines[0].clone(), other], ..params }).is_ok());
+
+        // Plinko boards must keep the house edge, and each rows and risk has one board
+        let table = PlinkoTable { rows: 8, risk: PlinkoRisk::Low, multipliers: vec![99; 9] };
+        let params = ChainParams { plinko_tables: vec![table.clone()], house_edge_bps: 100, ..Default::default() };
+        assert!(validate(&params).is_ok());
+        assert!(validate(&ChainParams { house_edge_bps: 200, ..params.clone() }).is_err());
+        let twice = ChainParams { plinko_tables: vec![table.clone(), table.clone()], ..params.clone() };
+        assert!(validate(&twice).is_err());
+        let high = PlinkoTable { risk: PlinkoRisk::High, ..table.clone() };
+        assert!(validate(&ChainParams { plinko_tables: vec![table, high], ..params }).is_ok());
+    }
+
+    #[test]
+    fn test_flip_payout() {
+        let params = ChainParams::default();
+        assert_eq!(flip_rtp_bps(&params), 10_000);
+        assert_eq!(flip_payout(&params, 50), Ok(100));
+        assert_eq!(flip_payout(&params, u64::MAX).unwrap_err().code, codes::BALANCE_OVERFLOW);
+
+        // 1% edge: 1.98x, rounded down
+        let params = ChainParams { house_edge_bps: 100, ..Default::default() };
+        assert_eq!(flip_rtp_bps(&params), 9_900);
+        assert_eq!(flip_payout(&params, 100), Ok(198));
+        assert_eq!(flip_payout(&params, 1), Ok(1));
+        assert_eq!(flip_payout(&params, u64::MAX / 2), Ok(((u64::MAX / 2) as u128 * 19_800 / 10_000) as u64));
+    }
+
+    #[test]
+    fn test_check_bet() {
+        let params = ChainParams { min_bet: 10, max_bet: 100, ..Default::default() };
+        assert!(check_bet(&params, 10).is_ok());
+        assert!(check_bet(&params, 100).is_ok());
+        assert_eq!(check_bet(&params, 9).unwrap_err().code, codes::BET_OUT_OF_RANGE);
+        assert_eq!(check_bet(&params, 101).unwrap_err().code, codes::BET_OUT_OF_RANGE);
+    }
+}
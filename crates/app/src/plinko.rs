@@ -0,0 +1,298 @@
+//! Plinko: a ball bounces left or right on each row of pegs and lands in a slot
+//! paying that slot's multiplier
+//!
+//! Each bounce is one bit of the bet's VRF output, and the stored path lets
+//! anyone replay the drop from the proof. Multipliers come from the chain params,
+//! one table per number of rows and risk profile. A ball lands in slot `k` of a
+//! board with `n` rows with probability `C(n, k) / 2^n`, so each table's return to
+//! player is computed exactly when the params are loaded, and tables keeping less
+//! than the house edge are refused.
+
+use anyhow::{ensure, Context, Result};
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    BetKind, BetRecord, ChainParams, PlinkoRisk, PlinkoTable, SignedTx, Tx, TxKind, TxPlinko,
+    BPS_DENOMINATOR, MAX_PLINKO_ROWS, MIN_PLINKO_ROWS,
+};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::VrfEngine;
+use crate::{accounts, params, BlockContext};
+
+/// Multipliers are in hundredths of the stake
+const MULTIPLIER_DENOMINATOR: u128 = 100;
+
+/// Plinko boards defined in the chain params
+pub struct Plinko;
+
+impl Game for Plinko {
+    fn id(&self) -> &'static str {
+        "plinko"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::Plinko]
+    }
+
+    fn validate(&self, tx: &Tx) -> Result<(), TxRejection> {
+        match tx {
+            Tx::Plinko(bet) => check_rows(bet.rows),
+            _ => Ok(()),
+        }
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        let Tx::Plinko(bet) = &signed.tx else {
+            return Err(game::unhandled(self.id(), &signed.tx));
+        };
+        let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+        params::check_bet(&chain_params, bet.amount)?;
+        table(&chain_params, bet.rows, bet.risk)?;
+        Ok(bet.amount)
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::Plinko(tx) => execute_plinko(storage, batch, vrf_engine, block, tx, tx_hash),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+}
+
+/// Require a number of rows a board can have
+pub fn check_rows(rows: u8) -> Result<(), TxRejection> {
+    if !(MIN_PLINKO_ROWS..=MAX_PLINKO_ROWS).contains(&rows) {
+        return Err(TxRejection::new(
+            codes::INVALID_BET,
+            format!("Invalid rows: must be {} to {}", MIN_PLINKO_ROWS, MAX_PLINKO_ROWS),
+        ));
+    }
+    Ok(())
+}
+
+/// Check a table's layout and that its return to player is at most `max_rtp_bps`
+pub fn validate_table(table: &PlinkoTable, max_rtp_bps: u64) -> Result<()> {
+    ensure!(
+        (MIN_PLINKO_ROWS..=MAX_PLINKO_ROWS).contains(&table.rows),
+        "Board must have {} to {} rows",
+        MIN_PLINKO_ROWS,
+        MAX_PLINKO_ROWS
+    );
+    ensure!(
+        table.multipliers.len() == table.rows as usize + 1,
+        "Board with {} rows must have {} multipliers, got {}",
+        table.rows,
+        table.rows as usize + 1,
+        table.multipliers.len()
+    );
+    let rtp = rtp_bps(table).context("Multipliers are too large to price")?;
+    ensure!(
+        rtp <= max_rtp_bps as u128,
+        "Board would pay back {} bps of stakes, above the maximum of {}",
+        rtp,
+        max_rtp_bps
+    );
+    Ok(())
+}
+
+/// Theoretical return to player of a table, in basis points rounded up; `None`
+/// if the multipliers are too large to price
+pub fn rtp_bps(table: &PlinkoTable) -> Option<u128> {
+    let rows = table.rows as u32;
+    // C(rows, k) built up slot by slot
+    let mut paths = 1u128;
+    let mut total = 0u128;
+    for (slot, &multiplier) in table.multipliers.iter().enumerate() {
+        total = total.checked_add(paths.checked_mul(multiplier as u128)?)?;
+        paths = paths * (rows as u128).saturating_sub(slot as u128) / (slot as u128 + 1);
+    }
+    let numerator = total.checked_mul(BPS_DENOMINATOR as u128)?;
+    let denominator = 1u128.checked_shl(rows)?.checked_mul(MULTIPLIER_DENOMINATOR)?;
+    Some(numerator.div_ceil(denominator))
+}
+
+/// Table with the given rows and risk
+pub fn table(params: &ChainParams, rows: u8, risk: PlinkoRisk) -> Result<&PlinkoTable, TxRejection> {
+    check_rows(rows)?;
+    params
+        .plinko_tables
+        .iter()
+        .find(|table| table.rows == rows && table.risk == risk)
+        .ok_or_else(|| {
+            TxRejection::new(codes::INVALID_BET, format!("No {:?} risk Plinko board with {} rows", risk, rows))
+        })
+}
+
+/// Slot a path lands in: the number of right bounces
+pub fn landing_slot(path: &[bool]) -> usize {
+    path.iter().filter(|&&right| right).count()
+}
+
+/// Payout of a ball landing on `multiplier` hundredths of the stake, rounded down
+pub fn payout(amount: u64, multiplier: u64) -> Result<u64, TxRejection> {
+    let payout = amount as u128 * multiplier as u128 / MULTIPLIER_DENOMINATOR;
+    u64::try_from(payout).map_err(|_| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+/// Settle a Plinko bet: debit the stake, drop the ball with the VRF, credit its slot
+pub fn execute_plinko(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxPlinko,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+    let table = table(&chain_params, tx.rows, tx.risk)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    // The path is not known yet, so the stake must be payable in any slot
+    payout(tx.amount, table.multipliers.iter().copied().max().unwrap_or(0))?;
+
+    let (vrf_message, vrf_proof, vrf_output) = vrf_engine
+        .prove_bet(&block.chain_id, block.height, &block.block_random, &tx_hash, &tx.wallet, tx.nonce)
+        .map_err(TxRejection::internal)?;
+    let path = VrfEngine::derive_plinko_path(&vrf_output, tx.rows);
+    let slot = landing_slot(&path);
+    let win_payout = payout(tx.amount, table.multipliers[slot])?;
+    let record = BetRecord {
+        wallet: tx.wallet,
+        amount: tx.amount,
+        kind: BetKind::Plinko { rows: tx.rows, risk: tx.risk, path: path.clone() },
+        nonce: tx.nonce,
+        vrf_message,
+        vrf_proof,
+        vrf_output,
+        won: win_payout > tx.amount,
+        payout: win_payout,
+        height: block.height,
+        tx_hash,
+    };
+    let balance = accounts::credit(balance, record.payout)?;
+
+    storage.store_bet(&tx_hash, &record, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+
+    let path: String = path.iter().map(|&right| if right { 'R' } else { 'L' }).collect();
+    Ok(Event {
+        kind: "plinko".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(record.wallet)).into(),
+            ("amount".to_string(), record.amount.to_string()).into(),
+            ("rows".to_string(), tx.rows.to_string()).into(),
+            ("risk".to_string(), format!("{:?}", tx.risk).to_lowercase()).into(),
+            ("path".to_string(), path).into(),
+            ("slot".to_string(), slot.to_string()).into(),
+            ("won".to_string(), record.won.to_string()).into(),
+            ("payout".to_string(), record.payout.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(record.tx_hash)).into(),
+            ("vrf_proof".to_string(), hex::encode(&record.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&record.vrf_output)).into(),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+
+    /// Eight rows, paying 5.6x at the edges down to 0.5x in the middle
+    fn low_risk_eight() -> PlinkoTable {
+        PlinkoTable {
+            rows: 8,
+            risk: PlinkoRisk::Low,
+            multipliers: vec![560, 210, 110, 100, 50, 100, 110, 210, 560],
+        }
+    }
+
+    #[test]
+    fn test_rtp_is_exact() {
+        // Slots are hit 1, 8, 28, 56, 70, 56, 28, 8, 1 times in 256
+        let expected = (2 * 560 + 2 * 8 * 210 + 2 * 28 * 110 + 2 * 56 * 100 + 70 * 50) * 10_000;
+        assert_eq!(rtp_bps(&low_risk_eight()), Some((expected as u128).div_ceil(256 * 100)));
+        assert_eq!(rtp_bps(&low_risk_eight()), Some(9_899));
+
+        // Every slot paying 1x returns exactly the stake, whatever the rows
+        let even = PlinkoTable { rows: 16, risk: PlinkoRisk::High, multipliers: vec![100; 17] };
+        assert_eq!(rtp_bps(&even), Some(10_000));
+    }
+
+    #[test]
+    fn test_validate_table() {
+        let table = low_risk_eight();
+        assert!(validate_table(&table, 10_000).is_ok());
+        assert!(validate_table(&table, 9_899).is_ok());
+        assert!(validate_table(&table, 9_898).is_err());
+
+        assert!(validate_table(&PlinkoTable { rows: 7, multipliers: vec![100; 8], ..table.clone() }, 10_000).is_err());
+        assert!(validate_table(&PlinkoTable { rows: 17, multipliers: vec![100; 18], ..table.clone() }, 10_000).is_err());
+        assert!(validate_table(&PlinkoTable { multipliers: vec![100; 8], ..table.clone() }, 10_000).is_err());
+        let multipliers = vec![u64::MAX; 9];
+        assert!(validate_table(&PlinkoTable { multipliers, ..table }, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_landing_slot_and_payout() {
+        assert_eq!(landing_slot(&[false; 8]), 0);
+        assert_eq!(landing_slot(&[true; 8]), 8);
+        assert_eq!(landing_slot(&[true, false, true, false, false, false, false, true]), 3);
+
+        assert_eq!(payout(100, 560), Ok(560));
+        assert_eq!(payout(3, 50), Ok(1));
+        assert_eq!(payout(100, 0), Ok(0));
+        assert_eq!(payout(u64::MAX, 200).unwrap_err().code, codes::BALANCE_OVERFLOW);
+    }
+
+    #[test]
+    fn test_execute_plinko() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let chain_params = ChainParams { plinko_tables: vec![low_risk_eight()], ..Default::default() };
+        let mut batch = storage.batch();
+        storage.set_params(&chain_params, &mut batch)?;
+        storage.apply_batch(batch)?;
+        let block = block(5);
+        let tx = TxPlinko { wallet, amount: 100, rows: 8, risk: PlinkoRisk::Low, nonce: 0 };
+
+        let mut batch = storage.batch();
+        execute_plinko(&storage, &mut batch, &vrf_engine, &block, &tx, [9u8; 32])?;
+        storage.apply_batch(batch)?;
+
+        // The stored path replays from the VRF output and decides the balance
+        let record = storage.get_bet(&[9u8; 32])?.unwrap();
+        let BetKind::Plinko { rows, risk, path } = record.kind else {
+            panic!("expected a plinko record, got {:?}", record.kind);
+        };
+        assert_eq!((rows, risk), (8, PlinkoRisk::Low));
+        assert_eq!(path, VrfEngine::derive_plinko_path(&record.vrf_output, 8));
+        let expected = low_risk_eight().multipliers[landing_slot(&path)];
+        assert_eq!(record.payout, expected);
+        // A slot paying back less than the stake is a loss
+        assert_eq!(record.won, expected > 100);
+        assert_eq!(storage.get_balance(&wallet)?, 900 + expected);
+
+        // Boards that are not configured cannot be played
+        let tx = TxPlinko { risk: PlinkoRisk::High, nonce: 1, ..tx };
+        let mut batch = storage.batch();
+        let rejection = execute_plinko(&storage, &mut batch, &vrf_engine, &block, &tx, [8u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_BET);
+        assert!(storage.get_bet(&[8u8; 32])?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use ics23::commitment_proof::Proof;
+use ics23::{CommitmentProof, ExistenceProof, HashOp, InnerOp, LeafOp, LengthOp, NonExistenceProof};
+use mychain_storage::merkle::{MerkleProof, Side, StateProof, INNER_PREFIX, LEAF_PREFIX};
+use prost::Message;
+use tendermint::merkle::proof::{ProofOp, ProofOps};
+
+/// Proof op type of ICS23 proofs over a `tendermint_spec` tree (as used by the
+/// Cosmos SDK for simple Merkle stores)
+pub const PROOF_OP_TYPE: &str = "ics23:simple";
+
+/// Leaf encoding of the state tree, matching `ics23::tendermint_spec()`
+pub fn leaf_op() -> LeafOp {
+    LeafOp {
+        hash: HashOp::Sha256.into(),
+        prehash_key: HashOp::NoHash.into(),
+        prehash_value: HashOp::Sha256.into(),
+        length: LengthOp::VarProto.into(),
+        prefix: vec![LEAF_PREFIX],
+    }
+}
+
+/// Convert a Merkle path into an ICS23 existence proof
+fn existence_proof(proof: &MerkleProof) -> ExistenceProof {
+    let path = proof.path.iter()
+        .map(|step| match step.side {
+            // Sibling on the left: hash(0x01 || sibling || node)
+            Side::Left => InnerOp {
+                hash: HashOp::Sha256.into(),
+                prefix: [&[INNER_PREFIX][..], &step.sibling].concat(),
+                suffix: vec![],
+            },
+            // Sibling on the right: hash(0x01 || node || sibling)
+            Side::Right => InnerOp {
+                hash: HashOp::Sha256.into(),
+                prefix: vec![INNER_PREFIX],
+                suffix: step.sibling.to_vec(),
+            },
+        })
+        .collect();
+
+    ExistenceProof {
+        key: proof.key.clone(),
+        value: proof.value.clone(),
+        leaf: Some(leaf_op()),
+        path,
+    }
+}
+
+/// Convert a state proof into an ICS23 commitment proof
+pub fn commitment_proof(proof: &StateProof) -> CommitmentProof {
+    let proof = match proof {
+        StateProof::Exists(proof) => Proof::Exist(existence_proof(proof)),
+        StateProof::Absent { key, left, right } => Proof::Nonexist(NonExistenceProof {
+            key: key.clone(),
+            left: left.as_ref().map(existence_proof),
+            right: right.as_ref().map(existence_proof),
+        }),
+    };
+    CommitmentProof { proof: Some(proof) }
+}
+
+/// Wrap a state proof into the `ProofOps` of an ABCI query response
+///
+/// The single op proves the key directly against the app hash.
+pub fn proof_ops(proof: &StateProof) -> ProofOps {
+    ProofOps {
+        ops: vec![ProofOp {
+            field_type: PROOF_OP_TYPE.to_string(),
+            key: proof.key().to_vec(),
+            data: commitment_proof(proof).encode_to_vec(),
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use ics23::HostFunctionsManager;
+    use mychain_storage::Storage;
+
+    #[test]
+    fn test_ics23_membership_and_non_membership() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+
+        let mut batch = storage.batch();
+        storage.set_last_height(9, &mut batch)?;
+        for height in [1, 3, 5, 7, 9] {
+            storage.store_block_random(height, &[height as u8; 32], &mut batch)?;
+        }
+        storage.apply_batch(batch)?;
+        let root = storage.compute_app_hash(&storage.batch())?.to_vec();
+        let spec = ics23::tendermint_spec();
+
+        for height in [1, 3, 5, 7, 9] {
+            let state_proof = storage.prove_block_random(height)?;
+            let proof = commitment_proof(&state_proof);
+            assert!(ics23::verify_membership::<HostFunctionsManager>(
+                &proof, &spec, &root, state_proof.key(), &[height as u8; 32]
+            ));
+            assert!(!ics23::verify_membership::<HostFunctionsManager>(
+                &proof, &spec, &root, state_proof.key(), &[0u8; 32]
+            ));
+        }
+
+        // Missing keys: before, between and after the existing ones
+        for height in [0, 4, 8, 99] {
+            let state_proof = storage.prove_block_random(height)?;
+            let proof = commitment_proof(&state_proof);
+            assert!(ics23::verify_non_membership::<HostFunctionsManager>(
+                &proof, &spec, &root, state_proof.key()
+            ));
+        }
+        let state_proof = storage.prove_bet(&[1u8; 32])?;
+        assert!(ics23::verify_non_membership::<HostFunctionsManager>(
+            &commitment_proof(&state_proof), &spec, &root, state_proof.key()
+        ));
+
+        // ProofOps carry the encoded proof
+        let ops = proof_ops(&storage.prove_block_random(5)?);
+        assert_eq!(ops.ops.len(), 1);
+        assert_eq!(ops.ops[0].field_type, PROOF_OP_TYPE);
+        let decoded = CommitmentProof::decode(ops.ops[0].data.as_slice())?;
+        assert_eq!(decoded, commitment_proof(&storage.prove_block_random(5)?));
+
+        Ok(())
+    }
+}
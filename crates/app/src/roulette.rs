@@ -0,0 +1,300 @@
+//! European roulette at shared tables
+//!
+//! The first bet at a table in a block spins it: the number is drawn from a VRF
+//! proof over the block randomness and the table id, so it is fixed for the whole
+//! block and every bet at the table settles against the same spin. A winning bet
+//! covering `n` numbers pays `36 / n` times the stake; the zero pocket is the
+//! house edge.
+
+use anyhow::Result;
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    BetKind, BetRecord, RouletteBet, RouletteSpin, SignedTx, Tx, TxKind, TxRoulette,
+};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::VrfEngine;
+use crate::{accounts, params, BlockContext};
+
+/// Red numbers of a European wheel; every other number but 0 is black
+pub const RED_NUMBERS: [u8; 18] = [1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36];
+
+/// A winning bet pays the stake times this, divided by the count of numbers covered
+const PAYOUT_NUMERATOR: u64 = 36;
+
+/// Roulette tables, spun once per block
+pub struct Roulette;
+
+impl Game for Roulette {
+    fn id(&self) -> &'static str {
+        "roulette"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::Roulette]
+    }
+
+    fn validate(&self, tx: &Tx) -> Result<(), TxRejection> {
+        match tx {
+            Tx::Roulette(bet) => check_bet(&bet.bet).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        game::stake(storage, signed.tx.amount())
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::Roulette(tx) => execute_roulette(storage, batch, vrf_engine, block, tx, tx_hash),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+
+    fn end_block(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        _vrf_engine: &VrfEngine,
+        block: &BlockContext,
+    ) -> Result<Vec<Event>> {
+        // Tables were spun by their first bet; publish each spin once
+        spin_events(storage, batch, block.height)
+    }
+}
+
+/// Numbers covered by a bet, or `None` for a bet that does not exist on the layout
+pub fn covered_numbers(bet: &RouletteBet) -> Option<Vec<u8>> {
+    let numbers: Vec<u8> = match *bet {
+        RouletteBet::Straight(n) if n <= 36 => vec![n],
+        // Row neighbours, column neighbours, or zero with 1, 2 or 3
+        RouletteBet::Split(0, b) if (1..=3).contains(&b) => vec![0, b],
+        RouletteBet::Split(a, b) if a >= 1 && b <= 36 && (b == a + 3 || (b == a + 1 && a % 3 != 0)) => {
+            vec![a, b]
+        }
+        RouletteBet::Street(n) if n % 3 == 1 && n <= 34 => (n..n + 3).collect(),
+        RouletteBet::Corner(n) if n >= 1 && n % 3 != 0 && n <= 32 => vec![n, n + 1, n + 3, n + 4],
+        RouletteBet::SixLine(n) if n % 3 == 1 && n <= 31 => (n..n + 6).collect(),
+        RouletteBet::Dozen(d) if (1..=3).contains(&d) => (12 * (d - 1) + 1..=12 * d).collect(),
+        RouletteBet::Column(c) if (1..=3).contains(&c) => (1..=36).filter(|n| n % 3 == c % 3).collect(),
+        RouletteBet::Red => RED_NUMBERS.to_vec(),
+        RouletteBet::Black => (1..=36).filter(|n| !RED_NUMBERS.contains(n)).collect(),
+        RouletteBet::Odd => (1..=36).filter(|n| n % 2 == 1).collect(),
+        RouletteBet::Even => (1..=36).filter(|n| n % 2 == 0).collect(),
+        RouletteBet::Low => (1..=18).collect(),
+        RouletteBet::High => (19..=36).collect(),
+        _ => return None,
+    };
+    Some(numbers)
+}
+
+/// Require a bet that exists on the layout
+pub fn check_bet(bet: &RouletteBet) -> Result<Vec<u8>, TxRejection> {
+    covered_numbers(bet).ok_or_else(|| {
+        TxRejection::new(codes::INVALID_BET, format!("Invalid roulette bet: {}", bet_name(bet)))
+    })
+}
+
+/// Payout of a winning bet covering `covered` numbers
+pub fn payout(amount: u64, covered: usize) -> Result<u64, TxRejection> {
+    amount
+        .checked_mul(PAYOUT_NUMERATOR)
+        .map(|total| total / covered as u64)
+        .ok_or_else(|| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+/// Name of a bet, as used in events and logs
+pub fn bet_name(bet: &RouletteBet) -> String {
+    match bet {
+        RouletteBet::Straight(n) => format!("straight:{}", n),
+        RouletteBet::Split(a, b) => format!("split:{}-{}", a, b),
+        RouletteBet::Street(n) => format!("street:{}", n),
+        RouletteBet::Corner(n) => format!("corner:{}", n),
+        RouletteBet::SixLine(n) => format!("six_line:{}", n),
+        RouletteBet::Dozen(d) => format!("dozen:{}", d),
+        RouletteBet::Column(c) => format!("column:{}", c),
+        RouletteBet::Red => "red".to_string(),
+        RouletteBet::Black => "black".to_string(),
+        RouletteBet::Odd => "odd".to_string(),
+        RouletteBet::Even => "even".to_string(),
+        RouletteBet::Low => "low".to_string(),
+        RouletteBet::High => "high".to_string(),
+    }
+}
+
+/// Settle a roulette bet against its table's spin for the block
+pub fn execute_roulette(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxRoulette,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+    let numbers = check_bet(&tx.bet)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    let win_payout = payout(tx.amount, numbers.len())?;
+    let (spin, new_spin) = match storage.get_pending_roulette_spin(block.height, tx.table, batch)
+        .map_err(TxRejection::internal)?
+    {
+        Some(spin) => (spin, false),
+        None => (spin_table(vrf_engine, block, tx.table).map_err(TxRejection::internal)?, true),
+    };
+    let won = numbers.contains(&spin.number);
+    let record = BetRecord {
+        wallet: tx.wallet,
+        amount: tx.amount,
+        kind: BetKind::Roulette { table: tx.table, bet: tx.bet.clone(), number: spin.number },
+        nonce: tx.nonce,
+        vrf_message: Vec::new(),
+        vrf_proof: Vec::new(),
+        vrf_output: Vec::new(),
+        won,
+        payout: if won { win_payout } else { 0 },
+        height: block.height,
+        tx_hash,
+    };
+    let balance = accounts::credit(balance, record.payout)?;
+
+    // The first bet on the table this block stores the spin later bets land on
+    if new_spin {
+        storage.store_roulette_spin(&spin, batch).map_err(TxRejection::internal)?;
+    }
+    storage.store_bet(&tx_hash, &record, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+
+    Ok(Event {
+        kind: "roulette".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(record.wallet)).into(),
+            ("amount".to_string(), record.amount.to_string()).into(),
+            ("table".to_string(), tx.table.to_string()).into(),
+            ("bet".to_string(), bet_name(&tx.bet)).into(),
+            ("number".to_string(), spin.number.to_string()).into(),
+            ("won".to_string(), record.won.to_string()).into(),
+            ("payout".to_string(), record.payout.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(record.tx_hash)).into(),
+        ],
+    })
+}
+
+/// Draw the number of a table for the block
+fn spin_table(vrf_engine: &VrfEngine, block: &BlockContext, table: u32) -> Result<RouletteSpin> {
+    let (vrf_message, vrf_proof, vrf_output, number) =
+        vrf_engine.process_spin(&block.chain_id, block.height, &block.block_random, table)?;
+    Ok(RouletteSpin { table, height: block.height, vrf_message, vrf_proof, vrf_output, number })
+}
+
+/// One event per table spun in the block, in the order the tables were first bet on
+pub fn spin_events(storage: &Storage, batch: &StorageBatch, height: u64) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for table in storage.get_pending_roulette_tables(height, batch)? {
+        let Some(spin) = storage.get_pending_roulette_spin(height, table, batch)? else {
+            anyhow::bail!("Missing spin of roulette table {} at height {}", table, height);
+        };
+        events.push(Event {
+            kind: "roulette_spin".to_string(),
+            attributes: vec![
+                ("table".to_string(), spin.table.to_string()).into(),
+                ("height".to_string(), spin.height.to_string()).into(),
+                ("number".to_string(), spin.number.to_string()).into(),
+                ("vrf_proof".to_string(), hex::encode(&spin.vrf_proof)).into(),
+                ("vrf_output".to_string(), hex::encode(&spin.vrf_output)).into(),
+            ],
+        });
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+
+    #[test]
+    fn test_covered_numbers() {
+        let covered = |bet: RouletteBet| covered_numbers(&bet).map(|numbers| numbers.len());
+
+        assert_eq!(covered(RouletteBet::Straight(0)), Some(1));
+        assert_eq!(covered(RouletteBet::Straight(37)), None);
+        assert_eq!(covered(RouletteBet::Split(0, 2)), Some(2));
+        assert_eq!(covered(RouletteBet::Split(2, 3)), Some(2));
+        assert_eq!(covered(RouletteBet::Split(33, 36)), Some(2));
+        // 3 and 4 sit at the ends of different rows
+        assert_eq!(covered(RouletteBet::Split(3, 4)), None);
+        assert_eq!(covered(RouletteBet::Split(2, 1)), None);
+        assert_eq!(covered(RouletteBet::Street(34)), Some(3));
+        assert_eq!(covered(RouletteBet::Street(2)), None);
+        assert_eq!(covered_numbers(&RouletteBet::Corner(32)), Some(vec![32, 33, 35, 36]));
+        assert_eq!(covered(RouletteBet::Corner(3)), None);
+        assert_eq!(covered(RouletteBet::Corner(34)), None);
+        assert_eq!(covered(RouletteBet::SixLine(31)), Some(6));
+        assert_eq!(covered(RouletteBet::SixLine(34)), None);
+        assert_eq!(covered_numbers(&RouletteBet::Dozen(3)), Some((25..=36).collect()));
+        assert_eq!(covered(RouletteBet::Dozen(0)), None);
+        assert_eq!(covered_numbers(&RouletteBet::Column(3)).unwrap()[..2], [3, 6]);
+        assert_eq!(covered(RouletteBet::Column(4)), None);
+
+        // Even-money bets never cover zero
+        for bet in [RouletteBet::Red, RouletteBet::Black, RouletteBet::Odd, RouletteBet::Even, RouletteBet::Low, RouletteBet::High] {
+            let numbers = covered_numbers(&bet).unwrap();
+            assert_eq!(numbers.len(), 18, "{:?}", bet);
+            assert!(!numbers.contains(&0));
+        }
+    }
+
+    #[test]
+    fn test_payout() {
+        assert_eq!(payout(10, 1), Ok(360));
+        assert_eq!(payout(10, 2), Ok(180));
+        assert_eq!(payout(10, 12), Ok(30));
+        assert_eq!(payout(10, 18), Ok(20));
+        assert_eq!(payout(u64::MAX, 18).unwrap_err().code, codes::BALANCE_OVERFLOW);
+    }
+
+    #[test]
+    fn test_bets_at_a_table_share_the_spin() -> anyhow::Result<()> {
+        let wallets = [[1u8; 32], [2u8; 32]];
+        let (_temp_dir, storage, vrf_engine) = setup(&wallets, 1_000)?;
+        let block = block(5);
+        let mut batch = storage.batch();
+        for (i, wallet) in wallets.iter().enumerate() {
+            let tx = TxRoulette { wallet: *wallet, amount: 10, table: 3, bet: RouletteBet::Red, nonce: 0 };
+            execute_roulette(&storage, &mut batch, &vrf_engine, &block, &tx, [i as u8; 32])?;
+        }
+
+        // One spin, one event, and both bets settled against it
+        let events = spin_events(&storage, &batch, 5)?;
+        assert_eq!(events.len(), 1);
+        storage.apply_batch(batch)?;
+
+        let spin = storage.get_roulette_spin(5, 3)?.unwrap();
+        assert_eq!(spin.vrf_message, VrfEngine::compute_spin_message("test_chain", 5, &[7u8; 32], 3));
+        assert!(VrfEngine::verify(&vrf_engine.public_key(), &spin.vrf_message, &spin.vrf_proof, &spin.vrf_output)?);
+        let red = RED_NUMBERS.contains(&spin.number);
+        for (i, wallet) in wallets.iter().enumerate() {
+            let record = storage.get_bet(&[i as u8; 32])?.unwrap();
+            assert_eq!(record.kind, BetKind::Roulette { table: 3, bet: RouletteBet::Red, number: spin.number });
+            assert_eq!(record.won, red);
+            assert_eq!(storage.get_balance(wallet)?, if red { 1_010 } else { 990 });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+//! Game sessions: games played over several transactions, across blocks
+//!
+//! A game opens a session when it takes the stake, which the session holds in
+//! escrow until it settles. Every action must be one the game allows in the
+//! session's state (see [`Game::session_actions`]), and moves the session's
+//! timeout to `session_timeout_blocks` past the action. A session nobody acted
+//! on is handed to its game at the end of the block it expires at (see
+//! [`Game::time_out`]), so an abandoned game never keeps its stake locked. A
+//! session its game fails to settle is handed to it again at the next block.
+//!
+//! These helpers only compute; the game stores the session along with its own
+//! writes, once the whole transaction is known to succeed.
+
+use anyhow::{Context, Result};
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{GameSession, TxKind};
+use tendermint::abci::Event;
+use tracing::error;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{Game, GameRegistry};
+use crate::vrf::VrfEngine;
+use crate::{accounts, params, BlockContext};
+
+/// A new session of `game` for `wallet`, escrowing `stake`
+///
+/// A wallet plays one session of a game at a time. The stake is not debited
+/// here; the game debits it along with storing the session.
+pub fn open(
+    storage: &Storage,
+    batch: &StorageBatch,
+    block: &BlockContext,
+    game: &dyn Game,
+    wallet: &[u8; 32],
+    stake: u64,
+    state: &str,
+) -> Result<GameSession, TxRejection> {
+    if let Some(session) = active(storage, batch, game.id(), wallet)? {
+        return Err(TxRejection::new(
+            codes::INVALID_MOVE,
+            format!("{} session {} opened at height {} is still in play", game.id(), session.id, session.opened_height),
+        ));
+    }
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    let id = storage.get_pending_latest_session_id(batch).map_err(TxRejection::internal)?.unwrap_or(0) + 1;
+    Ok(GameSession {
+        id,
+        game: game.id().to_string(),
+        wallet: *wallet,
+        stake,
+        state: state.to_string(),
+        opened_height: block.height,
+        last_action_height: block.height,
+        expires_at: block.height.saturating_add(chain_params.session_timeout_blocks),
+        finished: false,
+        payout: 0,
+    })
+}
+
+/// The wallet's session of `game`, if its state allows a `kind` action
+pub fn in_play(
+    storage: &Storage,
+    batch: &StorageBatch,
+    game: &dyn Game,
+    wallet: &[u8; 32],
+    kind: TxKind,
+) -> Result<GameSession, TxRejection> {
+    let session = active(storage, batch, game.id(), wallet)?
+        .ok_or_else(|| TxRejection::new(codes::INVALID_BET, format!("No {} session in play", game.id())))?;
+    if !game.session_actions(&session.state).contains(&kind) {
+        return Err(TxRejection::new(
+            codes::INVALID_MOVE,
+            format!("{} session {} does not take {} transactions while {}", game.id(), session.id, kind.name(), session.state),
+        ));
+    }
+    Ok(session)
+}
+
+/// Record an action that leaves the session in play, in `state`
+pub fn advance(storage: &Storage, block: &BlockContext, session: &mut GameSession, state: &str) -> Result<(), TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    session.state = state.to_string();
+    session.last_action_height = block.height;
+    session.expires_at = block.height.saturating_add(chain_params.session_timeout_blocks);
+    Ok(())
+}
+
+/// Record the end of the session, releasing its escrow as `payout`
+///
+/// The payout is not credited here; the game credits it along with storing the session.
+pub fn settle(block: &BlockContext, session: &mut GameSession, payout: u64) {
+    session.finished = true;
+    session.payout = payout;
+    session.last_action_height = block.height;
+}
+
+/// Settle a session by crediting its stake back, as if it had not been played
+pub fn refund(storage: &Storage, batch: &mut StorageBatch, block: &BlockContext, mut session: GameSession) -> Result<()> {
+    let balance = storage.get_pending_balance(&session.wallet, batch)?;
+    let stake = session.stake;
+    let balance = accounts::credit(balance, stake)?;
+    settle(block, &mut session, stake);
+    storage.store_game_session(&session, batch)?;
+    storage.set_balance(&session.wallet, balance, batch)
+}
+
+/// Hand the sessions expiring at this block to their games to settle
+///
+/// A session whose game fails to settle it keeps none of its writes and expires
+/// at the next block instead, so the other sessions still settle.
+pub fn end_block(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    games: &GameRegistry,
+) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for id in storage.get_pending_sessions_due(block.height, batch)? {
+        match batch.atomically(|batch| time_out(storage, batch, vrf_engine, block, games, id)) {
+            Ok(session_events) => events.extend(session_events),
+            Err(e) => {
+                error!("Failed to time out game session {}: {:#}", id, e);
+                if let Some(mut session) = storage.get_pending_game_session(id, batch)? {
+                    session.expires_at = block.height + 1;
+                    storage.store_game_session(&session, batch)?;
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Hand a session to its game to settle, if it expires at this block
+fn time_out(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    games: &GameRegistry,
+    id: u64,
+) -> Result<Vec<Event>> {
+    let Some(session) = storage.get_pending_game_session(id, batch)? else {
+        anyhow::bail!("Missing game session {}", id);
+    };
+    // Settled, or acted on since it was indexed here
+    if session.finished || session.expires_at != block.height {
+        return Ok(vec![]);
+    }
+
+    let game = games.get(&session.game)
+        .with_context(|| format!("Game session {} is of unknown game {}", id, session.game))?;
+    let mut events = game.time_out(storage, batch, vrf_engine, block, session)?;
+    let Some(session) = storage.get_pending_game_session(id, batch)?.filter(|session| session.finished) else {
+        anyhow::bail!("Game {} left session {} in play once it timed out", game.id(), id);
+    };
+    events.push(Event {
+        kind: "session_timeout".to_string(),
+        attributes: vec![
+            ("session".to_string(), session.id.to_string()).into(),
+            ("game".to_string(), session.game.clone()).into(),
+            ("wallet".to_string(), hex::encode(session.wallet)).into(),
+            ("stake".to_string(), session.stake.to_string()).into(),
+            ("payout".to_string(), session.payout.to_string()).into(),
+        ],
+    });
+    Ok(events)
+}
+
+/// The wallet's session of a game, if one is in play
+fn active(
+    storage: &Storage,
+    batch: &StorageBatch,
+    game: &str,
+    wallet: &[u8; 32],
+) -> Result<Option<GameSession>, TxRejection> {
+    for id in storage.get_pending_active_sessions(wallet, batch).map_err(TxRejection::internal)? {
+        let session = storage.get_pending_game_session(id, batch)
+            .map_err(TxRejection::internal)?
+            .ok_or_else(|| TxRejection::internal(format!("Missing game session {}", id)))?;
+        if session.game == game {
+            return Ok(Some(session));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game;
+    use crate::testing::{block, setup};
+    use mychain_types::{ChainParams, SignedTx};
+    use std::sync::Arc;
+
+    /// A game whose sessions take dice transactions, and refund on timeout
+    struct Tap;
+
+    impl Game for Tap {
+        fn id(&self) -> &'static str {
+            "tap"
+        }
+
+        fn tx_kinds(&self) -> &'static [TxKind] {
+            &[TxKind::Dice]
+        }
+
+        fn settle(
+            &self,
+            _storage: &Storage,
+            _batch: &mut StorageBatch,
+            _vrf_engine: &VrfEngine,
+            _block: &BlockContext,
+            signed: &SignedTx,
+            _tx_hash: [u8; 32],
+        ) -> Result<Event, TxRejection> {
+            Err(game::unhandled(self.id(), &signed.tx))
+        }
+
+        fn session_actions(&self, state: &str) -> &'static [TxKind] {
+            match state {
+                "open" => &[TxKind::Dice],
+                _ => &[],
+            }
+        }
+    }
+
+    #[test]
+    fn test_sessions_time_out_unless_acted_on() -> Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 900)?;
+        let mut batch = storage.batch();
+        storage.set_params(&ChainParams { session_timeout_blocks: 5, ..Default::default() }, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        let mut games = GameRegistry::default();
+        games.register(Arc::new(Tap))?;
+
+        // The game debited the stake when it opened the session
+        let mut batch = storage.batch();
+        let session = open(&storage, &batch, &block(10), &Tap, &wallet, 100, "open")?;
+        assert_eq!((session.id, session.expires_at), (1, 15));
+        storage.store_game_session(&session, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        let batch = storage.batch();
+        assert_eq!(open(&storage, &batch, &block(11), &Tap, &wallet, 100, "open").unwrap_err().code, codes::INVALID_MOVE);
+        assert_eq!(in_play(&storage, &batch, &Tap, &[2u8; 32], TxKind::Dice).unwrap_err().code, codes::INVALID_BET);
+        assert_eq!(in_play(&storage, &batch, &Tap, &wallet, TxKind::Flip).unwrap_err().code, codes::INVALID_MOVE);
+
+        // An action pushes the timeout back
+        let mut batch = storage.batch();
+        let mut session = in_play(&storage, &batch, &Tap, &wallet, TxKind::Dice)?;
+        advance(&storage, &block(12), &mut session, "open")?;
+        storage.store_game_session(&session, &mut batch)?;
+        assert!(end_block(&storage, &mut batch, &vrf_engine, &block(15), &games)?.is_empty());
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_active_sessions(&wallet)?, vec![1]);
+
+        // Left alone, the session settles at its timeout and frees the stake
+        let mut batch = storage.batch();
+        let events = end_block(&storage, &mut batch, &vrf_engine, &block(17), &games)?;
+        storage.apply_batch(batch)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, "session_timeout");
+        let session = storage.get_game_session(1)?.unwrap();
+        assert!(session.finished);
+        assert_eq!((session.payout, session.last_action_height), (100, 17));
+        assert_eq!(storage.get_balance(&wallet)?, 1_000);
+        assert!(storage.get_active_sessions(&wallet)?.is_empty());
+
+        let batch = storage.batch();
+        assert_eq!(open(&storage, &batch, &block(18), &Tap, &wallet, 100, "open")?.id, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sessions_that_fail_to_time_out_retry_next_block() -> Result<()> {
+        let wallets = [[1u8; 32], [2u8; 32]];
+        let (_temp_dir, storage, vrf_engine) = setup(&[], 0)?;
+        let mut batch = storage.batch();
+        storage.set_params(&ChainParams { session_timeout_blocks: 5, ..Default::default() }, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        let mut games = GameRegistry::default();
+        games.register(Arc::new(Tap))?;
+
+        let mut batch = storage.batch();
+        for wallet in &wallets {
+            let session = open(&storage, &batch, &block(10), &Tap, wallet, 100, "open")?;
+            storage.store_game_session(&session, &mut batch)?;
+        }
+        // The first wallet's refund would overflow its balance
+        storage.set_balance(&wallets[0], u64::MAX, &mut batch)?;
+        storage.set_balance(&wallets[1], 900, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        let mut batch = storage.batch();
+        let events = end_block(&storage, &mut batch, &vrf_engine, &block(15), &games)?;
+        storage.apply_batch(batch)?;
+        assert_eq!(events.len(), 1);
+        assert!(storage.get_game_session(2)?.unwrap().finished);
+        assert_eq!(storage.get_balance(&wallets[1])?, 1_000);
+        let session = storage.get_game_session(1)?.unwrap();
+        assert!(!session.finished);
+        assert_eq!((session.expires_at, session.last_action_height), (16, 10));
+        assert_eq!(storage.get_balance(&wallets[0])?, u64::MAX);
+
+        let mut batch = storage.batch();
+        storage.set_balance(&wallets[0], 900, &mut batch)?;
+        let events = end_block(&storage, &mut batch, &vrf_engine, &block(16), &games)?;
+        storage.apply_batch(batch)?;
+        assert_eq!(events.len(), 1);
+        assert!(storage.get_game_session(1)?.unwrap().finished);
+        assert_eq!(storage.get_balance(&wallets[0])?, 1_000);
+        Ok(())
+    }
+}
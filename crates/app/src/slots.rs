@@ -0,0 +1,421 @@
+//! Slots: reel strips, paylines and paytables come from the chain params, so a
+//! new machine ships as configuration rather than code
+//!
+//! Each reel stops at a position drawn from the bet's VRF output. The stake
+//! covers every payline, and each winning line pays its paytable entry in
+//! hundredths of the stake. A machine's theoretical return to player is computed
+//! exactly when the params are loaded, and machines paying back more than
+//! `max_slot_rtp_bps`, or more than the house edge leaves, are refused.
+
+use anyhow::{ensure, Context, Result};
+use mychain_storage::{Storage, StorageBatch};
+use mychain_types::{
+    BetKind, BetRecord, ChainParams, SignedTx, SlotMachine, Tx, TxKind, TxSlots, BPS_DENOMINATOR,
+    MAX_SLOT_PAYLINES, MAX_SLOT_REELS, MAX_SLOT_ROWS, MAX_SLOT_STOPS,
+};
+use tendermint::abci::Event;
+
+use crate::codes::{self, TxRejection};
+use crate::game::{self, Game};
+use crate::vrf::VrfEngine;
+use crate::{accounts, params, BlockContext};
+
+/// Paytable amounts are in hundredths of the stake
+const PAYS_DENOMINATOR: u128 = 100;
+
+/// Slot machines defined in the chain params
+pub struct Slots;
+
+impl Game for Slots {
+    fn id(&self) -> &'static str {
+        "slots"
+    }
+
+    fn tx_kinds(&self) -> &'static [TxKind] {
+        &[TxKind::Slots]
+    }
+
+    fn exposure(&self, storage: &Storage, signed: &SignedTx) -> Result<u64, TxRejection> {
+        let Tx::Slots(bet) = &signed.tx else {
+            return Err(game::unhandled(self.id(), &signed.tx));
+        };
+        let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+        params::check_bet(&chain_params, bet.amount)?;
+        machine(&chain_params, bet.machine)?;
+        Ok(bet.amount)
+    }
+
+    fn settle(
+        &self,
+        storage: &Storage,
+        batch: &mut StorageBatch,
+        vrf_engine: &VrfEngine,
+        block: &BlockContext,
+        signed: &SignedTx,
+        tx_hash: [u8; 32],
+    ) -> Result<Event, TxRejection> {
+        match &signed.tx {
+            Tx::Slots(tx) => execute_slots(storage, batch, vrf_engine, block, tx, tx_hash),
+            tx => Err(game::unhandled(self.id(), tx)),
+        }
+    }
+}
+
+/// Check a machine's layout and that its return to player is at most `max_rtp_bps`
+pub fn validate_machine(machine: &SlotMachine, max_rtp_bps: u64) -> Result<()> {
+    ensure!(
+        (1..=MAX_SLOT_REELS).contains(&machine.reels.len()),
+        "Machine must have 1 to {} reels",
+        MAX_SLOT_REELS
+    );
+    ensure!((1..=MAX_SLOT_ROWS).contains(&machine.rows), "Machine must show 1 to {} rows", MAX_SLOT_ROWS);
+    for (index, reel) in machine.reels.iter().enumerate() {
+        ensure!(
+            (machine.rows as usize..=MAX_SLOT_STOPS).contains(&reel.len()),
+            "Reel {} must have {} to {} stops, got {}",
+            index,
+            machine.rows,
+            MAX_SLOT_STOPS,
+            reel.len()
+        );
+    }
+    ensure!(
+        (1..=MAX_SLOT_PAYLINES).contains(&machine.paylines.len()),
+        "Machine must have 1 to {} paylines",
+        MAX_SLOT_PAYLINES
+    );
+    for (index, line) in machine.paylines.iter().enumerate() {
+        ensure!(
+            line.len() == machine.reels.len() && line.iter().all(|&row| row < machine.rows),
+            "Payline {} must cross one of the {} rows on each reel",
+            index,
+            machine.rows
+        );
+    }
+    for entry in &machine.paytable {
+        ensure!(
+            (1..=machine.reels.len()).contains(&(entry.count as usize)),
+            "Paytable run of {} symbol {} on {} reels",
+            entry.count,
+            entry.symbol,
+            machine.reels.len()
+        );
+    }
+    let rtp = rtp_bps(machine).context("Paytable is too large to price")?;
+    ensure!(
+        rtp <= max_rtp_bps as u128,
+        "Machine would pay back {} bps of stakes, above the maximum of {}",
+        rtp,
+        max_rtp_bps
+    );
+    Ok(())
+}
+
+/// Theoretical return to player of a machine, in basis points rounded up;
+/// `None` if the paytable is too large to price
+///
+/// Every stop is equally likely and the reels are independent, so each payline
+/// sees the same symbol distribution. A line's expected pay is summed over the
+/// symbol on the first reel and the exact length of its run, over all
+/// `product(reel lengths)` outcomes.
+pub fn rtp_bps(machine: &SlotMachine) -> Option<u128> {
+    let lengths: Vec<u128> = machine.reels.iter().map(|reel| reel.len() as u128).collect();
+    let outcomes = lengths.iter().try_fold(1u128, |acc, &len| acc.checked_mul(len))?;
+
+    let mut symbols = machine.reels.first()?.clone();
+    symbols.sort_unstable();
+    symbols.dedup();
+
+    // Sum of pays over every outcome of one line
+    let mut total_pays = 0u128;
+    for symbol in symbols {
+        let counts: Vec<u128> = machine
+            .reels
+            .iter()
+            .map(|reel| reel.iter().filter(|&&s| s == symbol).count() as u128)
+            .collect();
+        // Outcomes whose first `run` reels all show the symbol
+        let mut prefix = 1u128;
+        for run in 1..=lengths.len() {
+            prefix = prefix.checked_mul(counts[run - 1])?;
+            let pays = line_pays(machine, symbol, run);
+            if prefix == 0 || pays == 0 {
+                continue;
+            }
+            // ...and whose next reel, if any, breaks the run
+            let breaks = match lengths.get(run) {
+                Some(&len) => len - counts[run],
+                None => 1,
+            };
+            let rest = lengths.iter().skip(run + 1).try_fold(1u128, |acc, &len| acc.checked_mul(len))?;
+            let exact = prefix.checked_mul(breaks)?.checked_mul(rest)?;
+            total_pays = total_pays.checked_add(exact.checked_mul(pays as u128)?)?;
+        }
+    }
+
+    let numerator = total_pays
+        .checked_mul(machine.paylines.len() as u128)?
+        .checked_mul(BPS_DENOMINATOR as u128)?;
+    let denominator = outcomes.checked_mul(PAYS_DENOMINATOR)?;
+    Some(numerator.div_ceil(denominator))
+}
+
+/// Machine with the given id
+pub fn machine(params: &ChainParams, id: u32) -> Result<&SlotMachine, TxRejection> {
+    params
+        .slot_machines
+        .iter()
+        .find(|machine| machine.id == id)
+        .ok_or_else(|| TxRejection::new(codes::INVALID_BET, format!("No slot machine {}", id)))
+}
+
+/// Symbols shown in the window for the given stops, row by row
+pub fn window(machine: &SlotMachine, stops: &[u16]) -> Vec<Vec<u8>> {
+    (0..machine.rows as usize)
+        .map(|row| {
+            machine
+                .reels
+                .iter()
+                .zip(stops)
+                .map(|(reel, &stop)| reel[(stop as usize + row) % reel.len()])
+                .collect()
+        })
+        .collect()
+}
+
+/// Pays of every payline for the given stops, in hundredths of the stake
+pub fn spin_pays(machine: &SlotMachine, stops: &[u16]) -> Vec<u64> {
+    let window = window(machine, stops);
+    machine
+        .paylines
+        .iter()
+        .map(|line| {
+            let symbols: Vec<u8> = line.iter().enumerate().map(|(reel, &row)| window[row as usize][reel]).collect();
+            let run = symbols.iter().take_while(|&&s| s == symbols[0]).count();
+            line_pays(machine, symbols[0], run)
+        })
+        .collect()
+}
+
+/// Payout of a spin whose lines pay `pays` hundredths of the stake in total, rounded down
+pub fn payout(amount: u64, pays: u128) -> Result<u64, TxRejection> {
+    let payout = amount as u128 * pays / PAYS_DENOMINATOR;
+    u64::try_from(payout).map_err(|_| TxRejection::new(codes::BALANCE_OVERFLOW, "Payout overflow"))
+}
+
+/// Settle a slots bet: debit the stake, stop the reels with the VRF, credit the winning lines
+pub fn execute_slots(
+    storage: &Storage,
+    batch: &mut StorageBatch,
+    vrf_engine: &VrfEngine,
+    block: &BlockContext,
+    tx: &TxSlots,
+    tx_hash: [u8; 32],
+) -> Result<Event, TxRejection> {
+    let chain_params = params::load(storage).map_err(TxRejection::internal)?;
+    params::check_bet(&chain_params, tx.amount)?;
+    let machine = machine(&chain_params, tx.machine)?;
+
+    let balance = storage.get_pending_balance(&tx.wallet, batch).map_err(TxRejection::internal)?;
+    let balance = accounts::debit(balance, tx.amount)?;
+
+    // The stops are not known yet, so the stake must be payable on the best spin
+    payout(tx.amount, max_spin_pays(machine))?;
+
+    let (vrf_message, vrf_proof, vrf_output) = vrf_engine
+        .prove_bet(&block.chain_id, block.height, &block.block_random, &tx_hash, &tx.wallet, tx.nonce)
+        .map_err(TxRejection::internal)?;
+    let lengths: Vec<usize> = machine.reels.iter().map(Vec::len).collect();
+    let stops = VrfEngine::derive_slot_stops(&vrf_output, &lengths);
+    let pays: u128 = spin_pays(machine, &stops).into_iter().map(u128::from).sum();
+    let win_payout = payout(tx.amount, pays)?;
+    let record = BetRecord {
+        wallet: tx.wallet,
+        amount: tx.amount,
+        kind: BetKind::Slots { machine: tx.machine, stops: stops.clone() },
+        nonce: tx.nonce,
+        vrf_message,
+        vrf_proof,
+        vrf_output,
+        won: win_payout > tx.amount,
+        payout: win_payout,
+        height: block.height,
+        tx_hash,
+    };
+    let balance = accounts::credit(balance, record.payout)?;
+
+    storage.store_bet(&tx_hash, &record, batch).map_err(TxRejection::internal)?;
+    storage.set_balance(&tx.wallet, balance, batch).map_err(TxRejection::internal)?;
+
+    let stops = stops.iter().map(u16::to_string).collect::<Vec<_>>().join(",");
+    Ok(Event {
+        kind: "slots".to_string(),
+        attributes: vec![
+            ("wallet".to_string(), hex::encode(record.wallet)).into(),
+            ("amount".to_string(), record.amount.to_string()).into(),
+            ("machine".to_string(), tx.machine.to_string()).into(),
+            ("stops".to_string(), stops).into(),
+            ("won".to_string(), record.won.to_string()).into(),
+            ("payout".to_string(), record.payout.to_string()).into(),
+            ("tx_hash".to_string(), hex::encode(record.tx_hash)).into(),
+            ("vrf_proof".to_string(), hex::encode(&record.vrf_proof)).into(),
+            ("vrf_output".to_string(), hex::encode(&record.vrf_output)).into(),
+        ],
+    })
+}
+
+/// Pays of a line whose run from the first reel is `run` of `symbol`: its best
+/// paytable entry, or 0
+fn line_pays(machine: &SlotMachine, symbol: u8, run: usize) -> u64 {
+    machine
+        .paytable
+        .iter()
+        .filter(|entry| entry.symbol == symbol && entry.count as usize <= run)
+        .map(|entry| entry.pays)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Upper bound on the pays of one spin: every line paying the best entry
+fn max_spin_pays(machine: &SlotMachine) -> u128 {
+    let best = machine.paytable.iter().map(|entry| entry.pays).max().unwrap_or(0);
+    best as u128 * machine.paylines.len() as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block, setup};
+    use mychain_types::SlotPay;
+
+    /// Three reels of ten stops, one line across the middle row
+    fn three_reel_machine(pays: u64) -> SlotMachine {
+        let reel = vec![7, 1, 1, 1, 1, 2, 2, 2, 2, 2];
+        SlotMachine {
+            id: 1,
+            reels: vec![reel.clone(), reel.clone(), reel],
+            rows: 3,
+            paylines: vec![vec![1, 1, 1]],
+            paytable: vec![
+                SlotPay { symbol: 7, count: 3, pays },
+                SlotPay { symbol: 1, count: 3, pays: 500 },
+                SlotPay { symbol: 1, count: 2, pays: 100 },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_rtp_is_exact() {
+        // 7-7-7: 1/1000 at `pays`; 1-1-1: 64/1000 at 5x; 1-1-x: 4*4*6/1000 at 1x
+        let machine = three_reel_machine(10_000);
+        let expected = (10_000 + 64 * 500 + 96 * 100) * 10_000 / (1_000 * 100);
+        assert_eq!(rtp_bps(&machine), Some(expected));
+        assert_eq!(rtp_bps(&machine), Some(5_160));
+
+        // Every line has the same expectation
+        let machine = SlotMachine { paylines: vec![vec![0, 0, 0], vec![1, 2, 0]], ..machine };
+        assert_eq!(rtp_bps(&machine), Some(10_320));
+
+        // Rounded up
+        let machine = SlotMachine {
+            reels: vec![vec![1, 2, 3]],
+            rows: 1,
+            paylines: vec![vec![0]],
+            paytable: vec![SlotPay { symbol: 1, count: 1, pays: 100 }],
+            id: 2,
+        };
+        assert_eq!(rtp_bps(&machine), Some(3_334));
+    }
+
+    #[test]
+    fn test_rtp_matches_enumeration() {
+        let machine = SlotMachine {
+            paylines: vec![vec![0, 1, 2], vec![2, 1, 0], vec![1, 1, 1]],
+            ..three_reel_machine(2_500)
+        };
+        let mut total = 0u128;
+        for a in 0..10 {
+            for b in 0..10 {
+                for c in 0..10 {
+                    total += spin_pays(&machine, &[a, b, c]).into_iter().map(u128::from).sum::<u128>();
+                }
+            }
+        }
+        assert_eq!(rtp_bps(&machine), Some((total * 10_000).div_ceil(1_000 * 100)));
+    }
+
+    #[test]
+    fn test_validate_machine() {
+        let machine = three_reel_machine(10_000);
+        assert!(validate_machine(&machine, 10_000).is_ok());
+        assert!(validate_machine(&machine, 5_160).is_ok());
+        assert!(validate_machine(&machine, 5_159).is_err());
+        // A jackpot large enough to pay back more than is staked
+        assert!(validate_machine(&three_reel_machine(1_000_000), 10_000).is_err());
+
+        assert!(validate_machine(&SlotMachine { reels: vec![], paylines: vec![vec![]], ..machine.clone() }, 10_000).is_err());
+        assert!(validate_machine(&SlotMachine { rows: 0, ..machine.clone() }, 10_000).is_err());
+        assert!(validate_machine(&SlotMachine { rows: 11, ..machine.clone() }, 10_000).is_err());
+        assert!(validate_machine(&SlotMachine { paylines: vec![], ..machine.clone() }, 10_000).is_err());
+        assert!(validate_machine(&SlotMachine { paylines: vec![vec![1, 1]], ..machine.clone() }, 10_000).is_err());
+        assert!(validate_machine(&SlotMachine { paylines: vec![vec![1, 3, 1]], ..machine.clone() }, 10_000).is_err());
+        let paytable = vec![SlotPay { symbol: 7, count: 4, pays: 100 }];
+        assert!(validate_machine(&SlotMachine { paytable, ..machine.clone() }, 10_000).is_err());
+        let paytable = vec![SlotPay { symbol: 7, count: 1, pays: u64::MAX }];
+        assert!(validate_machine(&SlotMachine { paytable, ..machine }, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_spin_pays() {
+        let machine = SlotMachine { paylines: vec![vec![0, 0, 0], vec![1, 1, 1], vec![0, 1, 2]], ..three_reel_machine(10_000) };
+        // Top row 7-7-7, middle row 1-1-1, diagonal 7-1-1
+        assert_eq!(window(&machine, &[0, 0, 0])[0], vec![7, 7, 7]);
+        assert_eq!(spin_pays(&machine, &[0, 0, 0]), vec![10_000, 500, 0]);
+        // Stops wrap around the strip: top row 2-2-7, middle 7-7-1
+        assert_eq!(spin_pays(&machine, &[9, 9, 0]), vec![0, 0, 0]);
+        // A run broken on the last reel still pays its two-reel entry
+        assert_eq!(spin_pays(&machine, &[1, 1, 5]), vec![100, 100, 100]);
+
+        assert_eq!(payout(100, 10_600), Ok(10_600));
+        assert_eq!(payout(3, 50), Ok(1));
+        assert_eq!(payout(u64::MAX, 200).unwrap_err().code, codes::BALANCE_OVERFLOW);
+    }
+
+    #[test]
+    fn test_execute_slots() -> anyhow::Result<()> {
+        let wallet = [1u8; 32];
+        let (_temp_dir, storage, vrf_engine) = setup(&[wallet], 1_000)?;
+        let chain_params = ChainParams { slot_machines: vec![three_reel_machine(10_000)], ..Default::default() };
+        let mut batch = storage.batch();
+        storage.set_params(&chain_params, &mut batch)?;
+        storage.apply_batch(batch)?;
+        let block = block(5);
+        let tx = TxSlots { wallet, amount: 100, machine: 1, nonce: 0 };
+
+        let mut batch = storage.batch();
+        execute_slots(&storage, &mut batch, &vrf_engine, &block, &tx, [9u8; 32])?;
+        storage.apply_batch(batch)?;
+
+        // The stored stops come from the VRF output and decide the balance
+        let record = storage.get_bet(&[9u8; 32])?.unwrap();
+        let BetKind::Slots { machine: id, stops } = record.kind else {
+            panic!("expected a slots record, got {:?}", record.kind);
+        };
+        assert_eq!(id, 1);
+        assert_eq!(stops, VrfEngine::derive_slot_stops(&record.vrf_output, &[10, 10, 10]));
+        let pays: u64 = spin_pays(&chain_params.slot_machines[0], &stops).iter().sum();
+        assert_eq!(record.payout, pays);
+        // A spin paying back less than the stake is a loss
+        assert_eq!(record.won, pays > 100);
+        assert_eq!(storage.get_balance(&wallet)?, 900 + pays);
+
+        // Machines that are not configured cannot be played
+        let tx = TxSlots { machine: 2, nonce: 1, ..tx };
+        let mut batch = storage.batch();
+        let rejection = execute_slots(&storage, &mut batch, &vrf_engine, &block, &tx, [8u8; 32]).unwrap_err();
+        assert_eq!(rejection.code, codes::INVALID_BET);
+        assert!(storage.get_bet(&[8u8; 32])?.is_none());
+
+        Ok(())
+    }
+}
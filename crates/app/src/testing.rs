@@ -0,0 +1,27 @@
+//! Fixtures shared by the games' tests
+
+use anyhow::Result;
+use mychain_storage::Storage;
+use tempfile::TempDir;
+
+use crate::vrf::VrfEngine;
+use crate::BlockContext;
+
+/// Storage in a fresh directory with every wallet holding `balance`, and a VRF engine
+///
+/// The directory is removed when the returned `TempDir` is dropped, so keep it bound.
+pub fn setup(wallets: &[[u8; 32]], balance: u64) -> Result<(TempDir, Storage, VrfEngine)> {
+    let temp_dir = tempfile::tempdir()?;
+    let storage = Storage::open(temp_dir.path())?;
+    let mut batch = storage.batch();
+    for wallet in wallets {
+        storage.set_balance(wallet, balance, &mut batch)?;
+    }
+    storage.apply_batch(batch)?;
+    Ok((temp_dir, storage, VrfEngine::generate()))
+}
+
+/// Context of the block at `height`
+pub fn block(height: u64) -> BlockContext {
+    BlockContext { height, block_random: [7u8; 32], chain_id: "test_chain".to_string() }
+}
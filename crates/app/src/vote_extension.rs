@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use mychain_storage::Storage;
+use serde::{Deserialize, Serialize};
+
+use crate::vrf::VrfEngine;
+
+/// Prefix marking the randomness transaction injected by the proposer.
+/// User transactions starting with it are rejected in CheckTx.
+pub const RANDOMNESS_TX_PREFIX: &[u8] = b"MYCHAIN:RAND:v1";
+
+/// Length of a CometBFT validator address
+pub const VALIDATOR_ADDRESS_LENGTH: usize = 20;
+
+/// A validator's VRF contribution, carried in its vote extension
+///
+/// The VRF input is [`VrfEngine::compute_extension_message`] for the height and
+/// block hash being voted on, so a validator has exactly one valid contribution
+/// per block and cannot grind it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VrfContribution {
+    /// VRF proof
+    pub vrf_proof: Vec<u8>,
+    /// VRF output
+    pub vrf_output: Vec<u8>,
+}
+
+impl VrfContribution {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+
+    /// Check the contribution against the validator's registered VRF key
+    pub fn verify(
+        &self,
+        validator_vrf_pk: &[u8],
+        chain_id: &str,
+        height: u64,
+        block_hash: &[u8],
+    ) -> Result<bool> {
+        let message = VrfEngine::compute_extension_message(chain_id, height, block_hash);
+        VrfEngine::verify(validator_vrf_pk, &message, &self.vrf_proof, &self.vrf_output)
+    }
+}
+
+/// Contribution of a single validator inside a [`RandomnessTx`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ValidatorContribution {
+    /// Validator address
+    pub validator: [u8; VALIDATOR_ADDRESS_LENGTH],
+    /// The validator's vote extension
+    pub contribution: VrfContribution,
+}
+
+/// Vote extension contributions of block `height`, injected by the proposer of
+/// block `height + 1` as its first transaction
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RandomnessTx {
+    /// Height whose precommits carried the contributions
+    pub height: u64,
+    /// Contributions sorted by validator address
+    pub contributions: Vec<ValidatorContribution>,
+}
+
+impl RandomnessTx {
+    /// Check whether raw transaction bytes carry the randomness prefix
+    pub fn is_randomness_tx(data: &[u8]) -> bool {
+        data.starts_with(RANDOMNESS_TX_PREFIX)
+    }
+
+    /// Encode as `RANDOMNESS_TX_PREFIX || bincode(self)`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        let mut bytes = RANDOMNESS_TX_PREFIX.to_vec();
+        bytes.extend(bincode::serialize(self)?);
+        Ok(bytes)
+    }
+
+    /// Decode a transaction produced by [`RandomnessTx::to_bytes`]
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let payload = data
+            .strip_prefix(RANDOMNESS_TX_PREFIX)
+            .context("Missing randomness tx prefix")?;
+        bincode::deserialize(payload).context("Failed to decode randomness tx")
+    }
+
+    /// Aggregate all contributions into a single 32-byte seed
+    /// aggregate = blake3('MYCHAIN:VRF:AGG:v1' || height || (validator || vrf_output)*)
+    pub fn aggregate(&self) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"MYCHAIN:VRF:AGG:v1");
+        hasher.update(&self.height.to_le_bytes());
+        for entry in &self.contributions {
+            hasher.update(&entry.validator);
+            hasher.update(&entry.contribution.vrf_output);
+        }
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Verify the transaction as proposed for block `self.height + 1`
+    ///
+    /// `block_hash` is the hash of block `self.height` and `signers` are the
+    /// validators whose precommit for it made it into the last commit. Every signer
+    /// with a registered VRF key must be present with a valid contribution, so the
+    /// proposer cannot bias the seed by leaving contributions out.
+    pub fn verify(
+        &self,
+        storage: &Storage,
+        chain_id: &str,
+        block_hash: &[u8],
+        signers: &[[u8; VALIDATOR_ADDRESS_LENGTH]],
+    ) -> Result<()> {
+        // Canonical order: strictly ascending, which also rules out duplicates
+        if self.contributions.windows(2).any(|w| w[0].validator >= w[1].validator) {
+            anyhow::bail!("Contributions are not sorted by validator address");
+        }
+
+        for entry in &self.contributions {
+            if !signers.contains(&entry.validator) {
+                anyhow::bail!(
+                    "Contribution from {} which did not sign the last commit",
+                    hex::encode(entry.validator)
+                );
+            }
+            let vrf_pk = storage.get_validator_vrf_key(&entry.validator)?.with_context(|| {
+                format!("Validator {} has no registered VRF key", hex::encode(entry.validator))
+            })?;
+            if !entry.contribution.verify(&vrf_pk, chain_id, self.height, block_hash)? {
+                anyhow::bail!("Invalid VRF contribution from {}", hex::encode(entry.validator));
+            }
+        }
+
+        for signer in signers {
+            let included = self.contributions.iter().any(|entry| &entry.validator == signer);
+            if !included && storage.get_validator_vrf_key(signer)?.is_some() {
+                anyhow::bail!("Missing VRF contribution from {}", hex::encode(signer));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether block `height` must carry the vote extension contributions of `height - 1`
+///
+/// CometBFT produces extensions from `enable_height` onwards (0 or unset disables
+/// them), and they are first available to the proposer of the next block.
+pub fn contributions_expected(enable_height: Option<u64>, height: u64) -> bool {
+    matches!(enable_height, Some(enable) if enable > 0 && height > enable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contribution(engine: &VrfEngine, chain_id: &str, height: u64, block_hash: &[u8]) -> VrfContribution {
+        let message = VrfEngine::compute_extension_message(chain_id, height, block_hash);
+        let (vrf_output, vrf_proof) = engine.prove(&message).unwrap();
+        VrfContribution { vrf_proof, vrf_output }
+    }
+
+    fn storage_with_keys(keys: &[([u8; 20], &VrfEngine)]) -> Result<(tempfile::TempDir, Storage)> {
+        let temp_dir = tempfile::tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        let mut batch = storage.batch();
+        for (address, engine) in keys {
+            storage.set_validator_vrf_key(address, &engine.public_key(), &mut batch)?;
+        }
+        storage.apply_batch(batch)?;
+        Ok((temp_dir, storage))
+    }
+
+    #[test]
+    fn test_randomness_tx_roundtrip() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let tx = RandomnessTx {
+            height: 7,
+            contributions: vec![ValidatorContribution {
+                validator: [1u8; 20],
+                contribution: contribution(&engine, "test_chain", 7, &[9u8; 32]),
+            }],
+        };
+
+        let bytes = tx.to_bytes()?;
+        assert!(RandomnessTx::is_randomness_tx(&bytes));
+        assert_eq!(RandomnessTx::from_bytes(&bytes)?, tx);
+        assert!(RandomnessTx::from_bytes(&bytes[1..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_contributions() -> Result<()> {
+        let (a, b) = ([1u8; 20], [2u8; 20]);
+        let (engine_a, engine_b) = (VrfEngine::generate(), VrfEngine::generate());
+        let (_dir, storage) = storage_with_keys(&[(a, &engine_a), (b, &engine_b)])?;
+
+        let block_hash = [5u8; 32];
+        let tx = RandomnessTx {
+            height: 10,
+            contributions: vec![
+                ValidatorContribution { validator: a, contribution: contribution(&engine_a, "c", 10, &block_hash) },
+                ValidatorContribution { validator: b, contribution: contribution(&engine_b, "c", 10, &block_hash) },
+            ],
+        };
+        tx.verify(&storage, "c", &block_hash, &[a, b])?;
+
+        // Omitting a registered signer is rejected
+        let mut partial = tx.clone();
+        partial.contributions.truncate(1);
+        assert!(partial.verify(&storage, "c", &block_hash, &[a, b]).is_err());
+        partial.verify(&storage, "c", &block_hash, &[a])?;
+
+        // Contributions for another block are rejected
+        assert!(tx.verify(&storage, "c", &[6u8; 32], &[a, b]).is_err());
+
+        // Swapped keys are rejected
+        let mut swapped = tx.clone();
+        swapped.contributions[0].contribution = contribution(&engine_b, "c", 10, &block_hash);
+        assert!(swapped.verify(&storage, "c", &block_hash, &[a, b]).is_err());
+
+        // Unsorted contributions are rejected
+        let mut unsorted = tx.clone();
+        unsorted.contributions.reverse();
+        assert!(unsorted.verify(&storage, "c", &block_hash, &[a, b]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_depends_on_every_contribution() {
+        let engine = VrfEngine::generate();
+        let base = RandomnessTx {
+            height: 3,
+            contributions: vec![ValidatorContribution {
+                validator: [1u8; 20],
+                contribution: contribution(&engine, "c", 3, &[0u8; 32]),
+            }],
+        };
+        let mut other = base.clone();
+        other.contributions[0].contribution = contribution(&engine, "c", 3, &[1u8; 32]);
+
+        assert_eq!(base.aggregate(), base.clone().aggregate());
+        assert_ne!(base.aggregate(), other.aggregate());
+    }
+
+    #[test]
+    fn test_contributions_expected() {
+        assert!(!contributions_expected(None, 5));
+        assert!(!contributions_expected(Some(0), 5));
+        assert!(!contributions_expected(Some(5), 5));
+        assert!(contributions_expected(Some(5), 6));
+    }
+}
@@ -1,9 +1,43 @@
-use anyhow::Result;
-use fastcrypto::vrf::VRFKeyPair;
-use fastcrypto::vrf::ecvrf::ECVRFKeyPair;
+use anyhow::{Context, Result};
+use fastcrypto::vrf::{VRFKeyPair, VRFProof};
+use fastcrypto::vrf::ecvrf::{ECVRFKeyPair, ECVRFPrivateKey, ECVRFProof, ECVRFPublicKey};
+use mychain_types::{DICE_SIDES, ROULETTE_POCKETS};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 use blake3;
 
+/// File name of the VRF key inside the node data directory
+pub const VRF_KEY_FILE: &str = "vrf_key.json";
+
+/// File name of the validator's vote extension VRF key inside the node data directory
+pub const VALIDATOR_VRF_KEY_FILE: &str = "validator_vrf_key.json";
+
+/// Length of an encoded VRF private key
+pub const VRF_PRIVATE_KEY_LENGTH: usize = 32;
+
+/// Length of an encoded VRF public key
+pub const VRF_PUBLIC_KEY_LENGTH: usize = 32;
+
+/// Length of an encoded VRF proof: gamma (32) || challenge (16) || response scalar (32)
+pub const VRF_PROOF_LENGTH: usize = 80;
+
+/// Length of a VRF output (SHA-512 proof-to-hash)
+pub const VRF_OUTPUT_LENGTH: usize = 64;
+
+/// A proven game outcome: (vrf_message, vrf_proof, vrf_output, outcome)
+pub type VrfOutcome<T> = (Vec<u8>, Vec<u8>, Vec<u8>, T);
+
+/// On-disk representation of the VRF keypair (hex encoded)
+#[derive(Serialize, Deserialize)]
+struct VrfKeyFile {
+    public_key: String,
+    private_key: String,
+}
+
 /// VRF Engine using fastcrypto ECVRF with Ristretto255
 /// 
 /// Provides provably fair randomness for coin flip outcomes
@@ -19,43 +53,120 @@ impl VrfEngine {
         Self { keypair }
     }
 
-    /// Load VRF engine from private key bytes (placeholder for POC)
-    pub fn from_private_key(_private_key_bytes: &[u8]) -> Result<Self> {
-        // For POC, just generate a new keypair since fastcrypto ECVRF 
-        // serialization API is complex
-        Ok(Self::generate())
+    /// Load VRF engine from private key bytes (32-byte canonical Ristretto255 scalar)
+    pub fn from_private_key(private_key_bytes: &[u8]) -> Result<Self> {
+        if private_key_bytes.len() != VRF_PRIVATE_KEY_LENGTH {
+            anyhow::bail!(
+                "Invalid VRF private key length: expected {}, got {}",
+                VRF_PRIVATE_KEY_LENGTH,
+                private_key_bytes.len()
+            );
+        }
+        let sk: ECVRFPrivateKey = bincode::deserialize(private_key_bytes)
+            .context("Invalid VRF private key encoding")?;
+        Ok(Self { keypair: ECVRFKeyPair::from(sk) })
     }
 
-    /// Get the VRF public key as bytes (placeholder for POC)
+    /// Get the VRF public key as bytes (32-byte compressed Ristretto255 point)
     pub fn public_key(&self) -> Vec<u8> {
-        // For now, return a fixed placeholder until we can properly serialize ECVRF keys
-        vec![1u8; 32] // Placeholder
+        bincode::serialize(&self.keypair.pk).expect("VRF public key serialization cannot fail")
     }
 
-    /// Get the VRF private key as bytes (placeholder for POC)
+    /// Get the VRF private key as bytes (32-byte canonical Ristretto255 scalar)
     pub fn private_key(&self) -> Vec<u8> {
-        // For now, return a fixed placeholder until we can properly serialize ECVRF keys
-        vec![2u8; 32] // Placeholder
+        bincode::serialize(&self.keypair.sk).expect("VRF private key serialization cannot fail")
+    }
+
+    /// Load VRF engine from a key file written by [`VrfEngine::save_key_file`]
+    ///
+    /// The stored public key is checked against the one derived from the private key,
+    /// so a corrupted or hand-edited file is rejected instead of silently signing with
+    /// a different key.
+    pub fn load_key_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read VRF key file {}", path.display()))?;
+        let key_file: VrfKeyFile = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse VRF key file {}", path.display()))?;
+
+        let private_key = hex::decode(&key_file.private_key)
+            .context("Invalid hex in VRF private key")?;
+        let engine = Self::from_private_key(&private_key)?;
+
+        if hex::encode(engine.public_key()) != key_file.public_key.to_lowercase() {
+            anyhow::bail!(
+                "VRF key file {} is inconsistent: public key does not match private key",
+                path.display()
+            );
+        }
+
+        Ok(engine)
+    }
+
+    /// Write the keypair to a JSON key file (owner read/write only on unix)
+    pub fn save_key_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let key_file = VrfKeyFile {
+            public_key: hex::encode(self.public_key()),
+            private_key: hex::encode(self.private_key()),
+        };
+        let contents = serde_json::to_string_pretty(&key_file)?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Failed to create VRF key file {}", path.display()))?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
     }
 
     /// Prove VRF computation and return (output, proof)
+    ///
+    /// The proof uses the canonical encoding `gamma (32) || c (16) || s (32)`,
+    /// see [`VRF_PROOF_LENGTH`]. The output is the 64-byte proof-to-hash value.
     pub fn prove(&self, message: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
         let (output, proof) = self.keypair.output(message);
-        // Serialize output and proof using debug format for POC
-        let proof_bytes = format!("{:?}", proof).into_bytes();
+        let proof_bytes = bincode::serialize(&proof).context("Failed to encode VRF proof")?;
+        debug_assert_eq!(proof_bytes.len(), VRF_PROOF_LENGTH);
         Ok((output.to_vec(), proof_bytes))
     }
 
-    /// Verify a VRF proof (simplified for POC)
+    /// Verify a VRF proof against a public key, message and claimed output
+    ///
+    /// Returns an error only for inputs of the wrong length. Anything else that does
+    /// not check out (undecodable point or scalar, bad proof, output mismatch) is `Ok(false)`.
     pub fn verify(
-        _public_key: &[u8],
-        _message: &[u8], 
-        _proof_bytes: &[u8],
-        _expected_output: &[u8]
+        public_key: &[u8],
+        message: &[u8], 
+        proof_bytes: &[u8],
+        expected_output: &[u8]
     ) -> Result<bool> {
-        // Placeholder verification - always returns true for POC
-        // In production, would properly deserialize and verify
-        Ok(true)
+        if public_key.len() != VRF_PUBLIC_KEY_LENGTH {
+            anyhow::bail!("Invalid VRF public key length: {}", public_key.len());
+        }
+        if proof_bytes.len() != VRF_PROOF_LENGTH {
+            anyhow::bail!("Invalid VRF proof length: {}", proof_bytes.len());
+        }
+        let expected_output: &[u8; VRF_OUTPUT_LENGTH] = match expected_output.try_into() {
+            Ok(output) => output,
+            Err(_) => anyhow::bail!("Invalid VRF output length: {}", expected_output.len()),
+        };
+
+        let Ok(pk) = bincode::deserialize::<ECVRFPublicKey>(public_key) else {
+            return Ok(false);
+        };
+        let Ok(proof) = bincode::deserialize::<ECVRFProof>(proof_bytes) else {
+            return Ok(false);
+        };
+
+        Ok(proof.verify_output(message, &pk, expected_output).is_ok())
     }
 
     /// Compute VRF message for a coin flip transaction
@@ -79,19 +190,163 @@ impl VrfEngine {
         hasher.finalize().to_vec()
     }
 
+    /// Compute VRF message for the shoe of a blackjack deal
+    /// Message format: SHA256('MYCHAIN:VRF:BLACKJACK:v1' || chain_id || height || block_random || tx_hash || wallet || nonce)
+    pub fn compute_blackjack_message(
+        chain_id: &str,
+        height: u64,
+        block_random: &[u8],
+        tx_hash: &[u8],
+        wallet: &[u8],
+        nonce: u64,
+    ) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MYCHAIN:VRF:BLACKJACK:v1");
+        hasher.update(chain_id.as_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update(block_random);
+        hasher.update(tx_hash);
+        hasher.update(wallet);
+        hasher.update(nonce.to_le_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// Compute VRF message for the board of a mines game
+    /// Message format: SHA256('MYCHAIN:VRF:MINES:v1' || chain_id || height || block_random || tx_hash || wallet || nonce)
+    pub fn compute_mines_message(
+        chain_id: &str,
+        height: u64,
+        block_random: &[u8],
+        tx_hash: &[u8],
+        wallet: &[u8],
+        nonce: u64,
+    ) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MYCHAIN:VRF:MINES:v1");
+        hasher.update(chain_id.as_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update(block_random);
+        hasher.update(tx_hash);
+        hasher.update(wallet);
+        hasher.update(nonce.to_le_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// Compute VRF message for the spin of a roulette table
+    /// Message format: SHA256('MYCHAIN:VRF:ROULETTE:v1' || chain_id || height || block_random || table)
+    pub fn compute_spin_message(chain_id: &str, height: u64, block_random: &[u8], table: u32) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MYCHAIN:VRF:ROULETTE:v1");
+        hasher.update(chain_id.as_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update(block_random);
+        hasher.update(table.to_le_bytes());
+        hasher.finalize().to_vec()
+    }
+
+    /// Compute VRF message for the crash point of a crash round
+    /// Message format: SHA256('MYCHAIN:VRF:CRASH:v1' || chain_id || round || height || block_random)
+    pub fn compute_crash_message(chain_id: &str, round: u64, height: u64, block_random: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MYCHAIN:VRF:CRASH:v1");
+        hasher.update(chain_id.as_bytes());
+        hasher.update(round.to_le_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update(block_random);
+        hasher.finalize().to_vec()
+    }
+
+    /// Compute VRF message for the winners of a lottery draw
+    /// Message format: SHA256('MYCHAIN:VRF:LOTTERY:v1' || chain_id || draw || height || block_random)
+    pub fn compute_lottery_message(chain_id: &str, draw: u64, height: u64, block_random: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MYCHAIN:VRF:LOTTERY:v1");
+        hasher.update(chain_id.as_bytes());
+        hasher.update(draw.to_le_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update(block_random);
+        hasher.finalize().to_vec()
+    }
+
+    /// Compute VRF message for a validator's vote extension
+    /// Message format: SHA256('MYCHAIN:VRF:EXT:v1' || chain_id || height || block_hash)
+    pub fn compute_extension_message(chain_id: &str, height: u64, block_hash: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MYCHAIN:VRF:EXT:v1");
+        hasher.update(chain_id.as_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.update(block_hash);
+        hasher.finalize().to_vec()
+    }
+
     /// Derive coin flip result from VRF output
     /// result = blake3(output)[0] & 1
+    ///
+    /// This is the lowest bit of the first [`VrfRng`] word over the output.
     pub fn derive_flip_result(vrf_output: &[u8]) -> bool {
         let hash = blake3::hash(vrf_output);
         (hash.as_bytes()[0] & 1) == 1
     }
 
+    /// Derive a uniform value in `0..n` from VRF output, without modulo bias
+    ///
+    /// The first draw of [`VrfRng`] over the output.
+    pub fn derive_range(vrf_output: &[u8], n: u64) -> u64 {
+        VrfRng::new(vrf_output).range(n)
+    }
+
+    /// Derive a dice roll in `0..DICE_SIDES` from VRF output
+    pub fn derive_dice_roll(vrf_output: &[u8]) -> u16 {
+        Self::derive_range(vrf_output, DICE_SIDES as u64) as u16
+    }
+
+    /// Derive the stop position of each slot machine reel from VRF output
+    ///
+    /// Successive draws of [`VrfRng`], one per reel in order.
+    pub fn derive_slot_stops(vrf_output: &[u8], reel_lengths: &[usize]) -> Vec<u16> {
+        let mut rng = VrfRng::new(vrf_output);
+        reel_lengths.iter().map(|&len| rng.range(len as u64) as u16).collect()
+    }
+
+    /// Derive the bounces of a Plinko ball from VRF output (true = right)
+    ///
+    /// Bit `i` of the first [`VrfRng`] word, lowest bit first, is the bounce on
+    /// row `i` from the top; every bit of the word is fair.
+    pub fn derive_plinko_path(vrf_output: &[u8], rows: u8) -> Vec<bool> {
+        let word = VrfRng::new(vrf_output).next_u64();
+        (0..rows).map(|row| (word >> row) & 1 == 1).collect()
+    }
+
+    /// Derive a roulette number in `0..ROULETTE_POCKETS` from VRF output
+    pub fn derive_roulette_number(vrf_output: &[u8]) -> u8 {
+        Self::derive_range(vrf_output, ROULETTE_POCKETS as u64) as u8
+    }
+
     /// Compute block randomness seed
-    /// block_random[h] = blake3(prev_block_hash || prev_vrf_accum)
-    pub fn compute_block_random(prev_block_hash: &[u8], prev_vrf_accum: &[u8]) -> [u8; 32] {
+    /// block_random[h] = blake3(prev_block_hash || vrf_accum[h])
+    ///
+    /// `vrf_accum[h]` already includes the validator contributions over block h-1
+    /// (see [`VrfEngine::accumulate`]), which are the freshest VRF outputs available.
+    pub fn compute_block_random(prev_block_hash: &[u8], vrf_accum: &[u8]) -> [u8; 32] {
         let mut hasher = blake3::Hasher::new();
         hasher.update(prev_block_hash);
+        hasher.update(vrf_accum);
+        *hasher.finalize().as_bytes()
+    }
+
+    /// Fold a block's aggregated VRF contributions into the running accumulator
+    /// vrf_accum[h] = blake3('MYCHAIN:VRF:ACCUM:v1' || vrf_accum[h-1] || contributions[h])
+    ///
+    /// Blocks without contributions leave the accumulator unchanged, so the chain of
+    /// randomness still depends on every VRF output seen since genesis.
+    pub fn accumulate(prev_vrf_accum: &[u8; 32], contributions: Option<&[u8; 32]>) -> [u8; 32] {
+        let Some(contributions) = contributions else {
+            return *prev_vrf_accum;
+        };
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"MYCHAIN:VRF:ACCUM:v1");
         hasher.update(prev_vrf_accum);
+        hasher.update(contributions);
         *hasher.finalize().as_bytes()
     }
 
@@ -105,7 +360,60 @@ impl VrfEngine {
         tx_hash: &[u8],
         wallet: &[u8],
         nonce: u64,
-    ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>, bool)> {
+    ) -> Result<VrfOutcome<bool>> {
+        let (message, proof, output) = self.prove_bet(chain_id, height, block_random, tx_hash, wallet, nonce)?;
+
+        // Derive flip result
+        let result = Self::derive_flip_result(&output);
+
+        Ok((message, proof, output, result))
+    }
+
+    /// Process a dice transaction
+    /// Returns (vrf_message, vrf_proof, vrf_output, roll)
+    pub fn process_dice(
+        &self,
+        chain_id: &str,
+        height: u64,
+        block_random: &[u8],
+        tx_hash: &[u8],
+        wallet: &[u8],
+        nonce: u64,
+    ) -> Result<VrfOutcome<u16>> {
+        let (message, proof, output) = self.prove_bet(chain_id, height, block_random, tx_hash, wallet, nonce)?;
+        let roll = Self::derive_dice_roll(&output);
+        Ok((message, proof, output, roll))
+    }
+
+    /// Spin a roulette table for a block
+    /// Returns (vrf_message, vrf_proof, vrf_output, number)
+    pub fn process_spin(
+        &self,
+        chain_id: &str,
+        height: u64,
+        block_random: &[u8],
+        table: u32,
+    ) -> Result<VrfOutcome<u8>> {
+        let message = Self::compute_spin_message(chain_id, height, block_random, table);
+        let (output, proof) = self.prove(&message)?;
+        let number = Self::derive_roulette_number(&output);
+        Ok((message, proof, output, number))
+    }
+
+    /// Prove the VRF message of a bet
+    /// Returns (vrf_message, vrf_proof, vrf_output)
+    ///
+    /// Every game uses the flip message: the transaction hash already makes it
+    /// unique to the bet, and verifiers only need one message format.
+    pub fn prove_bet(
+        &self,
+        chain_id: &str,
+        height: u64,
+        block_random: &[u8],
+        tx_hash: &[u8],
+        wallet: &[u8],
+        nonce: u64,
+    ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
         // Compute VRF message
         let message = Self::compute_flip_message(
             chain_id, height, block_random, tx_hash, wallet, nonce
@@ -114,10 +422,96 @@ impl VrfEngine {
         // Generate VRF proof and output
         let (output, proof) = self.prove(&message)?;
 
-        // Derive flip result
-        let result = Self::derive_flip_result(&output);
+        Ok((message, proof, output))
+    }
+}
 
-        Ok((message, proof, output, result))
+/// Deterministic random number generator over a VRF output
+///
+/// Reads the blake3 XOF of the VRF output as a stream of little-endian u64 words,
+/// so anyone holding a verified output can replay every draw of a game:
+///
+/// - `next_u64`: the next word
+/// - `range(n)`: words below `2^64 mod n` are rejected, the first other word `w`
+///   gives `w mod n`, so every value in `0..n` is equally likely
+/// - `shuffle`: Fisher-Yates from the back, swapping `items[i]` with
+///   `items[range(i + 1)]` for `i = len-1, ..., 1`
+/// - `sample(n, k)`: partial Fisher-Yates from the front over `[0, 1, ..., n-1]`,
+///   swapping position `i` with `i + range(n - i)` for `i = 0, ..., k-1`, and
+///   returning the first `k` positions in draw order
+///
+/// Test vectors, for the 64-byte output `[0x00, 0x01, ..., 0x3f]`:
+///
+/// - `next_u64` x3: `0xd45c4aea4171ed4e`, `0xe2463fd26b6088b7`, `0x7ddcacebac9caf12`
+/// - `range(10_000)` x3: `830`, `71`, `7938`
+/// - `shuffle([0, 1, ..., 9])`: `[3, 1, 6, 7, 5, 9, 4, 2, 8, 0]`
+/// - `sample(49, 6)`: `[30, 24, 26, 16, 37, 39]`
+///
+/// Each vector starts from a fresh generator.
+pub struct VrfRng {
+    reader: blake3::OutputReader,
+}
+
+impl VrfRng {
+    /// Start the stream of a VRF output
+    pub fn new(vrf_output: &[u8]) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(vrf_output);
+        Self { reader: hasher.finalize_xof() }
+    }
+
+    /// Next 64 bits of the stream
+    pub fn next_u64(&mut self) -> u64 {
+        let mut word = [0u8; 8];
+        self.reader.fill(&mut word);
+        u64::from_le_bytes(word)
+    }
+
+    /// Uniform value in `0..n`, by rejection sampling
+    ///
+    /// Fewer than one word in 2^32 is rejected for any `n` below 2^32.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is 0.
+    pub fn range(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "range must not be empty");
+        let rejected_below = n.wrapping_neg() % n;
+        loop {
+            let value = self.next_u64();
+            if value >= rejected_below {
+                return value % n;
+            }
+        }
+    }
+
+    /// Shuffle in place, every permutation being equally likely
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.range(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+
+    /// `k` distinct values from `0..n`, in the order they were drawn
+    ///
+    /// Only the `k` swaps are tracked, so `n` can be far larger than memory.
+    ///
+    /// # Panics
+    ///
+    /// If `k` is greater than `n`.
+    pub fn sample(&mut self, n: usize, k: usize) -> Vec<usize> {
+        assert!(k <= n, "cannot draw {} distinct values out of {}", k, n);
+        // Positions whose value was swapped away; every other position holds itself
+        let mut moved: HashMap<usize, usize> = HashMap::new();
+        (0..k)
+            .map(|i| {
+                let j = i + self.range((n - i) as u64) as usize;
+                let value = moved.get(&j).copied().unwrap_or(j);
+                moved.insert(j, moved.get(&i).copied().unwrap_or(i));
+                value
+            })
+            .collect()
     }
 }
 
@@ -197,4 +591,239 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_derive_range_is_uniform() {
+        for n in [1u64, 2, 6, 10_000, u64::MAX] {
+            assert!(VrfEngine::derive_range(b"output", n) < n);
+        }
+        assert_eq!(VrfEngine::derive_range(b"output", 1), 0);
+        assert_eq!(VrfEngine::derive_range(b"output", 10_000), VrfEngine::derive_range(b"output", 10_000));
+
+        // Every face of a small die comes up about equally often
+        let mut counts = [0u32; 6];
+        for i in 0u32..60_000 {
+            counts[VrfEngine::derive_range(&i.to_le_bytes(), 6) as usize] += 1;
+        }
+        for count in counts {
+            assert!((9_500..10_500).contains(&count), "counts {:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_rng_vectors() {
+        // Published in the VrfRng docs: changing any of these changes game results
+        let output: Vec<u8> = (0u8..64).collect();
+
+        let mut rng = VrfRng::new(&output);
+        assert_eq!(
+            [rng.next_u64(), rng.next_u64(), rng.next_u64()],
+            [0xd45c4aea4171ed4e, 0xe2463fd26b6088b7, 0x7ddcacebac9caf12]
+        );
+
+        let mut rng = VrfRng::new(&output);
+        assert_eq!([rng.range(10_000), rng.range(10_000), rng.range(10_000)], [830, 71, 7938]);
+
+        let mut items: Vec<u32> = (0..10).collect();
+        VrfRng::new(&output).shuffle(&mut items);
+        assert_eq!(items, vec![3, 1, 6, 7, 5, 9, 4, 2, 8, 0]);
+
+        assert_eq!(VrfRng::new(&output).sample(49, 6), vec![30, 24, 26, 16, 37, 39]);
+
+        // Coin flips and dice rolls are draws of the same stream
+        assert_eq!(VrfEngine::derive_flip_result(&output), VrfRng::new(&output).next_u64() & 1 == 1);
+        assert_eq!(VrfEngine::derive_dice_roll(&output), 830);
+        // 0x...ed4e, lowest bit first
+        assert_eq!(
+            VrfEngine::derive_plinko_path(&output, 12),
+            [false, true, true, true, false, false, true, false, true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn test_rng_shuffle_and_sample() {
+        let mut rng = VrfRng::new(b"output");
+
+        let mut items: Vec<u32> = (0..52).collect();
+        rng.shuffle(&mut items);
+        let mut sorted = items.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..52).collect::<Vec<u32>>());
+        rng.shuffle(&mut [0u8; 0]);
+
+        let drawn = rng.sample(25, 5);
+        assert_eq!(drawn.len(), 5);
+        assert!(drawn.iter().all(|&value| value < 25));
+        let mut distinct = drawn.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 5);
+
+        // Drawing everything is a permutation, drawing nothing is empty
+        let mut all = rng.sample(10, 10);
+        all.sort_unstable();
+        assert_eq!(all, (0..10).collect::<Vec<usize>>());
+        assert!(rng.sample(10, 0).is_empty());
+    }
+
+    #[test]
+    fn test_dice_roll_uses_flip_message() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let (msg, proof, output, roll) = engine.process_dice("test_chain", 100, b"random", b"tx", b"wallet", 7)?;
+
+        assert_eq!(msg, VrfEngine::compute_flip_message("test_chain", 100, b"random", b"tx", b"wallet", 7));
+        assert!(roll < DICE_SIDES);
+        assert_eq!(roll, VrfEngine::derive_dice_roll(&output));
+        assert!(VrfEngine::verify(&engine.public_key(), &msg, &proof, &output)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spin_depends_on_table_only_within_a_block() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let (msg1, _, output1, number1) = engine.process_spin("test_chain", 100, b"random", 1)?;
+        let (msg2, _, output2, number2) = engine.process_spin("test_chain", 100, b"random", 1)?;
+        let (msg3, _, _, _) = engine.process_spin("test_chain", 100, b"random", 2)?;
+
+        assert_eq!((msg1.clone(), output1.clone(), number1), (msg2, output2, number2));
+        assert_ne!(msg1, msg3);
+        assert!(number1 < ROULETTE_POCKETS);
+        assert_eq!(number1, VrfEngine::derive_roulette_number(&output1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_private_key_roundtrip() -> Result<()> {
+        let engine = VrfEngine::generate();
+        assert_eq!(engine.public_key().len(), VRF_PUBLIC_KEY_LENGTH);
+        assert_eq!(engine.private_key().len(), VRF_PRIVATE_KEY_LENGTH);
+
+        let restored = VrfEngine::from_private_key(&engine.private_key())?;
+        assert_eq!(engine.public_key(), restored.public_key());
+
+        // Same key must produce the same output for the same message
+        let (output1, _) = engine.prove(b"message")?;
+        let (output2, _) = restored.prove(b"message")?;
+        assert_eq!(output1, output2);
+
+        assert!(VrfEngine::from_private_key(&[0u8; 31]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_file_roundtrip() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join(VRF_KEY_FILE);
+
+        let engine = VrfEngine::generate();
+        engine.save_key_file(&path)?;
+        let loaded = VrfEngine::load_key_file(&path)?;
+        assert_eq!(engine.public_key(), loaded.public_key());
+
+        // Never overwrite an existing key
+        assert!(VrfEngine::generate().save_key_file(&path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_encoding_is_canonical() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let (output, proof) = engine.prove(b"message")?;
+        assert_eq!(proof.len(), VRF_PROOF_LENGTH);
+        assert_eq!(output.len(), VRF_OUTPUT_LENGTH);
+
+        // Decoding and re-encoding must give back the same bytes
+        let decoded: ECVRFProof = bincode::deserialize(&proof)?;
+        assert_eq!(bincode::serialize(&decoded)?, proof);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_message() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let (output, proof) = engine.prove(b"message")?;
+
+        assert!(!VrfEngine::verify(&engine.public_key(), b"messagf", &proof, &output)?);
+        assert!(!VrfEngine::verify(&engine.public_key(), b"", &proof, &output)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_proof() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let (output, proof) = engine.prove(b"message")?;
+
+        // Flip one bit in each section of the proof: gamma, challenge and response
+        for index in [0, 40, 60] {
+            let mut tampered = proof.clone();
+            tampered[index] ^= 0x01;
+            assert!(!VrfEngine::verify(&engine.public_key(), b"message", &tampered, &output)?);
+        }
+
+        // A valid proof for another message must not verify either
+        let (_, other_proof) = engine.prove(b"other")?;
+        assert!(!VrfEngine::verify(&engine.public_key(), b"message", &other_proof, &output)?);
+
+        // Wrong length is an input error
+        assert!(VrfEngine::verify(&engine.public_key(), b"message", &proof[..79], &output).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_output() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let (output, proof) = engine.prove(b"message")?;
+
+        let mut tampered = output.clone();
+        tampered[63] ^= 0x80;
+        assert!(!VrfEngine::verify(&engine.public_key(), b"message", &proof, &tampered)?);
+
+        let (other_output, _) = engine.prove(b"other")?;
+        assert!(!VrfEngine::verify(&engine.public_key(), b"message", &proof, &other_output)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_key() -> Result<()> {
+        let engine = VrfEngine::generate();
+        let other = VrfEngine::generate();
+        let (output, proof) = engine.prove(b"message")?;
+
+        assert!(!VrfEngine::verify(&other.public_key(), b"message", &proof, &output)?);
+
+        // Corrupted key bytes (possibly not a valid point)
+        let mut tampered = engine.public_key();
+        tampered[0] ^= 0x01;
+        assert!(!VrfEngine::verify(&tampered, b"message", &proof, &output)?);
+
+        // The identity point is never a valid key
+        assert!(!VrfEngine::verify(&[0u8; 32], b"message", &proof, &output)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_random_chaining() {
+        let accum0 = [0u8; 32];
+        let accum1 = VrfEngine::accumulate(&accum0, Some(&[1u8; 32]));
+        assert_ne!(accum0, accum1);
+        assert_eq!(VrfEngine::accumulate(&accum1, None), accum1);
+
+        // Same contributions on top of a different history give a different accumulator
+        let other = VrfEngine::accumulate(&[9u8; 32], Some(&[1u8; 32]));
+        assert_ne!(accum1, other);
+
+        let random1 = VrfEngine::compute_block_random(&[5u8; 32], &accum1);
+        assert_eq!(random1, VrfEngine::compute_block_random(&[5u8; 32], &accum1));
+        assert_ne!(random1, VrfEngine::compute_block_random(&[6u8; 32], &accum1));
+        assert_ne!(random1, VrfEngine::compute_block_random(&[5u8; 32], &other));
+    }
+}
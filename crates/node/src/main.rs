@@ -1,6 +1,7 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use mychain_app::MyChainApp;
+use mychain_app::vrf::{VrfEngine, VALIDATOR_VRF_KEY_FILE, VRF_KEY_FILE};
 use std::path::PathBuf;
 use tracing::{info, error};
 use tracing_subscriber;
@@ -66,10 +67,29 @@ async fn start_node(abci_addr: String, data_dir: PathBuf, api_addr: String) -> R
     std::fs::create_dir_all(&data_dir)
         .context("Failed to create data directory")?;
 
+    // Load the VRF key created by `init`
+    let key_path = data_dir.join(VRF_KEY_FILE);
+    let vrf_engine = VrfEngine::load_key_file(&key_path)
+        .with_context(|| format!(
+            "Failed to load VRF key (run `mychain-node init --data-dir {}` first)",
+            data_dir.display()
+        ))?;
+    info!("VRF public key: {}", hex::encode(vrf_engine.public_key()));
+
     // Create ABCI application
-    let app = MyChainApp::new(&data_dir)
+    let mut app = MyChainApp::new(&data_dir, vrf_engine)
         .context("Failed to create MyChain application")?;
 
+    // Validators sign vote extensions with their own VRF key; full nodes have none
+    let validator_key_path = data_dir.join(VALIDATOR_VRF_KEY_FILE);
+    if validator_key_path.exists() {
+        let validator_vrf = VrfEngine::load_key_file(&validator_key_path)?;
+        info!("Validator VRF public key: {}", hex::encode(validator_vrf.public_key()));
+        app = app.with_validator_vrf_key(validator_vrf);
+    } else {
+        info!("No validator VRF key, running without vote extensions");
+    }
+
     // Start ABCI server
     info!("ABCI server listening on: {}", abci_addr);
 
@@ -119,9 +139,15 @@ async fn start_api_server(api_addr: String) -> Result<()> {
 
     #[derive(Deserialize)]
     struct FlipRequest {
-        wallet: String,
+        /// ed25519 public key (hex); the wallet is derived from it
+        public_key: String,
         amount: u64,
+        /// Side to bet on: "heads" or "tails"
+        choice: mychain_types::CoinSide,
+        /// Next nonce of the wallet, starting at 0
         nonce: u64,
+        /// ed25519 signature (hex) over `SignedTx::sign_bytes` for this chain
+        signature: String,
     }
 
     #[derive(Serialize)]
@@ -147,27 +173,30 @@ async fn start_api_server(api_addr: String) -> Result<()> {
         State(state): State<ApiState>,
         Json(request): Json<FlipRequest>,
     ) -> Result<Json<FlipResponse>, StatusCode> {
-        // Decode wallet hex
-        let wallet_bytes = hex::decode(&request.wallet)
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
-        
-        if wallet_bytes.len() != 32 {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-
-        let mut wallet = [0u8; 32];
-        wallet.copy_from_slice(&wallet_bytes);
-
-        // Create TxFlip
-        let tx = mychain_types::TxFlip {
-            version: 1,
-            wallet,
-            amount: request.amount,
-            nonce: request.nonce,
+        // Decode public key and signature hex
+        let public_key: [u8; 32] = hex::decode(&request.public_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        let signature: [u8; mychain_types::SIGNATURE_LENGTH] = hex::decode(&request.signature)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+
+        // Create signed TxFlip; the signature is checked by CheckTx
+        let tx = mychain_types::SignedTx {
+            tx: mychain_types::Tx::Flip(mychain_types::TxFlip::new(
+                mychain_types::wallet_from_public_key(&public_key),
+                request.amount,
+                request.choice,
+                request.nonce,
+            )),
+            public_key,
+            signature,
         };
 
         // Serialize transaction
-        let tx_bytes = bincode::serialize(&tx)
+        let tx_bytes = tx.to_bytes()
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
         // Submit to CometBFT via broadcast_tx_commit
@@ -228,8 +257,36 @@ async fn init_node(data_dir: PathBuf) -> Result<()> {
     std::fs::create_dir_all(&data_dir)
         .context("Failed to create data directory")?;
 
+    // Create the VRF key, keeping an existing one so re-running init is harmless
+    let key_path = data_dir.join(VRF_KEY_FILE);
+    let vrf_engine = if key_path.exists() {
+        info!("VRF key already exists: {}", key_path.display());
+        VrfEngine::load_key_file(&key_path)?
+    } else {
+        let vrf_engine = VrfEngine::generate();
+        vrf_engine.save_key_file(&key_path)?;
+        info!("Created VRF key: {}", key_path.display());
+        vrf_engine
+    };
+    info!("VRF public key: {}", hex::encode(vrf_engine.public_key()));
+    info!("Every node must run with the VRF key set as `vrf_public_key` in the genesis app_state; copy {} between nodes", VRF_KEY_FILE);
+
+    // Create this validator's vote extension key, registered per validator address
+    // under `validator_vrf_keys` in the genesis app_state
+    let validator_key_path = data_dir.join(VALIDATOR_VRF_KEY_FILE);
+    let validator_vrf = if validator_key_path.exists() {
+        info!("Validator VRF key already exists: {}", validator_key_path.display());
+        VrfEngine::load_key_file(&validator_key_path)?
+    } else {
+        let validator_vrf = VrfEngine::generate();
+        validator_vrf.save_key_file(&validator_key_path)?;
+        info!("Created validator VRF key: {}", validator_key_path.display());
+        validator_vrf
+    };
+    info!("Validator VRF public key: {}", hex::encode(validator_vrf.public_key()));
+
     // Initialize storage
-    let app = MyChainApp::new(&data_dir)
+    let _app = MyChainApp::new(&data_dir, vrf_engine)
         .context("Failed to initialize application")?;
 
     info!("Node initialized successfully");
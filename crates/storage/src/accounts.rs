@@ -0,0 +1,212 @@
+//! Per-wallet account state: nonces, balances and receipts of account operations
+//!
+//! Reads come in two flavours: `get_*` sees the committed state only, while
+//! `get_pending_*` also sees writes pending in a batch, which is what execution
+//! needs when several transactions of a block touch the same wallet.
+
+use anyhow::{Context, Result};
+
+use mychain_types::Receipt;
+
+use crate::{merkle, BatchOperation, Storage, StorageBatch};
+
+impl Storage {
+    /// Get the next nonce expected from a wallet (0 for a wallet never seen)
+    pub fn get_nonce(&self, wallet: &[u8; 32]) -> Result<u64> {
+        self.get_pending_nonce(wallet, &self.batch())
+    }
+
+    /// Get the next nonce expected from a wallet, including writes pending in `batch`
+    pub fn get_pending_nonce(&self, wallet: &[u8; 32], batch: &StorageBatch) -> Result<u64> {
+        self.get_u64(&nonce_key(wallet), batch).context("Invalid nonce format")
+    }
+
+    /// Set the next nonce expected from a wallet
+    pub fn set_nonce(&self, wallet: &[u8; 32], nonce: u64, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: nonce_key(wallet),
+            value: nonce.to_le_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Get the balance of a wallet (0 for a wallet never seen)
+    pub fn get_balance(&self, wallet: &[u8; 32]) -> Result<u64> {
+        self.get_pending_balance(wallet, &self.batch())
+    }
+
+    /// Get the balance of a wallet, including writes pending in `batch`
+    pub fn get_pending_balance(&self, wallet: &[u8; 32], batch: &StorageBatch) -> Result<u64> {
+        self.get_u64(&balance_key(wallet), batch).context("Invalid balance format")
+    }
+
+    /// Set the balance of a wallet
+    pub fn set_balance(&self, wallet: &[u8; 32], balance: u64, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: balance_key(wallet),
+            value: balance.to_le_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Merkle proof for the balance of a wallet against the last committed app hash
+    pub fn prove_balance(&self, wallet: &[u8; 32]) -> Result<merkle::StateProof> {
+        self.prove("app", &balance_key(wallet))
+    }
+
+    /// Get the public key allowed to sign deposits
+    pub fn get_minter_public_key(&self) -> Result<Option<[u8; 32]>> {
+        let tree = self.db.open_tree("app")?;
+        match tree.get("minter_pk")? {
+            Some(bytes) => {
+                let public_key: [u8; 32] = bytes.as_ref().try_into()
+                    .context("Invalid minter public key format")?;
+                Ok(Some(public_key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Set the public key allowed to sign deposits
+    pub fn set_minter_public_key(&self, public_key: &[u8; 32], batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: b"minter_pk".to_vec(),
+            value: public_key.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Store the receipt of a deposit, withdrawal or transfer
+    pub fn store_receipt(&self, tx_hash: &[u8], receipt: &Receipt, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: receipt_key(tx_hash),
+            value: receipt.to_bytes()?,
+        });
+        Ok(())
+    }
+
+    /// Get a receipt by transaction hash
+    pub fn get_receipt(&self, tx_hash: &[u8]) -> Result<Option<Receipt>> {
+        let tree = self.db.open_tree("app")?;
+        match tree.get(receipt_key(tx_hash))? {
+            Some(bytes) => Ok(Some(Receipt::from_bytes(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Merkle proof for a receipt against the last committed app hash
+    pub fn prove_receipt(&self, tx_hash: &[u8]) -> Result<merkle::StateProof> {
+        self.prove("app", &receipt_key(tx_hash))
+    }
+
+    /// Little-endian u64 in the app keyspace, 0 when absent
+    fn get_u64(&self, key: &[u8], batch: &StorageBatch) -> Result<u64> {
+        match self.get_with_batch("app", key, batch)? {
+            Some(bytes) => {
+                let value: [u8; 8] = bytes.as_slice().try_into()?;
+                Ok(u64::from_le_bytes(value))
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+fn nonce_key(wallet: &[u8; 32]) -> Vec<u8> {
+    format!("nonces/{}", hex::encode(wallet)).into_bytes()
+}
+
+fn balance_key(wallet: &[u8; 32]) -> Vec<u8> {
+    format!("balances/{}", hex::encode(wallet)).into_bytes()
+}
+
+fn receipt_key(tx_hash: &[u8]) -> Vec<u8> {
+    format!("receipts/{}", hex::encode(tx_hash)).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mychain_types::ReceiptKind;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_nonces_read_pending_writes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        let wallet = [3u8; 32];
+
+        assert_eq!(storage.get_nonce(&wallet)?, 0);
+
+        // Pending writes are visible through the batch, latest first, but not committed
+        let mut batch = storage.batch();
+        storage.set_nonce(&wallet, 1, &mut batch)?;
+        storage.set_nonce(&wallet, 2, &mut batch)?;
+        assert_eq!(storage.get_pending_nonce(&wallet, &batch)?, 2);
+        assert_eq!(storage.get_pending_nonce(&[4u8; 32], &batch)?, 0);
+        assert_eq!(storage.get_nonce(&wallet)?, 0);
+
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_nonce(&wallet)?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_balances_read_pending_writes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        let wallet = [3u8; 32];
+
+        assert_eq!(storage.get_balance(&wallet)?, 0);
+
+        let mut batch = storage.batch();
+        storage.set_balance(&wallet, 100, &mut batch)?;
+        storage.set_balance(&wallet, 60, &mut batch)?;
+        assert_eq!(storage.get_pending_balance(&wallet, &batch)?, 60);
+        assert_eq!(storage.get_balance(&wallet)?, 0);
+
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_balance(&wallet)?, 60);
+
+        // Balances are part of the committed state
+        let app_hash = storage.compute_app_hash(&storage.batch())?;
+        match storage.prove_balance(&wallet)? {
+            merkle::StateProof::Exists(proof) => {
+                assert_eq!(proof.value, 60u64.to_le_bytes());
+                assert!(proof.verify(&app_hash));
+            }
+            other => panic!("expected existence proof, got {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_receipts_and_minter() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+
+        assert_eq!(storage.get_minter_public_key()?, None);
+        assert_eq!(storage.get_receipt(&[1u8; 32])?, None);
+
+        let receipt = Receipt {
+            kind: ReceiptKind::Transfer { wallet: [2u8; 32], recipient: [3u8; 32], amount: 10 },
+            height: 4,
+            tx_hash: [1u8; 32],
+        };
+        let mut batch = storage.batch();
+        storage.set_minter_public_key(&[9u8; 32], &mut batch)?;
+        storage.store_receipt(&receipt.tx_hash, &receipt, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        assert_eq!(storage.get_minter_public_key()?, Some([9u8; 32]));
+        assert_eq!(storage.get_receipt(&[1u8; 32])?, Some(receipt));
+        assert!(matches!(storage.prove_receipt(&[1u8; 32])?, merkle::StateProof::Exists(_)));
+
+        Ok(())
+    }
+}
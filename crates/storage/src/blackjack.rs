@@ -0,0 +1,89 @@
+//! Blackjack sessions, one per wallet
+//!
+//! A wallet's session stays stored once it ends, with its shoe published, until
+//! the wallet's next deal replaces it.
+
+use anyhow::{Context, Result};
+
+use mychain_types::BlackjackSession;
+
+use crate::{merkle, BatchOperation, Storage, StorageBatch};
+
+impl Storage {
+    /// Get the latest blackjack session of a wallet, in play or ended
+    pub fn get_blackjack_session(&self, wallet: &[u8; 32]) -> Result<Option<BlackjackSession>> {
+        self.get_pending_blackjack_session(wallet, &self.batch())
+    }
+
+    /// Get the latest blackjack session of a wallet, including writes pending in `batch`
+    pub fn get_pending_blackjack_session(
+        &self,
+        wallet: &[u8; 32],
+        batch: &StorageBatch,
+    ) -> Result<Option<BlackjackSession>> {
+        match self.get_with_batch("app", &session_key(wallet), batch)? {
+            Some(bytes) => Ok(Some(BlackjackSession::from_bytes(&bytes)
+                .context("Invalid blackjack session format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the blackjack session of its wallet
+    pub fn store_blackjack_session(&self, session: &BlackjackSession, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: session_key(&session.wallet),
+            value: session.to_bytes()?,
+        });
+        Ok(())
+    }
+
+    /// Merkle proof for the blackjack session of a wallet against the last committed app hash
+    pub fn prove_blackjack_session(&self, wallet: &[u8; 32]) -> Result<merkle::StateProof> {
+        self.prove("app", &session_key(wallet))
+    }
+}
+
+fn session_key(wallet: &[u8; 32]) -> Vec<u8> {
+    format!("blackjack_sessions/{}", hex::encode(wallet)).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mychain_types::BlackjackHand;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_blackjack_sessions() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        let session = BlackjackSession {
+            wallet: [1u8; 32],
+            height: 10,
+            tx_hash: [2u8; 32],
+            nonce: 0,
+            vrf_message: vec![3],
+            commitment: [4u8; 32],
+            hands: vec![BlackjackHand { cards: vec![0, 9], bet: 50, doubled: false, stood: false }],
+            active: 0,
+            dealer: vec![5],
+            next_card: 4,
+            finished: false,
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+        };
+
+        let mut batch = storage.batch();
+        storage.store_blackjack_session(&session, &mut batch)?;
+        assert_eq!(storage.get_pending_blackjack_session(&[1u8; 32], &batch)?, Some(session.clone()));
+        assert_eq!(storage.get_blackjack_session(&[1u8; 32])?, None);
+
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_blackjack_session(&[1u8; 32])?, Some(session));
+        assert_eq!(storage.get_blackjack_session(&[2u8; 32])?, None);
+        assert!(matches!(storage.prove_blackjack_session(&[1u8; 32])?, merkle::StateProof::Exists(_)));
+
+        Ok(())
+    }
+}
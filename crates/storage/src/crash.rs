@@ -0,0 +1,142 @@
+//! Crash rounds and the bets placed in them
+//!
+//! Only the latest round can be open, so its id is all that is needed to find the
+//! round taking bets or in flight.
+
+use anyhow::{Context, Result};
+
+use mychain_types::{CrashBet, CrashRound};
+
+use crate::{merkle, BatchOperation, Storage, StorageBatch};
+
+impl Storage {
+    /// Get the id of the latest crash round, open or ended (None before the first)
+    pub fn get_latest_crash_round_id(&self) -> Result<Option<u64>> {
+        self.get_pending_latest_crash_round_id(&self.batch())
+    }
+
+    /// Get the id of the latest crash round, including writes pending in `batch`
+    pub fn get_pending_latest_crash_round_id(&self, batch: &StorageBatch) -> Result<Option<u64>> {
+        match self.get_with_batch("app", b"crash_latest_round", batch)? {
+            Some(bytes) => {
+                let id: [u8; 8] = bytes.as_slice().try_into()
+                    .context("Invalid crash round id format")?;
+                Ok(Some(u64::from_le_bytes(id)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get a crash round by id
+    pub fn get_crash_round(&self, id: u64) -> Result<Option<CrashRound>> {
+        self.get_pending_crash_round(id, &self.batch())
+    }
+
+    /// Get a crash round by id, including writes pending in `batch`
+    pub fn get_pending_crash_round(&self, id: u64, batch: &StorageBatch) -> Result<Option<CrashRound>> {
+        match self.get_with_batch("app", &round_key(id), batch)? {
+            Some(bytes) => Ok(Some(CrashRound::from_bytes(&bytes)
+                .context("Invalid crash round format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a crash round and make it the latest one
+    pub fn store_crash_round(&self, round: &CrashRound, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: round_key(round.id),
+            value: round.to_bytes()?,
+        });
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: b"crash_latest_round".to_vec(),
+            value: round.id.to_le_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Get the bet of a wallet in a crash round
+    pub fn get_crash_bet(&self, round: u64, wallet: &[u8; 32]) -> Result<Option<CrashBet>> {
+        self.get_pending_crash_bet(round, wallet, &self.batch())
+    }
+
+    /// Get the bet of a wallet in a crash round, including writes pending in `batch`
+    pub fn get_pending_crash_bet(
+        &self,
+        round: u64,
+        wallet: &[u8; 32],
+        batch: &StorageBatch,
+    ) -> Result<Option<CrashBet>> {
+        match self.get_with_batch("app", &bet_key(round, wallet), batch)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes).context("Invalid crash bet format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the bet of a wallet in a crash round
+    pub fn store_crash_bet(&self, round: u64, bet: &CrashBet, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: bet_key(round, &bet.wallet),
+            value: bincode::serialize(bet)?,
+        });
+        Ok(())
+    }
+
+    /// Merkle proof for a crash round against the last committed app hash
+    pub fn prove_crash_round(&self, id: u64) -> Result<merkle::StateProof> {
+        self.prove("app", &round_key(id))
+    }
+}
+
+fn round_key(id: u64) -> Vec<u8> {
+    format!("crash_rounds/{}", id).into_bytes()
+}
+
+fn bet_key(round: u64, wallet: &[u8; 32]) -> Vec<u8> {
+    format!("crash_bets/{}/{}", round, hex::encode(wallet)).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_crash_rounds_and_bets() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        assert_eq!(storage.get_latest_crash_round_id()?, None);
+
+        let round = CrashRound {
+            id: 1,
+            open_height: 10,
+            flight_height: 16,
+            vrf_message: vec![1],
+            commitment: [2u8; 32],
+            wallets: vec![[3u8; 32]],
+            end_height: None,
+            crash_point: None,
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+        };
+        let bet = CrashBet { wallet: [3u8; 32], amount: 50, cash_out: None, nonce: 0, tx_hash: [4u8; 32] };
+
+        let mut batch = storage.batch();
+        storage.store_crash_round(&round, &mut batch)?;
+        storage.store_crash_bet(1, &bet, &mut batch)?;
+        assert_eq!(storage.get_pending_latest_crash_round_id(&batch)?, Some(1));
+        assert_eq!(storage.get_pending_crash_bet(1, &[3u8; 32], &batch)?, Some(bet.clone()));
+        assert_eq!(storage.get_crash_round(1)?, None);
+
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_latest_crash_round_id()?, Some(1));
+        assert_eq!(storage.get_crash_round(1)?, Some(round));
+        assert_eq!(storage.get_crash_bet(1, &[3u8; 32])?, Some(bet));
+        assert_eq!(storage.get_crash_bet(2, &[3u8; 32])?, None);
+        assert!(matches!(storage.prove_crash_round(1)?, merkle::StateProof::Exists(_)));
+
+        Ok(())
+    }
+}
@@ -1,17 +1,61 @@
+pub mod accounts;
+pub mod blackjack;
+pub mod crash;
+pub mod lottery;
+pub mod merkle;
+pub mod mines;
+pub mod roulette;
+pub mod session;
+
 use anyhow::{Context, Result};
-use mychain_types::BetRecord;
+use mychain_types::{BetRecord, ChainParams};
 use sled::Db;
+use std::collections::BTreeMap;
 use std::path::Path;
 
+/// Keyspace holding per-height commitments; derived from the state, so it is
+/// not part of the state itself
+const STATE_TREE: &str = "state";
+
 /// Storage layer using sled with proper keyspace organization
 /// 
 /// Keyspaces:
 /// - /meta/last_height -> u64
+/// - /meta/last_block_hash -> bytes
+/// - /meta/chain_id -> utf8
 /// - /blocks/{height} -> bincode(Block)  
 /// - /tx/{tx_hash} -> height:u64
 /// - /app/vrf_pk -> bytes
+/// - /app/vrf_accum -> [u8; 32]
+/// - /app/block_random/{height} -> [u8; 32]
+/// - /app/vote_ext_height -> u64
+/// - /app/validator_vrf_keys/{address} -> bytes
 /// - /app/bets/{tx_hash} -> bincode(BetRecord)
+/// - /app/nonces/{wallet} -> u64 (next expected nonce)
+/// - /app/balances/{wallet} -> u64
+/// - /app/minter_pk -> [u8; 32]
+/// - /app/receipts/{tx_hash} -> bincode(Receipt)
+/// - /app/params -> json(ChainParams)
+/// - /app/roulette_spins/{height}/{table} -> bincode(RouletteSpin)
+/// - /app/roulette_tables/{height} -> bincode(Vec<u32>)
+/// - /app/crash_latest_round -> u64
+/// - /app/crash_rounds/{id} -> bincode(CrashRound)
+/// - /app/crash_bets/{round}/{wallet} -> bincode(CrashBet)
+/// - /app/lottery_latest_draw -> u64
+/// - /app/lottery_draws/{id} -> bincode(LotteryDraw)
+/// - /app/lottery_due/{height} -> bincode(Vec<u64>)
+/// - /app/lottery_tickets/{draw}/{wallet} -> u64
+/// - /app/lottery_winners/{draw} -> bincode(Vec<LotteryWinner>)
+/// - /app/blackjack_sessions/{wallet} -> bincode(BlackjackSession)
+/// - /app/mines_games/{wallet} -> bincode(MinesGame)
+/// - /app/session_latest -> u64
+/// - /app/sessions/{id} -> bincode(GameSession)
+/// - /app/session_active/{wallet} -> bincode(Vec<u64>)
+/// - /app/session_due/{height} -> bincode(Vec<u64>)
 /// - /state/app_hash/{height} -> [u8; 32]
+///
+/// Every keyspace except `/state` is committed to by the app hash: a Merkle tree
+/// (see [`merkle`]) over all `{keyspace}/{key}` entries in byte order.
 pub struct Storage {
     db: Db,
 }
@@ -29,6 +73,30 @@ enum BatchOperation {
     },
 }
 
+impl StorageBatch {
+    /// Run `write` on the batch, keeping its writes only if it succeeds
+    ///
+    /// Settlement at the end of a block has no transaction to reject: a failure
+    /// must leave nothing half written in a batch that is committed anyway.
+    pub fn atomically<T, E>(&mut self, write: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        let checkpoint = self.operations.len();
+        let result = write(self);
+        if result.is_err() {
+            self.operations.truncate(checkpoint);
+        }
+        result
+    }
+
+    /// Latest pending write to a key, if any
+    fn get(&self, tree_name: &str, key: &[u8]) -> Option<&[u8]> {
+        self.operations.iter().rev().find_map(|op| match op {
+            BatchOperation::Insert { tree_name: tree, key: k, value } => {
+                (tree == tree_name && k == key).then_some(value.as_slice())
+            }
+        })
+    }
+}
+
 impl Storage {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let db = sled::open(path).context("Failed to open sled database")?;
@@ -74,6 +142,157 @@ impl Storage {
         Ok(())
     }
 
+    /// Get the chain id recorded at InitChain
+    pub fn get_chain_id(&self) -> Result<Option<String>> {
+        let tree = self.db.open_tree("meta")?;
+        match tree.get("chain_id")? {
+            Some(bytes) => Ok(Some(String::from_utf8(bytes.to_vec())
+                .context("Invalid chain id format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the chain id
+    pub fn set_chain_id(&self, chain_id: &str, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "meta".to_string(),
+            key: b"chain_id".to_vec(),
+            value: chain_id.as_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Get the chain parameters set at genesis
+    pub fn get_params(&self) -> Result<Option<ChainParams>> {
+        let tree = self.db.open_tree("app")?;
+        match tree.get("params")? {
+            Some(bytes) => Ok(Some(ChainParams::from_bytes(&bytes)
+                .context("Invalid chain params format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the chain parameters
+    pub fn set_params(&self, params: &ChainParams, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: b"params".to_vec(),
+            value: params.to_bytes()?,
+        });
+        Ok(())
+    }
+
+    /// Get the hash of the last finalized block
+    pub fn get_last_block_hash(&self) -> Result<Option<Vec<u8>>> {
+        let tree = self.db.open_tree("meta")?;
+        Ok(tree.get("last_block_hash")?.map(|v| v.to_vec()))
+    }
+
+    /// Set the hash of the last finalized block
+    pub fn set_last_block_hash(&self, block_hash: &[u8], batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "meta".to_string(),
+            key: b"last_block_hash".to_vec(),
+            value: block_hash.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Get the height from which CometBFT produces vote extensions (None = disabled)
+    pub fn get_vote_extensions_enable_height(&self) -> Result<Option<u64>> {
+        let tree = self.db.open_tree("app")?;
+        match tree.get("vote_ext_height")? {
+            Some(bytes) => {
+                let height_bytes: [u8; 8] = bytes.as_ref().try_into()
+                    .context("Invalid height format")?;
+                Ok(Some(u64::from_le_bytes(height_bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Set the height from which CometBFT produces vote extensions
+    pub fn set_vote_extensions_enable_height(&self, height: u64, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: b"vote_ext_height".to_vec(),
+            value: height.to_le_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Register the VRF public key a validator uses for its vote extensions
+    pub fn set_validator_vrf_key(&self, address: &[u8], vrf_pk: &[u8], batch: &mut StorageBatch) -> Result<()> {
+        let key = format!("validator_vrf_keys/{}", hex::encode(address));
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: key.into_bytes(),
+            value: vrf_pk.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Get the registered vote extension VRF public key of a validator
+    pub fn get_validator_vrf_key(&self, address: &[u8]) -> Result<Option<Vec<u8>>> {
+        let tree = self.db.open_tree("app")?;
+        let key = format!("validator_vrf_keys/{}", hex::encode(address));
+        Ok(tree.get(key.as_bytes())?.map(|v| v.to_vec()))
+    }
+
+    /// Check whether any validator VRF key is registered
+    pub fn has_validator_vrf_keys(&self) -> Result<bool> {
+        let tree = self.db.open_tree("app")?;
+        Ok(tree.scan_prefix("validator_vrf_keys/").next().transpose()?.is_some())
+    }
+
+    /// Get the running VRF accumulator
+    pub fn get_vrf_accumulator(&self) -> Result<Option<[u8; 32]>> {
+        let tree = self.db.open_tree("app")?;
+        match tree.get("vrf_accum")? {
+            Some(bytes) => {
+                let accum: [u8; 32] = bytes.as_ref().try_into()
+                    .context("Invalid VRF accumulator format")?;
+                Ok(Some(accum))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Set the running VRF accumulator
+    pub fn set_vrf_accumulator(&self, vrf_accum: &[u8; 32], batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: b"vrf_accum".to_vec(),
+            value: vrf_accum.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Store the block randomness used for a height
+    pub fn store_block_random(&self, height: u64, block_random: &[u8; 32], batch: &mut StorageBatch) -> Result<()> {
+        let key = format!("block_random/{}", height);
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: key.into_bytes(),
+            value: block_random.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Get the block randomness used for a height
+    pub fn get_block_random(&self, height: u64) -> Result<Option<[u8; 32]>> {
+        let tree = self.db.open_tree("app")?;
+        let key = format!("block_random/{}", height);
+        match tree.get(key.as_bytes())? {
+            Some(bytes) => {
+                let block_random: [u8; 32] = bytes.as_ref().try_into()
+                    .context("Invalid block random format")?;
+                Ok(Some(block_random))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Store a bet record
     pub fn store_bet(&self, tx_hash: &[u8], bet: &BetRecord, batch: &mut StorageBatch) -> Result<()> {
         let key = format!("bets/{}", hex::encode(tx_hash));
@@ -189,22 +408,95 @@ impl Storage {
         Ok(())
     }
 
-    /// Compute app hash based on current state
-    /// Simple implementation: hash(height || last_vrf_accumulator)
-    pub fn compute_app_hash(&self, height: u64) -> Result<[u8; 32]> {
-        // For POC: simple hash of height
-        // In production: hash of canonical state serialization
-        let mut hasher = blake3::Hasher::new();
-        hasher.update(&height.to_le_bytes());
-        
-        // Add some state data to the hash if available
-        if let Ok(Some(vrf_pk)) = self.get_vrf_public_key() {
-            hasher.update(&vrf_pk);
+    /// Read a key as seen by `batch`: its latest pending write, else the committed value
+    fn get_with_batch(&self, tree_name: &str, key: &[u8], batch: &StorageBatch) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = batch.get(tree_name, key) {
+            return Ok(Some(value.to_vec()));
         }
-        
-        let hash = hasher.finalize();
-        Ok(*hash.as_bytes())
+        let tree = self.db.open_tree(tree_name)?;
+        Ok(tree.get(key)?.map(|v| v.to_vec()))
+    }
+
+    /// Compute the app hash: the Merkle root of the committed state with the
+    /// pending batch applied on top
+    ///
+    /// The tree is rebuilt from all entries on every call, which is linear in the
+    /// state size.
+    pub fn compute_app_hash(&self, batch: &StorageBatch) -> Result<[u8; 32]> {
+        let entries = self.state_entries(batch)?;
+        let leaves: Vec<[u8; 32]> = entries.iter()
+            .map(|(key, value)| merkle::leaf_hash(key, value))
+            .collect();
+        Ok(merkle::root(&leaves))
+    }
+
+    /// Merkle proof for a bet record against the last committed app hash
+    pub fn prove_bet(&self, tx_hash: &[u8]) -> Result<merkle::StateProof> {
+        self.prove("app", format!("bets/{}", hex::encode(tx_hash)).as_bytes())
+    }
+
+    /// Merkle proof for the block randomness of a height against the last committed app hash
+    pub fn prove_block_random(&self, height: u64) -> Result<merkle::StateProof> {
+        self.prove("app", format!("block_random/{}", height).as_bytes())
     }
+
+    /// Merkle proof for the chain parameters against the last committed app hash
+    pub fn prove_params(&self) -> Result<merkle::StateProof> {
+        self.prove("app", b"params")
+    }
+
+    /// Existence or non-existence proof for an entry of the committed state
+    fn prove(&self, tree_name: &str, key: &[u8]) -> Result<merkle::StateProof> {
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = self.state_entries(&self.batch())?.into_iter().collect();
+        Ok(merkle::StateProof::build(&entries, &state_key(tree_name, key)))
+    }
+
+    /// All committed state entries keyed by `{keyspace}/{key}`, with the pending
+    /// batch applied on top
+    fn state_entries(&self, batch: &StorageBatch) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let mut entries = BTreeMap::new();
+
+        for tree_name in self.db.tree_names() {
+            let Ok(tree_name) = std::str::from_utf8(&tree_name) else {
+                continue;
+            };
+            if !is_state_tree(tree_name) {
+                continue;
+            }
+            let tree = self.db.open_tree(tree_name)?;
+            for item in tree.iter() {
+                let (key, value) = item?;
+                entries.insert(state_key(tree_name, &key), value.to_vec());
+            }
+        }
+
+        for op in &batch.operations {
+            match op {
+                BatchOperation::Insert { tree_name, key, value } => {
+                    if is_state_tree(tree_name) {
+                        entries.insert(state_key(tree_name, key), value.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Whether a sled tree is part of the committed state
+fn is_state_tree(tree_name: &str) -> bool {
+    // sled's implicit default tree is never written by the application
+    tree_name != STATE_TREE && tree_name != "__sled__default"
+}
+
+/// Full key of an entry in the state tree
+fn state_key(tree_name: &str, key: &[u8]) -> Vec<u8> {
+    let mut full_key = Vec::with_capacity(tree_name.len() + 1 + key.len());
+    full_key.extend_from_slice(tree_name.as_bytes());
+    full_key.push(b'/');
+    full_key.extend_from_slice(key);
+    full_key
 }
 
 #[cfg(test)]
@@ -234,6 +526,142 @@ mod tests {
         
         assert_eq!(storage.get_vrf_public_key()?.unwrap(), vrf_pk);
 
+        // Test validator VRF key registry
+        assert!(!storage.has_validator_vrf_keys()?);
+        let mut batch = storage.batch();
+        storage.set_validator_vrf_key(&[7u8; 20], b"validator_key", &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        assert!(storage.has_validator_vrf_keys()?);
+        assert_eq!(storage.get_validator_vrf_key(&[7u8; 20])?.unwrap(), b"validator_key");
+        assert!(storage.get_validator_vrf_key(&[8u8; 20])?.is_none());
+
+        // Test block randomness per height
+        let mut batch = storage.batch();
+        storage.store_block_random(5, &[5u8; 32], &mut batch)?;
+        storage.store_block_random(6, &[6u8; 32], &mut batch)?;
+        storage.set_vrf_accumulator(&[9u8; 32], &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        assert_eq!(storage.get_block_random(5)?, Some([5u8; 32]));
+        assert_eq!(storage.get_block_random(6)?, Some([6u8; 32]));
+        assert_eq!(storage.get_block_random(7)?, None);
+        assert_eq!(storage.get_vrf_accumulator()?, Some([9u8; 32]));
+
+        // Test chain params
+        assert_eq!(storage.get_params()?, None);
+        let params = ChainParams { house_edge_bps: 150, max_bet: 1_000, ..Default::default() };
+        let mut batch = storage.batch();
+        storage.set_params(&params, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        assert_eq!(storage.get_params()?, Some(params));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_atomic_writes_drop_on_failure() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+
+        let mut batch = storage.batch();
+        storage.set_last_height(1, &mut batch)?;
+        let failed: Result<()> = batch.atomically(|batch| {
+            storage.set_last_height(2, batch)?;
+            anyhow::bail!("settlement failed")
+        });
+        assert!(failed.is_err());
+        assert_eq!(batch.atomically(|batch| storage.set_last_height(3, batch).map(|_| 3))?, 3);
+        assert_eq!(batch.operations.len(), 2);
+        storage.apply_batch(batch)?;
+
+        assert_eq!(storage.get_last_height()?, 3);
+
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_app_hash_commits_to_state() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+
+        let empty = storage.compute_app_hash(&storage.batch())?;
+        assert_eq!(empty, merkle::empty_hash());
+
+        // Pending writes are included before they are applied
+        let mut batch = storage.batch();
+        storage.set_last_height(1, &mut batch)?;
+        storage.store_tx_height(&[1u8; 32], 1, &mut batch)?;
+        let pending_hash = storage.compute_app_hash(&batch)?;
+        assert_ne!(pending_hash, empty);
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.compute_app_hash(&storage.batch())?, pending_hash);
+
+        // Writing to any committed keyspace changes the hash
+        let mut batch = storage.batch();
+        storage.set_vrf_accumulator(&[1u8; 32], &mut batch)?;
+        assert_ne!(storage.compute_app_hash(&batch)?, pending_hash);
+
+        // Per-height app hashes are not part of the state
+        let mut batch = storage.batch();
+        storage.store_app_hash(1, &pending_hash, &mut batch)?;
+        assert_eq!(storage.compute_app_hash(&batch)?, pending_hash);
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.compute_app_hash(&storage.batch())?, pending_hash);
+
+        // Same content in a fresh database gives the same hash
+        let other_dir = tempdir()?;
+        let other = Storage::open(other_dir.path())?;
+        let mut batch = other.batch();
+        other.store_tx_height(&[1u8; 32], 1, &mut batch)?;
+        other.set_last_height(1, &mut batch)?;
+        assert_eq!(other.compute_app_hash(&batch)?, pending_hash);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_proofs_match_app_hash() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+
+        let mut batch = storage.batch();
+        storage.set_last_height(3, &mut batch)?;
+        storage.store_block_random(2, &[2u8; 32], &mut batch)?;
+        storage.store_block_random(3, &[3u8; 32], &mut batch)?;
+        storage.apply_batch(batch)?;
+        let app_hash = storage.compute_app_hash(&storage.batch())?;
+
+        match storage.prove_block_random(3)? {
+            merkle::StateProof::Exists(proof) => {
+                assert_eq!(proof.key, b"app/block_random/3");
+                assert_eq!(proof.value, vec![3u8; 32]);
+                assert!(proof.verify(&app_hash));
+            }
+            other => panic!("expected existence proof, got {:?}", other),
+        }
+
+        // "app/bets/.." sorts before every other key
+        match storage.prove_bet(&[1u8; 32])? {
+            merkle::StateProof::Absent { left, right, .. } => {
+                assert!(left.is_none());
+                let right = right.unwrap();
+                assert_eq!(right.key, b"app/block_random/2");
+                assert!(right.verify(&app_hash));
+            }
+            other => panic!("expected absence proof, got {:?}", other),
+        }
+
+        // Between two heights: both neighbours are proven
+        match storage.prove_block_random(25)? {
+            merkle::StateProof::Absent { left, right, .. } => {
+                assert!(left.unwrap().verify(&app_hash));
+                assert!(right.unwrap().verify(&app_hash));
+            }
+            other => panic!("expected absence proof, got {:?}", other),
+        }
+
+        Ok(())
+    }
+}
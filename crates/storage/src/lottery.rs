@@ -0,0 +1,236 @@
+//! Lottery draws, the tickets held in them and their winners
+//!
+//! Draws are indexed by draw height, so the block at that height finds the draws
+//! it has to make without scanning every draw. A draw that could not be settled
+//! there is indexed again under a later height.
+
+use anyhow::{Context, Result};
+
+use mychain_types::{LotteryDraw, LotteryWinner};
+
+use crate::{merkle, BatchOperation, Storage, StorageBatch};
+
+impl Storage {
+    /// Get the id of the latest lottery draw opened (None before the first)
+    pub fn get_latest_lottery_draw_id(&self) -> Result<Option<u64>> {
+        self.get_pending_latest_lottery_draw_id(&self.batch())
+    }
+
+    /// Get the id of the latest lottery draw opened, including writes pending in `batch`
+    pub fn get_pending_latest_lottery_draw_id(&self, batch: &StorageBatch) -> Result<Option<u64>> {
+        match self.get_with_batch("app", b"lottery_latest_draw", batch)? {
+            Some(bytes) => {
+                let id: [u8; 8] = bytes.as_slice().try_into()
+                    .context("Invalid lottery draw id format")?;
+                Ok(Some(u64::from_le_bytes(id)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get a lottery draw by id
+    pub fn get_lottery_draw(&self, id: u64) -> Result<Option<LotteryDraw>> {
+        self.get_pending_lottery_draw(id, &self.batch())
+    }
+
+    /// Get a lottery draw by id, including writes pending in `batch`
+    pub fn get_pending_lottery_draw(&self, id: u64, batch: &StorageBatch) -> Result<Option<LotteryDraw>> {
+        match self.get_with_batch("app", &draw_key(id), batch)? {
+            Some(bytes) => Ok(Some(LotteryDraw::from_bytes(&bytes)
+                .context("Invalid lottery draw format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a lottery draw, indexing it under its draw height the first time
+    pub fn store_lottery_draw(&self, draw: &LotteryDraw, batch: &mut StorageBatch) -> Result<()> {
+        self.schedule_lottery_draw(draw.id, draw.draw_height, batch)?;
+        if self.get_pending_latest_lottery_draw_id(batch)?.is_none_or(|latest| latest < draw.id) {
+            batch.operations.push(BatchOperation::Insert {
+                tree_name: "app".to_string(),
+                key: b"lottery_latest_draw".to_vec(),
+                value: draw.id.to_le_bytes().to_vec(),
+            });
+        }
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: draw_key(draw.id),
+            value: draw.to_bytes()?,
+        });
+        Ok(())
+    }
+
+    /// Make a draw due at the end of a height, leaving its draw height unchanged
+    pub fn schedule_lottery_draw(&self, id: u64, height: u64, batch: &mut StorageBatch) -> Result<()> {
+        let mut due = self.get_pending_lottery_draws_due(height, batch)?;
+        if !due.contains(&id) {
+            due.push(id);
+            batch.operations.push(BatchOperation::Insert {
+                tree_name: "app".to_string(),
+                key: due_key(height),
+                value: bincode::serialize(&due)?,
+            });
+        }
+        Ok(())
+    }
+
+    /// Draws made at the end of a height, in the order they were opened or scheduled
+    pub fn get_lottery_draws_due(&self, height: u64) -> Result<Vec<u64>> {
+        self.get_pending_lottery_draws_due(height, &self.batch())
+    }
+
+    /// Draws made at the end of a height, including writes pending in `batch`
+    pub fn get_pending_lottery_draws_due(&self, height: u64, batch: &StorageBatch) -> Result<Vec<u64>> {
+        match self.get_with_batch("app", &due_key(height), batch)? {
+            Some(bytes) => bincode::deserialize(&bytes).context("Invalid lottery draws format"),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Get the number of tickets a wallet holds in a draw (0 if none)
+    pub fn get_lottery_tickets(&self, draw: u64, wallet: &[u8; 32]) -> Result<u64> {
+        self.get_pending_lottery_tickets(draw, wallet, &self.batch())
+    }
+
+    /// Get the number of tickets a wallet holds in a draw, including writes pending in `batch`
+    pub fn get_pending_lottery_tickets(&self, draw: u64, wallet: &[u8; 32], batch: &StorageBatch) -> Result<u64> {
+        match self.get_with_batch("app", &tickets_key(draw, wallet), batch)? {
+            Some(bytes) => {
+                let tickets: [u8; 8] = bytes.as_slice().try_into()
+                    .context("Invalid lottery tickets format")?;
+                Ok(u64::from_le_bytes(tickets))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Set the number of tickets a wallet holds in a draw
+    pub fn set_lottery_tickets(
+        &self,
+        draw: u64,
+        wallet: &[u8; 32],
+        tickets: u64,
+        batch: &mut StorageBatch,
+    ) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: tickets_key(draw, wallet),
+            value: tickets.to_le_bytes().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Get the winners of a draw, in the order they were drawn (None before the draw)
+    pub fn get_lottery_winners(&self, draw: u64) -> Result<Option<Vec<LotteryWinner>>> {
+        match self.get_with_batch("app", &winners_key(draw), &self.batch())? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes).context("Invalid lottery winners format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the winners of a draw
+    pub fn store_lottery_winners(
+        &self,
+        draw: u64,
+        winners: &[LotteryWinner],
+        batch: &mut StorageBatch,
+    ) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: winners_key(draw),
+            value: bincode::serialize(winners)?,
+        });
+        Ok(())
+    }
+
+    /// Merkle proof for a lottery draw against the last committed app hash
+    pub fn prove_lottery_draw(&self, id: u64) -> Result<merkle::StateProof> {
+        self.prove("app", &draw_key(id))
+    }
+
+    /// Merkle proof for the tickets of a wallet in a draw against the last committed app hash
+    pub fn prove_lottery_tickets(&self, draw: u64, wallet: &[u8; 32]) -> Result<merkle::StateProof> {
+        self.prove("app", &tickets_key(draw, wallet))
+    }
+
+    /// Merkle proof for the winners of a draw against the last committed app hash
+    pub fn prove_lottery_winners(&self, draw: u64) -> Result<merkle::StateProof> {
+        self.prove("app", &winners_key(draw))
+    }
+}
+
+fn draw_key(id: u64) -> Vec<u8> {
+    format!("lottery_draws/{}", id).into_bytes()
+}
+
+fn due_key(height: u64) -> Vec<u8> {
+    format!("lottery_due/{}", height).into_bytes()
+}
+
+fn tickets_key(draw: u64, wallet: &[u8; 32]) -> Vec<u8> {
+    format!("lottery_tickets/{}/{}", draw, hex::encode(wallet)).into_bytes()
+}
+
+fn winners_key(draw: u64) -> Vec<u8> {
+    format!("lottery_winners/{}", draw).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn draw(id: u64, draw_height: u64) -> LotteryDraw {
+        LotteryDraw {
+            id,
+            ticket_price: 10,
+            draw_height,
+            winners: 1,
+            house_cut_bps: 0,
+            tickets_sold: 0,
+            buyers: Vec::new(),
+            drawn: false,
+            vrf_message: Vec::new(),
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_lottery_draws_tickets_and_winners() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        assert_eq!(storage.get_latest_lottery_draw_id()?, None);
+
+        let mut batch = storage.batch();
+        storage.store_lottery_draw(&draw(1, 20), &mut batch)?;
+        storage.store_lottery_draw(&draw(2, 20), &mut batch)?;
+        storage.set_lottery_tickets(1, &[3u8; 32], 4, &mut batch)?;
+        assert_eq!(storage.get_pending_lottery_tickets(1, &[3u8; 32], &batch)?, 4);
+        assert_eq!(storage.get_lottery_draw(1)?, None);
+        storage.apply_batch(batch)?;
+
+        // Updating an older draw keeps the latest id and the index unchanged
+        let mut batch = storage.batch();
+        storage.store_lottery_draw(&LotteryDraw { drawn: true, ..draw(1, 20) }, &mut batch)?;
+        let winners = vec![LotteryWinner { ticket: 2, wallet: [3u8; 32], prize: 40 }];
+        storage.store_lottery_winners(1, &winners, &mut batch)?;
+        storage.schedule_lottery_draw(2, 21, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        assert_eq!(storage.get_latest_lottery_draw_id()?, Some(2));
+        assert_eq!(storage.get_lottery_draws_due(20)?, vec![1, 2]);
+        assert_eq!(storage.get_lottery_draws_due(21)?, vec![2]);
+        assert_eq!(storage.get_lottery_draws_due(22)?, Vec::<u64>::new());
+        assert_eq!(storage.get_lottery_draw(2)?.unwrap().draw_height, 20);
+        assert!(storage.get_lottery_draw(1)?.unwrap().drawn);
+        assert_eq!(storage.get_lottery_tickets(1, &[3u8; 32])?, 4);
+        assert_eq!(storage.get_lottery_tickets(2, &[3u8; 32])?, 0);
+        assert_eq!(storage.get_lottery_winners(1)?, Some(winners));
+        assert_eq!(storage.get_lottery_winners(2)?, None);
+        assert!(matches!(storage.prove_lottery_draw(1)?, merkle::StateProof::Exists(_)));
+        assert!(matches!(storage.prove_lottery_winners(1)?, merkle::StateProof::Exists(_)));
+
+        Ok(())
+    }
+}
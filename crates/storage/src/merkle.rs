@@ -0,0 +1,306 @@
+//! Binary Merkle tree over the sorted application state
+//!
+//! Hashing follows RFC 6962 as used by CometBFT's simple Merkle tree, with leaves
+//! encoded the way the ICS23 `tendermint_spec` expects, so roots and proofs can be
+//! checked by standard ICS23 verifiers:
+//!
+//! - leaf  = SHA256(0x00 || varint(len(key)) || key || varint(32) || SHA256(value))
+//! - inner = SHA256(0x01 || left || right)
+//! - empty = SHA256("")
+//!
+//! A tree of `n` leaves splits at the largest power of two smaller than `n`.
+
+use sha2::{Digest, Sha256};
+
+/// Prefix byte of leaf nodes
+pub const LEAF_PREFIX: u8 = 0x00;
+
+/// Prefix byte of inner nodes
+pub const INNER_PREFIX: u8 = 0x01;
+
+/// Which side of the path a sibling hash sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// One step from a node up to its parent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathStep {
+    /// Hash of the sibling node
+    pub sibling: [u8; 32],
+    /// Side of the sibling relative to the node being proven
+    pub side: Side,
+}
+
+/// Existence proof for a single key/value pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// Full state key (`{keyspace}/{key}`)
+    pub key: Vec<u8>,
+    /// Value stored under the key
+    pub value: Vec<u8>,
+    /// Steps from the leaf up to the root
+    pub path: Vec<PathStep>,
+}
+
+impl MerkleProof {
+    /// Recompute the root implied by this proof
+    pub fn compute_root(&self) -> [u8; 32] {
+        self.path.iter().fold(leaf_hash(&self.key, &self.value), |node, step| match step.side {
+            Side::Left => inner_hash(&step.sibling, &node),
+            Side::Right => inner_hash(&node, &step.sibling),
+        })
+    }
+
+    /// Check the proof against a root
+    pub fn verify(&self, root: &[u8; 32]) -> bool {
+        &self.compute_root() == root
+    }
+}
+
+/// Proof that a key is present in, or absent from, the state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateProof {
+    /// The key exists with the proven value
+    Exists(MerkleProof),
+    /// The key is absent: proofs of its immediate neighbours in key order
+    /// (either may be missing at the edges of the tree)
+    Absent {
+        key: Vec<u8>,
+        left: Option<MerkleProof>,
+        right: Option<MerkleProof>,
+    },
+}
+
+impl StateProof {
+    /// Full state key the proof is about
+    pub fn key(&self) -> &[u8] {
+        match self {
+            StateProof::Exists(proof) => &proof.key,
+            StateProof::Absent { key, .. } => key,
+        }
+    }
+
+    /// Build the proof for `key` from the full, sorted state
+    pub fn build(entries: &[(Vec<u8>, Vec<u8>)], key: &[u8]) -> Self {
+        let leaves: Vec<[u8; 32]> = entries.iter().map(|(k, v)| leaf_hash(k, v)).collect();
+        let prove = |index: usize| {
+            let (key, value) = &entries[index];
+            MerkleProof {
+                key: key.clone(),
+                value: value.clone(),
+                path: path(&leaves, index).expect("index is in range"),
+            }
+        };
+
+        match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+            Ok(index) => StateProof::Exists(prove(index)),
+            Err(insert_at) => StateProof::Absent {
+                key: key.to_vec(),
+                left: insert_at.checked_sub(1).map(prove),
+                right: (insert_at < entries.len()).then(|| prove(insert_at)),
+            },
+        }
+    }
+}
+
+/// Hash of an empty tree
+pub fn empty_hash() -> [u8; 32] {
+    Sha256::digest([]).into()
+}
+
+/// Hash of a leaf
+pub fn leaf_hash(key: &[u8], value: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(encode_varint(key.len() as u64));
+    hasher.update(key);
+    hasher.update(encode_varint(32));
+    hasher.update(Sha256::digest(value));
+    hasher.finalize().into()
+}
+
+/// Hash of an inner node
+pub fn inner_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([INNER_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Root of a tree over leaf hashes (in key order)
+pub fn root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    match leaves.len() {
+        0 => empty_hash(),
+        1 => leaves[0],
+        n => {
+            let split = split_point(n);
+            inner_hash(&root(&leaves[..split]), &root(&leaves[split..]))
+        }
+    }
+}
+
+/// Path from leaf `index` up to the root, or `None` when out of range
+pub fn path(leaves: &[[u8; 32]], index: usize) -> Option<Vec<PathStep>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut steps = Vec::new();
+    collect_path(leaves, index, &mut steps);
+    Some(steps)
+}
+
+/// Build the path top-down, then reverse so it reads leaf-to-root
+fn collect_path(leaves: &[[u8; 32]], index: usize, steps: &mut Vec<PathStep>) {
+    if leaves.len() <= 1 {
+        steps.reverse();
+        return;
+    }
+    let split = split_point(leaves.len());
+    if index < split {
+        steps.push(PathStep { sibling: root(&leaves[split..]), side: Side::Right });
+        collect_path(&leaves[..split], index, steps);
+    } else {
+        steps.push(PathStep { sibling: root(&leaves[..split]), side: Side::Left });
+        collect_path(&leaves[split..], index - split, steps);
+    }
+}
+
+/// Largest power of two strictly smaller than `n` (n >= 2)
+fn split_point(n: usize) -> usize {
+    let mut split = 1;
+    while split * 2 < n {
+        split *= 2;
+    }
+    split
+}
+
+/// Protobuf-style unsigned varint
+pub fn encode_varint(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(10);
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..n)
+            .map(|i| (format!("app/key{:03}", i).into_bytes(), vec![i as u8; i + 1]))
+            .collect()
+    }
+
+    fn hashes(entries: &[(Vec<u8>, Vec<u8>)]) -> Vec<[u8; 32]> {
+        entries.iter().map(|(k, v)| leaf_hash(k, v)).collect()
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        // SHA256("") and the RFC 6962 inner node layout
+        assert_eq!(
+            hex::encode(empty_hash()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(encode_varint(0), vec![0]);
+        assert_eq!(encode_varint(300), vec![0xac, 0x02]);
+
+        let a = [1u8; 32];
+        let b = [2u8; 32];
+        let mut expected = vec![INNER_PREFIX];
+        expected.extend_from_slice(&a);
+        expected.extend_from_slice(&b);
+        assert_eq!(inner_hash(&a, &b), <[u8; 32]>::from(Sha256::digest(&expected)));
+    }
+
+    #[test]
+    fn test_state_proof_build() {
+        let entries = leaves(5);
+        let tree_root = root(&hashes(&entries));
+
+        match StateProof::build(&entries, b"app/key002") {
+            StateProof::Exists(proof) => {
+                assert_eq!(proof.value, entries[2].1);
+                assert!(proof.verify(&tree_root));
+            }
+            other => panic!("expected existence proof, got {:?}", other),
+        }
+
+        // Between two keys: both neighbours are proven
+        match StateProof::build(&entries, b"app/key002x") {
+            StateProof::Absent { left, right, .. } => {
+                assert_eq!(left.as_ref().unwrap().key, entries[2].0);
+                assert_eq!(right.as_ref().unwrap().key, entries[3].0);
+                assert!(left.unwrap().verify(&tree_root));
+                assert!(right.unwrap().verify(&tree_root));
+            }
+            other => panic!("expected absence proof, got {:?}", other),
+        }
+
+        // Before the first and after the last key
+        assert!(matches!(
+            StateProof::build(&entries, b"a"),
+            StateProof::Absent { left: None, right: Some(_), .. }
+        ));
+        assert!(matches!(
+            StateProof::build(&entries, b"zzz"),
+            StateProof::Absent { left: Some(_), right: None, .. }
+        ));
+        assert!(matches!(
+            StateProof::build(&[], b"a"),
+            StateProof::Absent { left: None, right: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_split_point() {
+        assert_eq!(split_point(2), 1);
+        assert_eq!(split_point(3), 2);
+        assert_eq!(split_point(4), 2);
+        assert_eq!(split_point(5), 4);
+        assert_eq!(split_point(9), 8);
+    }
+
+    #[test]
+    fn test_root_changes_with_any_leaf() {
+        let entries = leaves(7);
+        let base = root(&hashes(&entries));
+
+        for i in 0..entries.len() {
+            let mut changed = entries.clone();
+            changed[i].1.push(0xff);
+            assert_ne!(root(&hashes(&changed)), base, "leaf {} not committed", i);
+        }
+    }
+
+    #[test]
+    fn test_proofs_verify_for_every_leaf() {
+        for n in 1..=17 {
+            let entries = leaves(n);
+            let leaf_hashes = hashes(&entries);
+            let tree_root = root(&leaf_hashes);
+
+            for (index, (key, value)) in entries.iter().enumerate() {
+                let proof = MerkleProof {
+                    key: key.clone(),
+                    value: value.clone(),
+                    path: path(&leaf_hashes, index).unwrap(),
+                };
+                assert!(proof.verify(&tree_root), "n={} index={}", n, index);
+
+                let mut tampered = proof.clone();
+                tampered.value.push(0);
+                assert!(!tampered.verify(&tree_root));
+            }
+            assert!(path(&leaf_hashes, n).is_none());
+        }
+    }
+}
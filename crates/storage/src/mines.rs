@@ -0,0 +1,85 @@
+//! Mines games, one per wallet
+//!
+//! A wallet's game stays stored once it ends, with its board published, until
+//! the wallet opens the next one.
+
+use anyhow::{Context, Result};
+
+use mychain_types::MinesGame;
+
+use crate::{merkle, BatchOperation, Storage, StorageBatch};
+
+impl Storage {
+    /// Get the latest mines game of a wallet, in play or ended
+    pub fn get_mines_game(&self, wallet: &[u8; 32]) -> Result<Option<MinesGame>> {
+        self.get_pending_mines_game(wallet, &self.batch())
+    }
+
+    /// Get the latest mines game of a wallet, including writes pending in `batch`
+    pub fn get_pending_mines_game(&self, wallet: &[u8; 32], batch: &StorageBatch) -> Result<Option<MinesGame>> {
+        match self.get_with_batch("app", &game_key(wallet), batch)? {
+            Some(bytes) => Ok(Some(MinesGame::from_bytes(&bytes)
+                .context("Invalid mines game format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the mines game of its wallet
+    pub fn store_mines_game(&self, game: &MinesGame, batch: &mut StorageBatch) -> Result<()> {
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: game_key(&game.wallet),
+            value: game.to_bytes()?,
+        });
+        Ok(())
+    }
+
+    /// Merkle proof for the mines game of a wallet against the last committed app hash
+    pub fn prove_mines_game(&self, wallet: &[u8; 32]) -> Result<merkle::StateProof> {
+        self.prove("app", &game_key(wallet))
+    }
+}
+
+fn game_key(wallet: &[u8; 32]) -> Vec<u8> {
+    format!("mines_games/{}", hex::encode(wallet)).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_mines_games() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        let game = MinesGame {
+            wallet: [1u8; 32],
+            height: 10,
+            tx_hash: [2u8; 32],
+            nonce: 0,
+            amount: 50,
+            grid: 5,
+            mines: 3,
+            vrf_message: vec![3],
+            commitment: [4u8; 32],
+            revealed: vec![7],
+            finished: false,
+            board: Vec::new(),
+            vrf_proof: Vec::new(),
+            vrf_output: Vec::new(),
+        };
+
+        let mut batch = storage.batch();
+        storage.store_mines_game(&game, &mut batch)?;
+        assert_eq!(storage.get_pending_mines_game(&[1u8; 32], &batch)?, Some(game.clone()));
+        assert_eq!(storage.get_mines_game(&[1u8; 32])?, None);
+
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_mines_game(&[1u8; 32])?, Some(game));
+        assert_eq!(storage.get_mines_game(&[2u8; 32])?, None);
+        assert!(matches!(storage.prove_mines_game(&[1u8; 32])?, merkle::StateProof::Exists(_)));
+
+        Ok(())
+    }
+}
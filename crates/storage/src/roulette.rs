@@ -0,0 +1,115 @@
+//! Roulette spins: one per table and block, shared by every bet at the table
+//!
+//! Spins are written by the first bet at a table in a block, so later bets of the
+//! same block read them through the pending batch.
+
+use anyhow::{Context, Result};
+
+use mychain_types::RouletteSpin;
+
+use crate::{merkle, BatchOperation, Storage, StorageBatch};
+
+impl Storage {
+    /// Get the spin of a table at a height
+    pub fn get_roulette_spin(&self, height: u64, table: u32) -> Result<Option<RouletteSpin>> {
+        self.get_pending_roulette_spin(height, table, &self.batch())
+    }
+
+    /// Get the spin of a table at a height, including writes pending in `batch`
+    pub fn get_pending_roulette_spin(
+        &self,
+        height: u64,
+        table: u32,
+        batch: &StorageBatch,
+    ) -> Result<Option<RouletteSpin>> {
+        match self.get_with_batch("app", &spin_key(height, table), batch)? {
+            Some(bytes) => Ok(Some(RouletteSpin::from_bytes(&bytes)
+                .context("Invalid roulette spin format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a spin and add its table to the tables spun at its height
+    pub fn store_roulette_spin(&self, spin: &RouletteSpin, batch: &mut StorageBatch) -> Result<()> {
+        let mut tables = self.get_pending_roulette_tables(spin.height, batch)?;
+        if !tables.contains(&spin.table) {
+            tables.push(spin.table);
+        }
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: tables_key(spin.height),
+            value: bincode::serialize(&tables)?,
+        });
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: spin_key(spin.height, spin.table),
+            value: spin.to_bytes()?,
+        });
+        Ok(())
+    }
+
+    /// Tables spun at a height, in the order they were first bet on
+    pub fn get_roulette_tables(&self, height: u64) -> Result<Vec<u32>> {
+        self.get_pending_roulette_tables(height, &self.batch())
+    }
+
+    /// Tables spun at a height, including writes pending in `batch`
+    pub fn get_pending_roulette_tables(&self, height: u64, batch: &StorageBatch) -> Result<Vec<u32>> {
+        match self.get_with_batch("app", &tables_key(height), batch)? {
+            Some(bytes) => bincode::deserialize(&bytes).context("Invalid roulette tables format"),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Merkle proof for the spin of a table against the last committed app hash
+    pub fn prove_roulette_spin(&self, height: u64, table: u32) -> Result<merkle::StateProof> {
+        self.prove("app", &spin_key(height, table))
+    }
+}
+
+fn spin_key(height: u64, table: u32) -> Vec<u8> {
+    format!("roulette_spins/{}/{}", height, table).into_bytes()
+}
+
+fn tables_key(height: u64) -> Vec<u8> {
+    format!("roulette_tables/{}", height).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn spin(height: u64, table: u32, number: u8) -> RouletteSpin {
+        RouletteSpin {
+            table,
+            height,
+            vrf_message: vec![1],
+            vrf_proof: vec![2],
+            vrf_output: vec![3],
+            number,
+        }
+    }
+
+    #[test]
+    fn test_spins_read_pending_writes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+
+        let mut batch = storage.batch();
+        storage.store_roulette_spin(&spin(5, 2, 17), &mut batch)?;
+        storage.store_roulette_spin(&spin(5, 0, 3), &mut batch)?;
+        assert_eq!(storage.get_pending_roulette_spin(5, 2, &batch)?, Some(spin(5, 2, 17)));
+        assert_eq!(storage.get_pending_roulette_tables(5, &batch)?, vec![2, 0]);
+        assert_eq!(storage.get_roulette_spin(5, 2)?, None);
+
+        storage.apply_batch(batch)?;
+        assert_eq!(storage.get_roulette_spin(5, 0)?, Some(spin(5, 0, 3)));
+        assert_eq!(storage.get_roulette_spin(6, 0)?, None);
+        assert_eq!(storage.get_roulette_tables(5)?, vec![2, 0]);
+        assert!(storage.get_roulette_tables(6)?.is_empty());
+        assert!(matches!(storage.prove_roulette_spin(5, 2)?, merkle::StateProof::Exists(_)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+//! Game sessions, the sessions each wallet has in play and their timeouts
+//!
+//! Sessions are indexed by the height they expire at, so the block at that
+//! height finds the sessions it has to time out without scanning every session.
+//! A session acted on moves to a later height; the entry left at the earlier one
+//! is stale, and skipped because the session no longer expires there.
+
+use anyhow::{Context, Result};
+
+use mychain_types::GameSession;
+
+use crate::{merkle, BatchOperation, Storage, StorageBatch};
+
+impl Storage {
+    /// Get the id of the latest game session opened (None before the first)
+    pub fn get_latest_session_id(&self) -> Result<Option<u64>> {
+        self.get_pending_latest_session_id(&self.batch())
+    }
+
+    /// Get the id of the latest game session opened, including writes pending in `batch`
+    pub fn get_pending_latest_session_id(&self, batch: &StorageBatch) -> Result<Option<u64>> {
+        match self.get_with_batch("app", b"session_latest", batch)? {
+            Some(bytes) => {
+                let id: [u8; 8] = bytes.as_slice().try_into()
+                    .context("Invalid session id format")?;
+                Ok(Some(u64::from_le_bytes(id)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get a game session by id
+    pub fn get_game_session(&self, id: u64) -> Result<Option<GameSession>> {
+        self.get_pending_game_session(id, &self.batch())
+    }
+
+    /// Get a game session by id, including writes pending in `batch`
+    pub fn get_pending_game_session(&self, id: u64, batch: &StorageBatch) -> Result<Option<GameSession>> {
+        match self.get_with_batch("app", &session_key(id), batch)? {
+            Some(bytes) => Ok(Some(GameSession::from_bytes(&bytes)
+                .context("Invalid game session format")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a game session, indexing it under its wallet while it is in play and
+    /// under the height it expires at
+    pub fn store_game_session(&self, session: &GameSession, batch: &mut StorageBatch) -> Result<()> {
+        let mut active = self.get_pending_active_sessions(&session.wallet, batch)?;
+        let listed = active.contains(&session.id);
+        if listed == session.finished {
+            active.retain(|&id| id != session.id);
+            if !session.finished {
+                active.push(session.id);
+            }
+            batch.operations.push(BatchOperation::Insert {
+                tree_name: "app".to_string(),
+                key: active_key(&session.wallet),
+                value: bincode::serialize(&active)?,
+            });
+        }
+        if !session.finished {
+            let mut due = self.get_pending_sessions_due(session.expires_at, batch)?;
+            if !due.contains(&session.id) {
+                due.push(session.id);
+                batch.operations.push(BatchOperation::Insert {
+                    tree_name: "app".to_string(),
+                    key: due_key(session.expires_at),
+                    value: bincode::serialize(&due)?,
+                });
+            }
+        }
+        if self.get_pending_latest_session_id(batch)?.is_none_or(|latest| latest < session.id) {
+            batch.operations.push(BatchOperation::Insert {
+                tree_name: "app".to_string(),
+                key: b"session_latest".to_vec(),
+                value: session.id.to_le_bytes().to_vec(),
+            });
+        }
+        batch.operations.push(BatchOperation::Insert {
+            tree_name: "app".to_string(),
+            key: session_key(session.id),
+            value: session.to_bytes()?,
+        });
+        Ok(())
+    }
+
+    /// Ids of the sessions a wallet has in play, in the order they were opened
+    pub fn get_active_sessions(&self, wallet: &[u8; 32]) -> Result<Vec<u64>> {
+        self.get_pending_active_sessions(wallet, &self.batch())
+    }
+
+    /// Ids of the sessions a wallet has in play, including writes pending in `batch`
+    pub fn get_pending_active_sessions(&self, wallet: &[u8; 32], batch: &StorageBatch) -> Result<Vec<u64>> {
+        match self.get_with_batch("app", &active_key(wallet), batch)? {
+            Some(bytes) => bincode::deserialize(&bytes).context("Invalid active sessions format"),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Sessions that have expired at a height unless acted on since, in the order they were indexed
+    pub fn get_sessions_due(&self, height: u64) -> Result<Vec<u64>> {
+        self.get_pending_sessions_due(height, &self.batch())
+    }
+
+    /// Sessions that have expired at a height, including writes pending in `batch`
+    pub fn get_pending_sessions_due(&self, height: u64, batch: &StorageBatch) -> Result<Vec<u64>> {
+        match self.get_with_batch("app", &due_key(height), batch)? {
+            Some(bytes) => bincode::deserialize(&bytes).context("Invalid sessions due format"),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Merkle proof for a game session against the last committed app hash
+    pub fn prove_game_session(&self, id: u64) -> Result<merkle::StateProof> {
+        self.prove("app", &session_key(id))
+    }
+
+    /// Merkle proof for the sessions a wallet has in play against the last committed app hash
+    pub fn prove_active_sessions(&self, wallet: &[u8; 32]) -> Result<merkle::StateProof> {
+        self.prove("app", &active_key(wallet))
+    }
+}
+
+fn session_key(id: u64) -> Vec<u8> {
+    format!("sessions/{}", id).into_bytes()
+}
+
+fn active_key(wallet: &[u8; 32]) -> Vec<u8> {
+    format!("session_active/{}", hex::encode(wallet)).into_bytes()
+}
+
+fn due_key(height: u64) -> Vec<u8> {
+    format!("session_due/{}", height).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn session(id: u64, expires_at: u64) -> GameSession {
+        GameSession {
+            id,
+            game: "mines".to_string(),
+            wallet: [1u8; 32],
+            stake: 50,
+            state: "open".to_string(),
+            opened_height: 10,
+            last_action_height: 10,
+            expires_at,
+            finished: false,
+            payout: 0,
+        }
+    }
+
+    #[test]
+    fn test_game_sessions() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = Storage::open(temp_dir.path())?;
+        assert_eq!(storage.get_latest_session_id()?, None);
+
+        let mut batch = storage.batch();
+        storage.store_game_session(&session(1, 20), &mut batch)?;
+        storage.store_game_session(&session(2, 20), &mut batch)?;
+        assert_eq!(storage.get_pending_active_sessions(&[1u8; 32], &batch)?, vec![1, 2]);
+        assert_eq!(storage.get_game_session(1)?, None);
+        storage.apply_batch(batch)?;
+
+        // Acting on a session moves its timeout; settling it drops it from its wallet
+        let mut batch = storage.batch();
+        storage.store_game_session(&GameSession { last_action_height: 15, ..session(1, 25) }, &mut batch)?;
+        storage.store_game_session(&GameSession { finished: true, payout: 75, ..session(2, 20) }, &mut batch)?;
+        storage.apply_batch(batch)?;
+
+        assert_eq!(storage.get_latest_session_id()?, Some(2));
+        assert_eq!(storage.get_active_sessions(&[1u8; 32])?, vec![1]);
+        assert_eq!(storage.get_active_sessions(&[2u8; 32])?, Vec::<u64>::new());
+        assert_eq!(storage.get_sessions_due(20)?, vec![1, 2]);
+        assert_eq!(storage.get_sessions_due(25)?, vec![1]);
+        assert_eq!(storage.get_game_session(1)?.unwrap().expires_at, 25);
+        assert_eq!(storage.get_game_session(2)?.unwrap().payout, 75);
+        assert_eq!(storage.get_game_session(3)?, None);
+        assert!(matches!(storage.prove_game_session(1)?, merkle::StateProof::Exists(_)));
+        assert!(matches!(storage.prove_active_sessions(&[1u8; 32])?, merkle::StateProof::Exists(_)));
+
+        Ok(())
+    }
+}
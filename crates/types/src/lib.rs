@@ -1,25 +1,94 @@
+use bincode::Options;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
 
+/// Domain separator prefixed to every transaction sign-doc
+pub const TX_SIGN_DOMAIN: &[u8] = b"MYCHAIN:TX:v1";
+
+/// Current version of the transaction envelope
+pub const TX_VERSION: u8 = 1;
+
+/// Length of an ed25519 signature
+pub const SIGNATURE_LENGTH: usize = 64;
+
+/// Length of the fixed envelope header: version || kind || public key || signature
+pub const TX_HEADER_LENGTH: usize = 1 + 2 + 32 + SIGNATURE_LENGTH;
+
+/// Errors from checking a signed transaction
+#[derive(Debug, thiserror::Error)]
+pub enum TxError {
+    #[error("invalid public key")]
+    InvalidPublicKey,
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("wallet does not match signer public key")]
+    WalletMismatch,
+    #[error("transaction shorter than the {TX_HEADER_LENGTH}-byte envelope header")]
+    Truncated,
+    #[error("unsupported transaction version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unknown transaction kind {0}")]
+    UnknownKind(u16),
+    #[error("encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+}
+
+/// Derive a wallet address from an ed25519 public key
+/// wallet = blake3('MYCHAIN:WALLET:v1' || public_key)
+pub fn wallet_from_public_key(public_key: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"MYCHAIN:WALLET:v1");
+    hasher.update(public_key);
+    *hasher.finalize().as_bytes()
+}
+
+/// Side of a coin
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CoinSide {
+    Heads,
+    Tails,
+}
+
+impl CoinSide {
+    /// Side shown by a flip result (true = heads)
+    pub fn from_result(result: bool) -> Self {
+        if result {
+            CoinSide::Heads
+        } else {
+            CoinSide::Tails
+        }
+    }
+
+    /// Lowercase name, as used in events
+    pub fn name(self) -> &'static str {
+        match self {
+            CoinSide::Heads => "heads",
+            CoinSide::Tails => "tails",
+        }
+    }
+}
+
 /// Transaction for a coin flip bet
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TxFlip {
-    /// Version for future compatibility
-    pub version: u8,
     /// Wallet address (32 bytes)
     pub wallet: [u8; 32],
     /// Bet amount in minimal units
     pub amount: u64,
+    /// Side the player bets on
+    pub choice: CoinSide,
     /// Nonce to prevent replay attacks
     pub nonce: u64,
 }
 
 impl TxFlip {
     /// Create a new flip transaction
-    pub fn new(wallet: [u8; 32], amount: u64, nonce: u64) -> Self {
+    pub fn new(wallet: [u8; 32], amount: u64, choice: CoinSide, nonce: u64) -> Self {
         Self {
-            version: 1,
             wallet,
             amount,
+            choice,
             nonce,
         }
     }
@@ -41,6 +110,1072 @@ impl TxFlip {
     }
 }
 
+/// Number of equally likely dice rolls: a roll is in `0..DICE_SIDES`
+pub const DICE_SIDES: u16 = 10_000;
+
+/// Lowest dice target
+pub const MIN_DICE_TARGET: u16 = 1;
+
+/// Highest dice target
+pub const MAX_DICE_TARGET: u16 = DICE_SIDES - 1;
+
+/// Transaction for a roll-under dice bet: wins when the roll is below `target`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxDice {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Bet amount in minimal units
+    pub amount: u64,
+    /// Winning rolls are `0..target`, with target in [`MIN_DICE_TARGET`]..=[`MAX_DICE_TARGET`]
+    pub target: u16,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Pockets on a European roulette wheel: 0 to 36
+pub const ROULETTE_POCKETS: u8 = 37;
+
+/// Numbers a roulette bet covers
+///
+/// Inside bets name the lowest number they cover, outside bets name a group.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RouletteBet {
+    /// One number, 0 to 36
+    Straight(u8),
+    /// Two adjacent numbers, lowest first (0 pairs with 1, 2 and 3)
+    Split(u8, u8),
+    /// Row of three starting at 1, 4, ..., 34
+    Street(u8),
+    /// Square of four with the given top-left number
+    Corner(u8),
+    /// Two rows of three starting at 1, 4, ..., 31
+    SixLine(u8),
+    /// 1-12, 13-24 or 25-36
+    Dozen(u8),
+    /// Numbers with the same remainder modulo 3 as the column (1, 2 or 3)
+    Column(u8),
+    Red,
+    Black,
+    Odd,
+    Even,
+    /// 1-18
+    Low,
+    /// 19-36
+    High,
+}
+
+/// Transaction for a bet at a roulette table
+///
+/// All bets at a table in a block settle against the same spin.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxRoulette {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Bet amount in minimal units
+    pub amount: u64,
+    /// Table the bet is placed at
+    pub table: u32,
+    /// Numbers the bet covers
+    pub bet: RouletteBet,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Spin of a roulette table, shared by all bets at the table in a block
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RouletteSpin {
+    /// Table that was spun
+    pub table: u32,
+    /// Block height of the spin
+    pub height: u64,
+    /// VRF message that was signed
+    pub vrf_message: Vec<u8>,
+    /// VRF proof
+    pub vrf_proof: Vec<u8>,
+    /// VRF output
+    pub vrf_output: Vec<u8>,
+    /// Winning number, 0 to 36
+    pub number: u8,
+}
+
+impl RouletteSpin {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// Transaction for a bet on the crash round taking bets, opening one if none is
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxCrashBet {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Bet amount in minimal units
+    pub amount: u64,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Transaction cashing out a crash bet at the multiplier of the block it lands in
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxCrashCashOut {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Round the bet was placed in
+    pub round: u64,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// A crash round: bets are taken until `flight_height`, then the multiplier grows
+/// every block until it reaches the crash point
+///
+/// The crash point is derived from a VRF output committed to when the round opens
+/// and only published when it ends.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CrashRound {
+    /// Round id, counting from 1
+    pub id: u64,
+    /// Height of the bet that opened the round
+    pub open_height: u64,
+    /// First height of the flight; bets are taken before it, cash-outs from it
+    pub flight_height: u64,
+    /// VRF message the crash point is proven over
+    pub vrf_message: Vec<u8>,
+    /// blake3 of the VRF output
+    pub commitment: [u8; 32],
+    /// Wallets with a bet in the round, in betting order
+    pub wallets: Vec<[u8; 32]>,
+    /// Height at which the round crashed
+    pub end_height: Option<u64>,
+    /// Crash multiplier in hundredths, published when the round ends
+    pub crash_point: Option<u64>,
+    /// VRF proof, published when the round ends
+    pub vrf_proof: Vec<u8>,
+    /// VRF output, published when the round ends
+    pub vrf_output: Vec<u8>,
+}
+
+impl CrashRound {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// A bet in a crash round, settled when the round ends
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CrashBet {
+    /// Wallet address
+    pub wallet: [u8; 32],
+    /// Bet amount
+    pub amount: u64,
+    /// Multiplier in hundredths the bet was cashed out at, if it was
+    pub cash_out: Option<u64>,
+    /// Nonce of the bet transaction
+    pub nonce: u64,
+    /// Hash of the bet transaction
+    pub tx_hash: [u8; 32],
+}
+
+/// Most winners a lottery draw can have
+pub const MAX_LOTTERY_WINNERS: u16 = 100;
+
+/// Transaction opening a lottery draw
+///
+/// Only the minter key registered at genesis may open draws.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxLotteryOpen {
+    /// Minter wallet (signer)
+    pub wallet: [u8; 32],
+    /// Price of one ticket in minimal units
+    pub ticket_price: u64,
+    /// Height at the end of which winners are drawn; tickets are sold before it
+    pub draw_height: u64,
+    /// Number of winning tickets, sharing the prize pool equally
+    pub winners: u16,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Transaction buying tickets for an open lottery draw
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxLotteryTicket {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Draw to buy tickets for
+    pub draw: u64,
+    /// Amount paid, a whole number of tickets at the draw's price
+    pub amount: u64,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// A lottery draw: tickets are sold until `draw_height`, and winners are drawn
+/// from a VRF output at the end of that block
+///
+/// Tickets are numbered from 0 when the draw is made, in the order of `buyers`,
+/// each buyer's tickets being consecutive.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LotteryDraw {
+    /// Draw id, counting from 1
+    pub id: u64,
+    /// Price of one ticket
+    pub ticket_price: u64,
+    /// Height at the end of which winners are drawn
+    pub draw_height: u64,
+    /// Number of winning tickets (fewer if fewer tickets were sold)
+    pub winners: u16,
+    /// Share of the pot kept by the house, in basis points, fixed when the draw opens
+    pub house_cut_bps: u64,
+    /// Tickets sold so far
+    pub tickets_sold: u64,
+    /// Wallets holding tickets, in order of their first purchase
+    pub buyers: Vec<[u8; 32]>,
+    /// Whether the draw is settled: winners drawn, or every ticket refunded if
+    /// the draw could not be made (no winners and no VRF proof)
+    pub drawn: bool,
+    /// VRF message the winners are drawn from, set at the draw
+    pub vrf_message: Vec<u8>,
+    /// VRF proof, set at the draw
+    pub vrf_proof: Vec<u8>,
+    /// VRF output, set at the draw
+    pub vrf_output: Vec<u8>,
+}
+
+impl LotteryDraw {
+    /// Total paid for the tickets sold
+    pub fn pot(&self) -> Option<u64> {
+        self.tickets_sold.checked_mul(self.ticket_price)
+    }
+
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// A winning ticket of a lottery draw
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LotteryWinner {
+    /// Ticket number
+    pub ticket: u64,
+    /// Wallet holding the ticket
+    pub wallet: [u8; 32],
+    /// Amount credited to the wallet
+    pub prize: u64,
+}
+
+/// Transaction dealing a blackjack hand, staking `amount` on it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxBlackjackDeal {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Bet amount in minimal units
+    pub amount: u64,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Player decision on the blackjack hand being played
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BlackjackMove {
+    /// Take a card
+    Hit,
+    /// Take no more cards
+    Stand,
+    /// Double the bet, take exactly one more card and stand
+    Double,
+    /// Split a pair into two hands, staking the bet again on the new one
+    Split,
+}
+
+/// Transaction playing a move in the wallet's blackjack session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxBlackjackAction {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Move on the hand being played
+    pub action: BlackjackMove,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// A player hand of a blackjack session
+///
+/// Cards are `0..52`: the rank is `card % 13` (0 ace, 1 to 9 two to ten, 10 to 12
+/// jack to king) and the suit `card / 13`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlackjackHand {
+    /// Cards in the order they were dealt
+    pub cards: Vec<u8>,
+    /// Amount staked on the hand, doubled by a double
+    pub bet: u64,
+    /// Whether the hand was doubled
+    pub doubled: bool,
+    /// Whether the hand takes no more cards (stood, doubled, bust or 21)
+    pub stood: bool,
+}
+
+/// A blackjack session: the hands dealt to a wallet, played over as many
+/// transactions and blocks as it takes
+///
+/// The shoe is shuffled from a VRF output proven at the deal. Until the session
+/// ends only the commitment `blake3(vrf_output)` is stored, so the order of the
+/// cards still in the shoe stays hidden; the proof and output are published when
+/// it ends, so every card can be checked.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlackjackSession {
+    /// Wallet playing the session
+    pub wallet: [u8; 32],
+    /// Height of the deal
+    pub height: u64,
+    /// Hash of the deal transaction, which keys the session's [`BetRecord`]
+    pub tx_hash: [u8; 32],
+    /// Nonce of the deal transaction
+    pub nonce: u64,
+    /// VRF message the shoe is shuffled from
+    pub vrf_message: Vec<u8>,
+    /// blake3 of the VRF output
+    pub commitment: [u8; 32],
+    /// Player hands; more than one after a split
+    pub hands: Vec<BlackjackHand>,
+    /// Index of the hand being played
+    pub active: u8,
+    /// Dealer cards: the up card while the session is in play, all of them once it ends
+    pub dealer: Vec<u8>,
+    /// Position in the shoe of the next card to deal
+    pub next_card: u16,
+    /// Whether the session has ended and been paid
+    pub finished: bool,
+    /// VRF proof, published when the session ends
+    pub vrf_proof: Vec<u8>,
+    /// VRF output, published when the session ends
+    pub vrf_output: Vec<u8>,
+}
+
+impl BlackjackSession {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// Smallest mines grid side: a 2x2 board
+pub const MIN_MINES_GRID: u8 = 2;
+
+/// Largest mines grid side: a 5x5 board
+pub const MAX_MINES_GRID: u8 = 5;
+
+/// Transaction opening a mines game on a `grid` x `grid` board
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxMinesOpen {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Bet amount in minimal units
+    pub amount: u64,
+    /// Side of the board, `MIN_MINES_GRID..=MAX_MINES_GRID`
+    pub grid: u8,
+    /// Mines hidden on the board; at least one tile must be a gem
+    pub mines: u8,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Transaction revealing a tile of the wallet's mines game
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxMinesReveal {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Tile to reveal, numbered row by row from 0
+    pub tile: u8,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Transaction cashing out the wallet's mines game at its current multiplier
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxMinesCashOut {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// A mines game: tiles are revealed one transaction at a time until a mine is
+/// hit or the player cashes out
+///
+/// The mines are placed by a VRF output proven when the game opens. Until the
+/// game ends only the commitment `blake3(vrf_output)` is stored; the board, proof
+/// and output are published when it ends, so anyone can check the board never
+/// changed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MinesGame {
+    /// Wallet playing the game
+    pub wallet: [u8; 32],
+    /// Height the game opened at
+    pub height: u64,
+    /// Hash of the opening transaction, which keys the game's [`BetRecord`]
+    pub tx_hash: [u8; 32],
+    /// Nonce of the opening transaction
+    pub nonce: u64,
+    /// Bet amount
+    pub amount: u64,
+    /// Side of the board
+    pub grid: u8,
+    /// Number of mines
+    pub mines: u8,
+    /// VRF message the board is drawn from
+    pub vrf_message: Vec<u8>,
+    /// blake3 of the VRF output
+    pub commitment: [u8; 32],
+    /// Tiles revealed, in order; only the last can be a mine
+    pub revealed: Vec<u8>,
+    /// Whether the game has ended and been paid
+    pub finished: bool,
+    /// Mine tiles in ascending order, published when the game ends
+    pub board: Vec<u8>,
+    /// VRF proof, published when the game ends
+    pub vrf_proof: Vec<u8>,
+    /// VRF output, published when the game ends
+    pub vrf_output: Vec<u8>,
+}
+
+impl MinesGame {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// Most reels a slot machine can have
+pub const MAX_SLOT_REELS: usize = 8;
+
+/// Most stops on a slot machine reel strip
+pub const MAX_SLOT_STOPS: usize = 256;
+
+/// Most rows a slot machine window can show
+pub const MAX_SLOT_ROWS: u8 = 5;
+
+/// Most paylines a slot machine can have
+pub const MAX_SLOT_PAYLINES: usize = 100;
+
+/// A slot machine, defined in the chain params so new machines need no code change
+///
+/// Each reel stops at a VRF-drawn position and shows `rows` consecutive symbols
+/// of its strip from there, wrapping around. A payline picks one row per reel; it
+/// wins when the symbol on its first reel repeats on the following reels at
+/// least as many times as a [`SlotPay`] entry for that symbol asks.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SlotMachine {
+    /// Machine id, chosen by [`TxSlots`]
+    pub id: u32,
+    /// Symbols of each reel strip, in order around the reel
+    pub reels: Vec<Vec<u8>>,
+    /// Rows shown in the window
+    pub rows: u8,
+    /// Row crossed on each reel by each payline, top row 0
+    pub paylines: Vec<Vec<u8>>,
+    /// Winning runs; a line pays its best matching entry
+    pub paytable: Vec<SlotPay>,
+}
+
+/// Paytable entry of a [`SlotMachine`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SlotPay {
+    /// Symbol of the run
+    pub symbol: u8,
+    /// Shortest run from the first reel that pays
+    pub count: u8,
+    /// Payout of a winning line, in hundredths of the stake
+    pub pays: u64,
+}
+
+/// Transaction spinning a slot machine once
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxSlots {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Bet amount in minimal units, covering every payline
+    pub amount: u64,
+    /// Id of the [`SlotMachine`] to play
+    pub machine: u32,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Fewest rows of pegs on a Plinko board
+pub const MIN_PLINKO_ROWS: u8 = 8;
+
+/// Most rows of pegs on a Plinko board
+pub const MAX_PLINKO_ROWS: u8 = 16;
+
+/// Risk profile of a Plinko board: how far its multipliers spread from the middle
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlinkoRisk {
+    Low,
+    Medium,
+    High,
+}
+
+/// Multipliers of the Plinko board with the given rows and risk, defined in the
+/// chain params
+///
+/// A ball bounces left or right on each row of pegs and lands in one of `rows + 1`
+/// slots, numbered by how many times it bounced right.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PlinkoTable {
+    /// Rows of pegs, `MIN_PLINKO_ROWS..=MAX_PLINKO_ROWS`
+    pub rows: u8,
+    /// Risk profile, chosen by [`TxPlinko`]
+    pub risk: PlinkoRisk,
+    /// Payout of each slot from left to right, in hundredths of the stake
+    pub multipliers: Vec<u64>,
+}
+
+/// Transaction dropping one Plinko ball
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxPlinko {
+    /// Wallet address (32 bytes)
+    pub wallet: [u8; 32],
+    /// Bet amount in minimal units
+    pub amount: u64,
+    /// Rows of pegs of the [`PlinkoTable`] to play
+    pub rows: u8,
+    /// Risk profile of the [`PlinkoTable`] to play
+    pub risk: PlinkoRisk,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Maximum length of a withdrawal destination
+pub const MAX_DESTINATION_LENGTH: usize = 128;
+
+/// Transaction crediting a wallet with funds from outside the chain
+///
+/// Only the minter key registered at genesis may sign deposits.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxDeposit {
+    /// Minter wallet (signer)
+    pub wallet: [u8; 32],
+    /// Wallet credited
+    pub recipient: [u8; 32],
+    /// Amount in minimal units
+    pub amount: u64,
+    /// Nonce of the minter wallet
+    pub nonce: u64,
+}
+
+/// Transaction taking funds out of the chain
+///
+/// The amount is burned on-chain; the stored receipt is what the bridge pays out against.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxWithdraw {
+    /// Wallet debited (signer)
+    pub wallet: [u8; 32],
+    /// Amount in minimal units
+    pub amount: u64,
+    /// Off-chain destination, at most [`MAX_DESTINATION_LENGTH`] bytes
+    pub destination: String,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Transaction moving funds between two wallets
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TxTransfer {
+    /// Wallet debited (signer)
+    pub wallet: [u8; 32],
+    /// Wallet credited
+    pub recipient: [u8; 32],
+    /// Amount in minimal units
+    pub amount: u64,
+    /// Nonce to prevent replay attacks
+    pub nonce: u64,
+}
+
+/// Wire tag of a transaction kind
+///
+/// Tags are part of the envelope and are never reused, so a new kind can be added
+/// without changing how existing ones are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum TxKind {
+    Flip = 1,
+    Deposit = 2,
+    Withdraw = 3,
+    Transfer = 4,
+    Dice = 5,
+    Roulette = 6,
+    CrashBet = 7,
+    CrashCashOut = 8,
+    LotteryOpen = 9,
+    LotteryTicket = 10,
+    BlackjackDeal = 11,
+    BlackjackAction = 12,
+    MinesOpen = 13,
+    MinesReveal = 14,
+    MinesCashOut = 15,
+    Slots = 16,
+    Plinko = 17,
+}
+
+impl TxKind {
+    /// Tag written to the envelope
+    pub fn tag(self) -> u16 {
+        self as u16
+    }
+
+    /// Kind of a tag, `None` for tags this version does not know
+    pub fn from_tag(tag: u16) -> Option<Self> {
+        match tag {
+            1 => Some(TxKind::Flip),
+            2 => Some(TxKind::Deposit),
+            3 => Some(TxKind::Withdraw),
+            4 => Some(TxKind::Transfer),
+            5 => Some(TxKind::Dice),
+            6 => Some(TxKind::Roulette),
+            7 => Some(TxKind::CrashBet),
+            8 => Some(TxKind::CrashCashOut),
+            9 => Some(TxKind::LotteryOpen),
+            10 => Some(TxKind::LotteryTicket),
+            11 => Some(TxKind::BlackjackDeal),
+            12 => Some(TxKind::BlackjackAction),
+            13 => Some(TxKind::MinesOpen),
+            14 => Some(TxKind::MinesReveal),
+            15 => Some(TxKind::MinesCashOut),
+            16 => Some(TxKind::Slots),
+            17 => Some(TxKind::Plinko),
+            _ => None,
+        }
+    }
+
+    /// Human-readable name, as used in events and logs
+    pub fn name(self) -> &'static str {
+        match self {
+            TxKind::Flip => "flip",
+            TxKind::Deposit => "deposit",
+            TxKind::Withdraw => "withdraw",
+            TxKind::Transfer => "transfer",
+            TxKind::Dice => "dice",
+            TxKind::Roulette => "roulette",
+            TxKind::CrashBet => "crash_bet",
+            TxKind::CrashCashOut => "crash_cash_out",
+            TxKind::LotteryOpen => "lottery_open",
+            TxKind::LotteryTicket => "lottery_ticket",
+            TxKind::BlackjackDeal => "blackjack_deal",
+            TxKind::BlackjackAction => "blackjack_action",
+            TxKind::MinesOpen => "mines_open",
+            TxKind::MinesReveal => "mines_reveal",
+            TxKind::MinesCashOut => "mines_cash_out",
+            TxKind::Slots => "slots",
+            TxKind::Plinko => "plinko",
+        }
+    }
+}
+
+/// Any transaction a wallet can sign
+///
+/// Each kind's payload is the bincode encoding of its struct. Payload layouts are
+/// frozen once released: changing one means a new kind or a new [`TX_VERSION`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tx {
+    Flip(TxFlip),
+    Deposit(TxDeposit),
+    Withdraw(TxWithdraw),
+    Transfer(TxTransfer),
+    Dice(TxDice),
+    Roulette(TxRoulette),
+    CrashBet(TxCrashBet),
+    CrashCashOut(TxCrashCashOut),
+    LotteryOpen(TxLotteryOpen),
+    LotteryTicket(TxLotteryTicket),
+    BlackjackDeal(TxBlackjackDeal),
+    BlackjackAction(TxBlackjackAction),
+    MinesOpen(TxMinesOpen),
+    MinesReveal(TxMinesReveal),
+    MinesCashOut(TxMinesCashOut),
+    Slots(TxSlots),
+    Plinko(TxPlinko),
+}
+
+impl Tx {
+    /// Kind tag of the transaction
+    pub fn kind(&self) -> TxKind {
+        match self {
+            Tx::Flip(_) => TxKind::Flip,
+            Tx::Deposit(_) => TxKind::Deposit,
+            Tx::Withdraw(_) => TxKind::Withdraw,
+            Tx::Transfer(_) => TxKind::Transfer,
+            Tx::Dice(_) => TxKind::Dice,
+            Tx::Roulette(_) => TxKind::Roulette,
+            Tx::CrashBet(_) => TxKind::CrashBet,
+            Tx::CrashCashOut(_) => TxKind::CrashCashOut,
+            Tx::LotteryOpen(_) => TxKind::LotteryOpen,
+            Tx::LotteryTicket(_) => TxKind::LotteryTicket,
+            Tx::BlackjackDeal(_) => TxKind::BlackjackDeal,
+            Tx::BlackjackAction(_) => TxKind::BlackjackAction,
+            Tx::MinesOpen(_) => TxKind::MinesOpen,
+            Tx::MinesReveal(_) => TxKind::MinesReveal,
+            Tx::MinesCashOut(_) => TxKind::MinesCashOut,
+            Tx::Slots(_) => TxKind::Slots,
+            Tx::Plinko(_) => TxKind::Plinko,
+        }
+    }
+
+    /// Wallet of the signer, whose nonce the transaction uses
+    pub fn wallet(&self) -> &[u8; 32] {
+        match self {
+            Tx::Flip(tx) => &tx.wallet,
+            Tx::Deposit(tx) => &tx.wallet,
+            Tx::Withdraw(tx) => &tx.wallet,
+            Tx::Transfer(tx) => &tx.wallet,
+            Tx::Dice(tx) => &tx.wallet,
+            Tx::Roulette(tx) => &tx.wallet,
+            Tx::CrashBet(tx) => &tx.wallet,
+            Tx::CrashCashOut(tx) => &tx.wallet,
+            Tx::LotteryOpen(tx) => &tx.wallet,
+            Tx::LotteryTicket(tx) => &tx.wallet,
+            Tx::BlackjackDeal(tx) => &tx.wallet,
+            Tx::BlackjackAction(tx) => &tx.wallet,
+            Tx::MinesOpen(tx) => &tx.wallet,
+            Tx::MinesReveal(tx) => &tx.wallet,
+            Tx::MinesCashOut(tx) => &tx.wallet,
+            Tx::Slots(tx) => &tx.wallet,
+            Tx::Plinko(tx) => &tx.wallet,
+        }
+    }
+
+    /// Nonce of the signer wallet
+    pub fn nonce(&self) -> u64 {
+        match self {
+            Tx::Flip(tx) => tx.nonce,
+            Tx::Deposit(tx) => tx.nonce,
+            Tx::Withdraw(tx) => tx.nonce,
+            Tx::Transfer(tx) => tx.nonce,
+            Tx::Dice(tx) => tx.nonce,
+            Tx::Roulette(tx) => tx.nonce,
+            Tx::CrashBet(tx) => tx.nonce,
+            Tx::CrashCashOut(tx) => tx.nonce,
+            Tx::LotteryOpen(tx) => tx.nonce,
+            Tx::LotteryTicket(tx) => tx.nonce,
+            Tx::BlackjackDeal(tx) => tx.nonce,
+            Tx::BlackjackAction(tx) => tx.nonce,
+            Tx::MinesOpen(tx) => tx.nonce,
+            Tx::MinesReveal(tx) => tx.nonce,
+            Tx::MinesCashOut(tx) => tx.nonce,
+            Tx::Slots(tx) => tx.nonce,
+            Tx::Plinko(tx) => tx.nonce,
+        }
+    }
+
+    /// Amount moved by the transaction; 0 for opening a lottery draw and for moves
+    /// in a game already paid for, whose extra stake (a blackjack double or split)
+    /// depends on the game's state
+    pub fn amount(&self) -> u64 {
+        match self {
+            Tx::Flip(tx) => tx.amount,
+            Tx::Deposit(tx) => tx.amount,
+            Tx::Withdraw(tx) => tx.amount,
+            Tx::Transfer(tx) => tx.amount,
+            Tx::Dice(tx) => tx.amount,
+            Tx::Roulette(tx) => tx.amount,
+            Tx::CrashBet(tx) => tx.amount,
+            Tx::CrashCashOut(_) => 0,
+            Tx::LotteryOpen(_) => 0,
+            Tx::LotteryTicket(tx) => tx.amount,
+            Tx::BlackjackDeal(tx) => tx.amount,
+            Tx::BlackjackAction(_) => 0,
+            Tx::MinesOpen(tx) => tx.amount,
+            Tx::MinesReveal(_) => 0,
+            Tx::MinesCashOut(_) => 0,
+            Tx::Slots(tx) => tx.amount,
+            Tx::Plinko(tx) => tx.amount,
+        }
+    }
+
+    /// Encode the kind-specific payload
+    pub fn encode_payload(&self) -> Result<Vec<u8>, bincode::Error> {
+        match self {
+            Tx::Flip(tx) => bincode::serialize(tx),
+            Tx::Deposit(tx) => bincode::serialize(tx),
+            Tx::Withdraw(tx) => bincode::serialize(tx),
+            Tx::Transfer(tx) => bincode::serialize(tx),
+            Tx::Dice(tx) => bincode::serialize(tx),
+            Tx::Roulette(tx) => bincode::serialize(tx),
+            Tx::CrashBet(tx) => bincode::serialize(tx),
+            Tx::CrashCashOut(tx) => bincode::serialize(tx),
+            Tx::LotteryOpen(tx) => bincode::serialize(tx),
+            Tx::LotteryTicket(tx) => bincode::serialize(tx),
+            Tx::BlackjackDeal(tx) => bincode::serialize(tx),
+            Tx::BlackjackAction(tx) => bincode::serialize(tx),
+            Tx::MinesOpen(tx) => bincode::serialize(tx),
+            Tx::MinesReveal(tx) => bincode::serialize(tx),
+            Tx::MinesCashOut(tx) => bincode::serialize(tx),
+            Tx::Slots(tx) => bincode::serialize(tx),
+            Tx::Plinko(tx) => bincode::serialize(tx),
+        }
+    }
+
+    /// Decode the payload of a kind; trailing bytes are rejected so every
+    /// transaction has exactly one encoding
+    pub fn decode_payload(kind: TxKind, payload: &[u8]) -> Result<Self, bincode::Error> {
+        let options = bincode::DefaultOptions::new().with_fixint_encoding();
+        Ok(match kind {
+            TxKind::Flip => Tx::Flip(options.deserialize(payload)?),
+            TxKind::Deposit => Tx::Deposit(options.deserialize(payload)?),
+            TxKind::Withdraw => Tx::Withdraw(options.deserialize(payload)?),
+            TxKind::Transfer => Tx::Transfer(options.deserialize(payload)?),
+            TxKind::Dice => Tx::Dice(options.deserialize(payload)?),
+            TxKind::Roulette => Tx::Roulette(options.deserialize(payload)?),
+            TxKind::CrashBet => Tx::CrashBet(options.deserialize(payload)?),
+            TxKind::CrashCashOut => Tx::CrashCashOut(options.deserialize(payload)?),
+            TxKind::LotteryOpen => Tx::LotteryOpen(options.deserialize(payload)?),
+            TxKind::LotteryTicket => Tx::LotteryTicket(options.deserialize(payload)?),
+            TxKind::BlackjackDeal => Tx::BlackjackDeal(options.deserialize(payload)?),
+            TxKind::BlackjackAction => Tx::BlackjackAction(options.deserialize(payload)?),
+            TxKind::MinesOpen => Tx::MinesOpen(options.deserialize(payload)?),
+            TxKind::MinesReveal => Tx::MinesReveal(options.deserialize(payload)?),
+            TxKind::MinesCashOut => Tx::MinesCashOut(options.deserialize(payload)?),
+            TxKind::Slots => Tx::Slots(options.deserialize(payload)?),
+            TxKind::Plinko => Tx::Plinko(options.deserialize(payload)?),
+        })
+    }
+}
+
+/// Signed transaction envelope, the only format accepted by the chain
+///
+/// Encoding: version (u8) || kind tag (u16 LE) || public key (32) || signature (64) || payload.
+/// The version is checked first and selects how the rest is read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedTx {
+    /// The transaction
+    pub tx: Tx,
+    /// Signer ed25519 public key; the transaction's wallet must be derived from it
+    pub public_key: [u8; 32],
+    /// ed25519 signature over [`SignedTx::sign_bytes`]
+    pub signature: [u8; SIGNATURE_LENGTH],
+}
+
+impl SignedTx {
+    /// Bytes to sign: TX_SIGN_DOMAIN || bincode(chain_id) || version || kind tag || payload
+    ///
+    /// Binding the chain id keeps a signature from being replayed on another chain,
+    /// and binding version and kind keeps a payload from being reinterpreted.
+    pub fn sign_bytes(tx: &Tx, chain_id: &str) -> Result<Vec<u8>, bincode::Error> {
+        let mut bytes = TX_SIGN_DOMAIN.to_vec();
+        bytes.extend(bincode::serialize(chain_id)?);
+        bytes.push(TX_VERSION);
+        bytes.extend(tx.kind().tag().to_le_bytes());
+        bytes.extend(tx.encode_payload()?);
+        Ok(bytes)
+    }
+
+    /// Sign a transaction; the wallet of `tx` should be derived from the key
+    pub fn sign(tx: Tx, chain_id: &str, signing_key: &SigningKey) -> Result<Self, bincode::Error> {
+        let signature = signing_key.sign(&Self::sign_bytes(&tx, chain_id)?);
+        Ok(Self {
+            tx,
+            public_key: signing_key.verifying_key().to_bytes(),
+            signature: signature.to_bytes(),
+        })
+    }
+
+    /// Check that the signer owns the wallet and the signature is valid for this chain
+    pub fn verify(&self, chain_id: &str) -> Result<(), TxError> {
+        if &wallet_from_public_key(&self.public_key) != self.tx.wallet() {
+            return Err(TxError::WalletMismatch);
+        }
+        let verifying_key = VerifyingKey::from_bytes(&self.public_key)
+            .map_err(|_| TxError::InvalidPublicKey)?;
+        let signature = Signature::from_bytes(&self.signature);
+        verifying_key
+            .verify_strict(&Self::sign_bytes(&self.tx, chain_id)?, &signature)
+            .map_err(|_| TxError::InvalidSignature)
+    }
+
+    /// Encode with the current envelope version
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        let payload = self.tx.encode_payload()?;
+        let mut bytes = Vec::with_capacity(TX_HEADER_LENGTH + payload.len());
+        bytes.push(TX_VERSION);
+        bytes.extend(self.tx.kind().tag().to_le_bytes());
+        bytes.extend(self.public_key);
+        bytes.extend(self.signature);
+        bytes.extend(payload);
+        Ok(bytes)
+    }
+
+    /// Decode an envelope, dispatching on its version
+    pub fn from_bytes(data: &[u8]) -> Result<Self, TxError> {
+        match data.first() {
+            None => Err(TxError::Truncated),
+            Some(&TX_VERSION) => Self::from_bytes_v1(data),
+            Some(&version) => Err(TxError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn from_bytes_v1(data: &[u8]) -> Result<Self, TxError> {
+        if data.len() < TX_HEADER_LENGTH {
+            return Err(TxError::Truncated);
+        }
+        let (header, payload) = data.split_at(TX_HEADER_LENGTH);
+        let tag = u16::from_le_bytes([header[1], header[2]]);
+        let kind = TxKind::from_tag(tag).ok_or(TxError::UnknownKind(tag))?;
+
+        Ok(Self {
+            tx: Tx::decode_payload(kind, payload)?,
+            public_key: header[3..35].try_into().expect("header length is checked"),
+            signature: header[35..].try_into().expect("header length is checked"),
+        })
+    }
+}
+
+/// A game the chain runs, as listed by the `/games` query
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GameInfo {
+    /// Game id
+    pub id: String,
+    /// Tags of the transaction kinds the game settles, see [`TxKind`]
+    pub tx_kinds: Vec<u16>,
+}
+
+/// A game played over several transactions, holding the player's stake in escrow
+///
+/// Its game keeps the cards or the board; the session keeps what every such game
+/// shares: whose it is, what it holds, which actions its state allows and when it
+/// times out. A session nobody acts on is settled by its game at `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GameSession {
+    /// Session id, counting up from 1 across all games
+    pub id: u64,
+    /// Id of the game playing the session
+    pub game: String,
+    /// Wallet playing the session
+    pub wallet: [u8; 32],
+    /// Stakes debited from the wallet and held until the session settles
+    pub stake: u64,
+    /// State of the game, which decides the actions it takes next
+    pub state: String,
+    /// Height the session opened at
+    pub opened_height: u64,
+    /// Height of the last action
+    pub last_action_height: u64,
+    /// Height at the end of which the session is settled if nobody acts on it
+    pub expires_at: u64,
+    /// Whether the session has settled and released its escrow
+    pub finished: bool,
+    /// Amount credited to the wallet when the session settled
+    pub payout: u64,
+}
+
+impl GameSession {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// Game played by a [`BetRecord`], with the player's pick and the outcome
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum BetKind {
+    Flip {
+        /// Side the player bet on
+        choice: CoinSide,
+        /// Coin flip result (true = heads, false = tails)
+        result: bool,
+    },
+    Dice {
+        /// Winning rolls are below the target
+        target: u16,
+        /// Roll in `0..DICE_SIDES`
+        roll: u16,
+    },
+    /// Settled against the [`RouletteSpin`] of the table at the record's height
+    Roulette {
+        /// Table the bet was placed at
+        table: u32,
+        /// Numbers the bet covered
+        bet: RouletteBet,
+        /// Winning number of the spin
+        number: u8,
+    },
+    /// Settled against the crash point of a [`CrashRound`]
+    Crash {
+        /// Round the bet was placed in
+        round: u64,
+        /// Multiplier in hundredths the bet was cashed out at, if it was
+        cash_out: Option<u64>,
+        /// Crash multiplier in hundredths
+        crash_point: u64,
+    },
+    /// Settled when the [`BlackjackSession`] dealt by the record's transaction ended
+    Blackjack {
+        /// Player hands with their final cards and stakes
+        hands: Vec<BlackjackHand>,
+        /// Dealer cards
+        dealer: Vec<u8>,
+    },
+    /// Settled when the [`MinesGame`] opened by the record's transaction ended
+    Mines {
+        /// Side of the board
+        grid: u8,
+        /// Tiles revealed, in order; the last is a mine if the game was lost
+        revealed: Vec<u8>,
+        /// Mine tiles in ascending order
+        board: Vec<u8>,
+    },
+    Slots {
+        /// Machine played
+        machine: u32,
+        /// Stop position of each reel
+        stops: Vec<u16>,
+    },
+    Plinko {
+        /// Rows of pegs
+        rows: u8,
+        /// Risk profile
+        risk: PlinkoRisk,
+        /// Bounce on each row from the top (true = right), so the ball landed in
+        /// the slot numbered by the count of right bounces
+        path: Vec<bool>,
+    },
+}
+
 /// Record of a completed bet stored in state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BetRecord {
@@ -48,16 +1183,21 @@ pub struct BetRecord {
     pub wallet: [u8; 32],
     /// Bet amount
     pub amount: u64,
+    /// Game, pick and outcome
+    pub kind: BetKind,
     /// Nonce used
     pub nonce: u64,
-    /// VRF message that was signed
+    /// VRF message that was signed (empty when the bet settled against a shared
+    /// draw, which carries its own proof)
     pub vrf_message: Vec<u8>,
     /// VRF proof
     pub vrf_proof: Vec<u8>,
     /// VRF output
     pub vrf_output: Vec<u8>,
-    /// Coin flip result (true = heads, false = tails)
-    pub result: bool,
+    /// Whether the player won
+    pub won: bool,
+    /// Amount credited to the player (0 on a loss)
+    pub payout: u64,
     /// Block height where bet was processed
     pub height: u64,
     /// Transaction hash
@@ -76,6 +1216,99 @@ impl BetRecord {
     }
 }
 
+/// Account operation recorded by a [`Receipt`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ReceiptKind {
+    Deposit { recipient: [u8; 32], amount: u64 },
+    Withdraw { wallet: [u8; 32], amount: u64, destination: String },
+    Transfer { wallet: [u8; 32], recipient: [u8; 32], amount: u64 },
+}
+
+/// Record of an executed deposit, withdrawal or transfer stored in state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Receipt {
+    /// What moved where
+    pub kind: ReceiptKind,
+    /// Block height where the transaction was executed
+    pub height: u64,
+    /// Transaction hash (BLAKE3 of the signed transaction bytes)
+    pub tx_hash: [u8; 32],
+}
+
+impl Receipt {
+    /// Serialize to bytes using bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserialize from bytes using bincode
+    pub fn from_bytes(data: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// Basis points in a whole (100%)
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Chain parameters, set at genesis
+///
+/// Missing fields in genesis take their default, which keeps the original
+/// rules: a winning flip pays twice the stake and the house keeps no edge.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainParams {
+    /// Share of every payout kept by the house, in basis points, in games paying
+    /// fair odds (flip, dice, crash, mines); roulette's edge is the zero pocket
+    /// and blackjack's is in its rules. Slot machines and Plinko tables must keep
+    /// at least this share of stakes, and lottery draws keep this share of their pot
+    pub house_edge_bps: u64,
+    /// Smallest accepted bet
+    pub min_bet: u64,
+    /// Largest accepted bet
+    pub max_bet: u64,
+    /// A winning coin flip pays back the stake times this, before the house edge
+    pub payout_multiplier: u64,
+    /// Slot machines on offer; none by default
+    pub slot_machines: Vec<SlotMachine>,
+    /// Highest theoretical return to player a slot machine may have, in basis
+    /// points; at most 10_000, and machines are held to the house edge below it
+    pub max_slot_rtp_bps: u64,
+    /// Plinko boards on offer, one per rows and risk; none by default
+    pub plinko_tables: Vec<PlinkoTable>,
+    /// Blocks a game session waits for an action before its game settles it
+    pub session_timeout_blocks: u64,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self {
+            house_edge_bps: 0,
+            min_bet: 1,
+            max_bet: u64::MAX,
+            payout_multiplier: 2,
+            slot_machines: Vec::new(),
+            max_slot_rtp_bps: BPS_DENOMINATOR,
+            plinko_tables: Vec::new(),
+            session_timeout_blocks: 100,
+        }
+    }
+}
+
+impl ChainParams {
+    /// Serialize to bytes as JSON
+    ///
+    /// Params gain fields as games are added. JSON names them, so params stored
+    /// before a field existed still decode, with the field at its default.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(self)
+    }
+
+    /// Deserialize from JSON
+    pub fn from_bytes(data: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(data)
+    }
+}
+
 /// Application state hash computation
 pub fn compute_app_hash(height: u64, block_random: &[u8; 32]) -> [u8; 32] {
     let mut hasher = blake3::Hasher::new();
@@ -88,9 +1321,18 @@ pub fn compute_app_hash(height: u64, block_random: &[u8; 32]) -> [u8; 32] {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chain_params_serialization() {
+        let params = ChainParams { house_edge_bps: 100, max_bet: 500, ..Default::default() };
+        assert_eq!(ChainParams::from_bytes(&params.to_bytes().unwrap()).unwrap(), params);
+
+        // Params stored before a field existed take its default
+        assert_eq!(ChainParams::from_bytes(br#"{"max_bet": 500}"#).unwrap().payout_multiplier, 2);
+    }
+
     #[test]
     fn test_tx_flip_serialization() {
-        let tx = TxFlip::new([1u8; 32], 1000, 42);
+        let tx = TxFlip::new([1u8; 32], 1000, CoinSide::Heads, 42);
         let bytes = tx.to_bytes().unwrap();
         let recovered = TxFlip::from_bytes(&bytes).unwrap();
         assert_eq!(tx, recovered);
@@ -98,15 +1340,104 @@ mod tests {
 
     #[test]
     fn test_tx_flip_hash() {
-        let tx = TxFlip::new([1u8; 32], 1000, 42);
+        let tx = TxFlip::new([1u8; 32], 1000, CoinSide::Heads, 42);
         let hash1 = tx.hash().unwrap();
         let hash2 = tx.hash().unwrap();
         assert_eq!(hash1, hash2);
 
         // Different nonce should produce different hash
-        let tx2 = TxFlip::new([1u8; 32], 1000, 43);
+        let tx2 = TxFlip::new([1u8; 32], 1000, CoinSide::Heads, 43);
         let hash3 = tx2.hash().unwrap();
         assert_ne!(hash1, hash3);
+
+        // So should the other side
+        let tx3 = TxFlip::new([1u8; 32], 1000, CoinSide::Tails, 42);
+        assert_ne!(hash1, tx3.hash().unwrap());
+    }
+
+    fn signed_flip(seed: u8, chain_id: &str) -> (SigningKey, SignedTx) {
+        let signing_key = SigningKey::from_bytes(&[seed; 32]);
+        let wallet = wallet_from_public_key(&signing_key.verifying_key().to_bytes());
+        let signed = SignedTx::sign(Tx::Flip(TxFlip::new(wallet, 1000, CoinSide::Tails, 1)), chain_id, &signing_key).unwrap();
+        (signing_key, signed)
+    }
+
+    #[test]
+    fn test_signed_tx_roundtrip() {
+        let (_, signed) = signed_flip(7, "test_chain");
+        signed.verify("test_chain").unwrap();
+
+        let bytes = signed.to_bytes().unwrap();
+        let recovered = SignedTx::from_bytes(&bytes).unwrap();
+        assert_eq!(signed, recovered);
+        recovered.verify("test_chain").unwrap();
+    }
+
+    #[test]
+    fn test_signed_tx_rejects_tampering() {
+        let (_, signed) = signed_flip(7, "test_chain");
+
+        // Signature is bound to the chain id
+        assert!(matches!(signed.verify("other_chain"), Err(TxError::InvalidSignature)));
+
+        // Any change to the body invalidates the signature
+        let mut tampered = signed.clone();
+        if let Tx::Flip(tx) = &mut tampered.tx {
+            tx.amount += 1;
+        }
+        assert!(matches!(tampered.verify("test_chain"), Err(TxError::InvalidSignature)));
+
+        let mut tampered = signed.clone();
+        tampered.signature[0] ^= 1;
+        assert!(matches!(tampered.verify("test_chain"), Err(TxError::InvalidSignature)));
+
+        // Someone else's key cannot spend from this wallet
+        let (other_key, _) = signed_flip(8, "test_chain");
+        let stolen = SignedTx::sign(signed.tx.clone(), "test_chain", &other_key).unwrap();
+        assert!(matches!(stolen.verify("test_chain"), Err(TxError::WalletMismatch)));
+
+        // Same fields under another transaction kind sign different bytes
+        let Tx::Flip(flip) = &signed.tx else { unreachable!() };
+        let transfer = Tx::Transfer(TxTransfer {
+            wallet: flip.wallet,
+            recipient: flip.wallet,
+            amount: flip.amount,
+            nonce: flip.nonce,
+        });
+        assert_ne!(
+            SignedTx::sign_bytes(&transfer, "test_chain").unwrap(),
+            SignedTx::sign_bytes(&signed.tx, "test_chain").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_envelope_version_and_kind_dispatch() {
+        let (_, signed) = signed_flip(7, "test_chain");
+        let bytes = signed.to_bytes().unwrap();
+        assert_eq!(bytes[0], TX_VERSION);
+        assert_eq!(u16::from_le_bytes([bytes[1], bytes[2]]), TxKind::Flip.tag());
+
+        let mut future = bytes.clone();
+        future[0] = TX_VERSION + 1;
+        assert!(matches!(SignedTx::from_bytes(&future), Err(TxError::UnsupportedVersion(2))));
+
+        let mut unknown = bytes.clone();
+        unknown[1..3].copy_from_slice(&999u16.to_le_bytes());
+        assert!(matches!(SignedTx::from_bytes(&unknown), Err(TxError::UnknownKind(999))));
+
+        assert!(matches!(SignedTx::from_bytes(&[]), Err(TxError::Truncated)));
+        assert!(matches!(SignedTx::from_bytes(&bytes[..TX_HEADER_LENGTH - 1]), Err(TxError::Truncated)));
+
+        // Exactly one encoding per transaction
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(SignedTx::from_bytes(&trailing), Err(TxError::Encoding(_))));
+
+        // Every kind round-trips through its tag
+        for tag in 1..=17 {
+            assert_eq!(TxKind::from_tag(tag).unwrap().tag(), tag);
+        }
+        assert_eq!(TxKind::from_tag(0), None);
     }
 
     #[test]
@@ -114,11 +1445,13 @@ mod tests {
         let record = BetRecord {
             wallet: [2u8; 32],
             amount: 500,
+            kind: BetKind::Flip { choice: CoinSide::Heads, result: true },
             nonce: 123,
-            msg: vec![1, 2, 3],
-            proof: vec![4, 5, 6],
-            output: vec![7, 8, 9],
-            result: true,
+            vrf_message: vec![1, 2, 3],
+            vrf_proof: vec![4, 5, 6],
+            vrf_output: vec![7, 8, 9],
+            won: true,
+            payout: 1000,
             height: 100,
             tx_hash: [3u8; 32],
         };
@@ -127,7 +1460,13 @@ mod tests {
         let recovered = BetRecord::from_bytes(&bytes).unwrap();
         assert_eq!(record.wallet, recovered.wallet);
         assert_eq!(record.amount, recovered.amount);
-        assert_eq!(record.result, recovered.result);
+        assert_eq!(record.kind, recovered.kind);
+        assert_eq!(record.won, recovered.won);
+        assert_eq!(record.payout, recovered.payout);
+
+        let record = BetRecord { kind: BetKind::Dice { target: 4_950, roll: 17 }, ..record };
+        let recovered = BetRecord::from_bytes(&record.to_bytes().unwrap()).unwrap();
+        assert_eq!(recovered.kind, BetKind::Dice { target: 4_950, roll: 17 });
     }
 
     #[test]